@@ -0,0 +1,103 @@
+//! Block size search: the throughput of increasing block sizes on the
+//! target, to answer which block size an application should use.
+
+use crate::{
+    offsets::{Mode, Offsets},
+    progress::Progress,
+    read_file,
+    worker::StartGate,
+    write_file, Job, PROBE_BYTES,
+};
+use anyhow::{Context, Result};
+use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+
+const MIN_SIZE: u64 = 4096;
+const MAX_SIZE: u64 = 16 << 20;
+
+/// Transfers up to [`PROBE_BYTES`] of the job's range with each block size
+/// from 4 KiB to 16 MiB, doubling, and prints the smallest one within
+/// `within` of the peak throughput.
+pub async fn run(job: &Job, write: bool, mode: Mode, within: f64) -> Result<()> {
+    let region = job.block_size * job.count;
+    let mut sizes = Vec::new();
+    let mut size = MIN_SIZE;
+    while size <= MAX_SIZE.min(region) {
+        sizes.push(size);
+        size *= 2;
+    }
+    if sizes.is_empty() {
+        sizes.push(region);
+    }
+
+    println!("{:>10} {:>14} {:>8}", "block size", "throughput", "of peak");
+    let mut rates = Vec::with_capacity(sizes.len());
+    for &size in &sizes {
+        let mode = match mode {
+            Mode::Random { seed, .. } => Mode::Random { seed, align: size },
+            Mode::RandomMap { seed, .. } => Mode::RandomMap { seed, align: size },
+            // `--offsets` sets the block size and conflicts with `--auto-bs`.
+            Mode::Sequential | Mode::List { .. } => Mode::Sequential,
+        };
+        let blocks = (PROBE_BYTES.min(region) / size).max(1);
+        let offsets = Offsets::new(size, mode, 0, 0..region);
+        let mut progress = Progress::new(blocks * size);
+        let gate = &mut StartGate::none();
+        let stats = if write {
+            write_file(
+                &job.file,
+                &offsets,
+                0..blocks,
+                job.strategy,
+                job.compute_per_block,
+                job.faults,
+                &mut progress,
+                gate,
+            )
+            .await
+        } else {
+            read_file(
+                &job.file,
+                job.read_flags(),
+                &offsets,
+                0..blocks,
+                job.strategy,
+                job.compute_per_block,
+                job.faults,
+                None,
+                &mut progress,
+                gate,
+            )
+            .await
+        }
+        .with_context(|| {
+            format!(
+                "probe with {} blocks failed",
+                SizeFormatter::new(size, BINARY)
+            )
+        })?;
+        rates.push(stats.bytes_per_sec());
+    }
+
+    let peak = rates.iter().copied().fold(0.0, f64::max);
+    for (&size, &rate) in sizes.iter().zip(&rates) {
+        println!(
+            "{:>10} {:>14} {:>7.1}%",
+            SizeFormatter::new(size, BINARY).to_string(),
+            format!("{}/s", ISizeFormatter::new(rate, BINARY)),
+            rate / peak * 100.0,
+        );
+    }
+    let (best, rate) = sizes
+        .iter()
+        .zip(&rates)
+        .find(|(_, &rate)| rate >= peak * (1.0 - within))
+        .expect("the peak is within any margin of itself");
+    println!(
+        "smallest block size within {:.0}% of the peak of {}/s: {} at {}/s",
+        within * 100.0,
+        ISizeFormatter::new(peak, BINARY),
+        SizeFormatter::new(*best, BINARY),
+        ISizeFormatter::new(*rate, BINARY),
+    );
+    Ok(())
+}
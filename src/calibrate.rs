@@ -0,0 +1,58 @@
+//! Measures the cost of raio's own instrumentation, which matters once
+//! operations take only a few microseconds.
+
+use crate::{latency::Latency, progress::Progress};
+use serde::{Deserialize, Serialize};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+const ROUNDS: u32 = 100_000;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Overhead {
+    /// Cost of one clock read in nanoseconds. Every latency includes about
+    /// one clock read.
+    pub clock_ns: f64,
+    /// Cost of recording one completed operation in nanoseconds. It is not
+    /// part of any latency but of the run's elapsed time.
+    pub record_ns: f64,
+    /// Whether `clock_ns` was subtracted from every latency.
+    pub subtracted: bool,
+}
+
+impl Overhead {
+    /// Measures both costs, recording into a histogram like `latency`.
+    pub fn measure(latency: &Latency, subtract: bool) -> Self {
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            black_box(Instant::now());
+        }
+        let clock = start.elapsed() / ROUNDS;
+
+        let mut progress = Progress::new(u64::MAX).with_latency(latency.empty_like());
+        let start = Instant::now();
+        for i in 0..u64::from(ROUNDS) {
+            let latency = Duration::from_nanos(black_box(i * 1000 % 10_000_000));
+            progress.add(i, 1, latency);
+        }
+        let record = start.elapsed() / ROUNDS;
+        debug!("clock read takes {clock:?}, recording an operation {record:?}");
+
+        Self {
+            clock_ns: clock.as_nanos() as f64,
+            record_ns: record.as_nanos() as f64,
+            subtracted: subtract,
+        }
+    }
+
+    pub fn clock(&self) -> Duration {
+        Duration::from_nanos(self.clock_ns as u64)
+    }
+
+    pub fn record(&self) -> Duration {
+        Duration::from_nanos(self.record_ns as u64)
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// cgroup v2 I/O limits that apply to the current process for one device.
+/// Limits are the tightest ones found on the path from the process cgroup up
+/// to the root.
+#[derive(Debug, Clone, Default)]
+pub struct IoLimits {
+    pub cgroup: String,
+    pub rbps: Option<u64>,
+    pub wbps: Option<u64>,
+    pub riops: Option<u64>,
+    pub wiops: Option<u64>,
+    pub weight: Option<u64>,
+}
+
+impl IoLimits {
+    /// Returns `None` if the process is not in a cgroup v2 hierarchy.
+    pub fn for_device(major: u32, minor: u32) -> Result<Option<Self>> {
+        let Some(mount) = cgroup2_mount()? else {
+            return Ok(None);
+        };
+        let Some(cgroup) = current_cgroup()? else {
+            return Ok(None);
+        };
+
+        let device = format!("{major}:{minor}");
+        let mut limits = Self {
+            cgroup: cgroup.clone(),
+            ..Default::default()
+        };
+
+        let mut dir = mount.join(cgroup.trim_start_matches('/'));
+        loop {
+            if let Ok(max) = fs::read_to_string(dir.join("io.max")) {
+                limits.apply_max(&max, &device);
+            }
+            if limits.weight.is_none() {
+                if let Ok(weight) = fs::read_to_string(dir.join("io.weight")) {
+                    limits.weight = parse_weight(&weight, &device);
+                }
+            }
+
+            if dir == mount || !dir.pop() {
+                break;
+            }
+        }
+
+        Ok(Some(limits))
+    }
+
+    fn apply_max(&mut self, max: &str, device: &str) {
+        let Some(line) = max
+            .lines()
+            .find(|line| line.split_whitespace().next() == Some(device))
+        else {
+            return;
+        };
+        for (key, value) in line
+            .split_whitespace()
+            .skip(1)
+            .filter_map(|kv| kv.split_once('='))
+        {
+            let Ok(value) = value.parse::<u64>() else {
+                continue; // "max"
+            };
+            let limit = match key {
+                "rbps" => &mut self.rbps,
+                "wbps" => &mut self.wbps,
+                "riops" => &mut self.riops,
+                "wiops" => &mut self.wiops,
+                _ => continue,
+            };
+            *limit = Some(limit.map_or(value, |limit| limit.min(value)));
+        }
+    }
+
+    /// Returns a warning if the measured throughput is close enough to a
+    /// limit that the cgroup rather than the device is the likely bottleneck.
+    pub fn throttle_warning(&self, write: bool, bytes_per_sec: f64, iops: f64) -> Option<String> {
+        let (bps_limit, iops_limit, bps_key, iops_key) = if write {
+            (self.wbps, self.wiops, "wbps", "wiops")
+        } else {
+            (self.rbps, self.riops, "rbps", "riops")
+        };
+
+        if let Some(limit) = bps_limit {
+            if bytes_per_sec >= limit as f64 * 0.9 {
+                return Some(format!(
+                    "throughput is within 10% of the cgroup limit {bps_key}={limit}, \
+                     the benchmark is likely throttled by cgroup {}",
+                    self.cgroup,
+                ));
+            }
+        }
+        if let Some(limit) = iops_limit {
+            if iops >= limit as f64 * 0.9 {
+                return Some(format!(
+                    "IOPS are within 10% of the cgroup limit {iops_key}={limit}, \
+                     the benchmark is likely throttled by cgroup {}",
+                    self.cgroup,
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for IoLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn opt(value: Option<u64>) -> String {
+            match value {
+                Some(value) => value.to_string(),
+                None => "max".to_string(),
+            }
+        }
+
+        write!(
+            f,
+            "cgroup {}: rbps={} wbps={} riops={} wiops={} weight={}",
+            self.cgroup,
+            opt(self.rbps),
+            opt(self.wbps),
+            opt(self.riops),
+            opt(self.wiops),
+            match self.weight {
+                Some(weight) => weight.to_string(),
+                None => "-".to_string(),
+            },
+        )
+    }
+}
+
+/// io.weight has a `default N` line and optional per-device `MAJ:MIN N` lines.
+fn parse_weight(weight: &str, device: &str) -> Option<u64> {
+    let mut default = None;
+    for line in weight.lines() {
+        let Some(entry) = line.split_once(' ') else {
+            continue;
+        };
+        match entry {
+            ("default", value) => default = value.trim().parse().ok(),
+            (dev, value) if dev == device => return value.trim().parse().ok(),
+            _ => (),
+        }
+    }
+    default
+}
+
+fn cgroup2_mount() -> Result<Option<PathBuf>> {
+    let mountinfo =
+        fs::read_to_string("/proc/self/mountinfo").context("failed to read mountinfo")?;
+    for line in mountinfo.lines() {
+        let Some((mount, fs_type)) = line.split_once(" - ") else {
+            continue;
+        };
+        if fs_type.split_whitespace().next() == Some("cgroup2") {
+            if let Some(mount_point) = mount.split_whitespace().nth(4) {
+                return Ok(Some(Path::new(mount_point).to_path_buf()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn current_cgroup() -> Result<Option<String>> {
+    let cgroup = fs::read_to_string("/proc/self/cgroup").context("failed to read cgroup")?;
+    Ok(cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(str::to_string))
+}
@@ -0,0 +1,237 @@
+//! Periodic checkpoints so that an interrupted run can continue where it
+//! stopped instead of starting over.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub op: String,
+    pub file: String,
+    pub strategy: String,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub random_align: Option<u64>,
+    #[serde(default)]
+    pub random_map: bool,
+    pub block_size: u64,
+    pub count: u64,
+    /// Every block before this one has completed.
+    pub next_block: u64,
+    pub bytes: u64,
+    pub seconds: f64,
+}
+
+impl Checkpoint {
+    /// Loads a checkpoint, `None` if there is none at `path`.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read checkpoint {}", path.display()))
+            }
+        };
+        let checkpoint = serde_json::from_slice(&data)
+            .with_context(|| format!("invalid checkpoint {}", path.display()))?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Writes the checkpoint to a temp file first so that an interruption
+    /// never leaves a truncated checkpoint behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Fails if `self` was taken from a run with different parameters than `job`.
+    pub fn ensure_matches(&self, job: &Checkpoint) -> Result<()> {
+        let params = [
+            ("operation", &self.op, &job.op),
+            ("file", &self.file, &job.file),
+            ("strategy", &self.strategy, &job.strategy),
+        ];
+        for (name, saved, current) in params {
+            if saved != current {
+                bail!("checkpoint {name} `{saved}` does not match `{current}`");
+            }
+        }
+        let offsets = (self.seed, self.random_align, self.random_map);
+        if offsets != (job.seed, job.random_align, job.random_map) {
+            bail!("checkpoint was taken with different random offsets");
+        }
+        if (self.block_size, self.count) != (job.block_size, job.count) {
+            bail!(
+                "checkpoint is for {} blocks of {} bytes, not {} blocks of {} bytes",
+                self.count,
+                self.block_size,
+                job.count,
+                job.block_size,
+            );
+        }
+        if self.next_block > self.count {
+            bail!("checkpoint is past the last block");
+        }
+        Ok(())
+    }
+}
+
+/// Saves a checkpoint once per interval while blocks complete.
+#[derive(Debug)]
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    state: Checkpoint,
+    base_seconds: f64,
+    start: Instant,
+    last: Instant,
+    ahead: BTreeSet<u64>,
+}
+
+impl Checkpointer {
+    /// `state` is the checkpoint the run starts from.
+    pub fn new(path: PathBuf, interval: Duration, state: Checkpoint) -> Self {
+        let now = Instant::now();
+        Self {
+            path,
+            interval,
+            base_seconds: state.seconds,
+            state,
+            start: now,
+            last: now,
+            ahead: BTreeSet::new(),
+        }
+    }
+
+    /// Records that `block` completed. Blocks may complete out of order, the
+    /// checkpoint only advances past blocks without gaps before them.
+    pub fn complete(&mut self, block: u64, now: Instant) {
+        if block != self.state.next_block {
+            self.ahead.insert(block);
+        } else {
+            self.state.next_block += 1;
+            while self.ahead.remove(&self.state.next_block) {
+                self.state.next_block += 1;
+            }
+        }
+
+        if now - self.last >= self.interval {
+            self.save(now);
+        }
+    }
+
+    pub fn save(&mut self, now: Instant) {
+        self.state.bytes = self.state.next_block * self.state.block_size;
+        self.state.seconds = self.base_seconds + (now - self.start).as_secs_f64();
+        self.last = now;
+        match self.state.save(&self.path) {
+            Ok(()) => info!(
+                "checkpoint at block {}/{}",
+                self.state.next_block, self.state.count
+            ),
+            Err(err) => warn!(
+                "failed to write checkpoint {}: {err:#}",
+                self.path.display()
+            ),
+        }
+    }
+
+    /// Removes the checkpoint after the run completed.
+    pub fn finish(self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                warn!("failed to remove checkpoint {}: {err}", self.path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("raio-checkpoint-{}-{name}", std::process::id()))
+    }
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            op: "read".to_string(),
+            file: "data.bin".to_string(),
+            strategy: "io_uring8".to_string(),
+            seed: Some(7),
+            random_align: None,
+            random_map: false,
+            block_size: 4096,
+            count: 10,
+            next_block: 4,
+            bytes: 4 * 4096,
+            seconds: 1.5,
+        }
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let path = path("round-trip");
+        assert!(Checkpoint::load(&path).unwrap().is_none());
+        checkpoint().save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.next_block, loaded.bytes), (4, 4 * 4096));
+        loaded.ensure_matches(&checkpoint()).unwrap();
+    }
+
+    #[test]
+    fn ensure_matches_rejects_other_runs() {
+        let saved = checkpoint();
+        let other = |change: fn(&mut Checkpoint)| {
+            let mut job = checkpoint();
+            change(&mut job);
+            saved.ensure_matches(&job).unwrap_err().to_string()
+        };
+        assert_eq!(
+            other(|job| job.file = "other.bin".to_string()),
+            "checkpoint file `data.bin` does not match `other.bin`"
+        );
+        assert_eq!(
+            other(|job| job.seed = Some(8)),
+            "checkpoint was taken with different random offsets"
+        );
+        assert_eq!(
+            other(|job| job.count = 20),
+            "checkpoint is for 10 blocks of 4096 bytes, not 20 blocks of 4096 bytes"
+        );
+    }
+
+    #[test]
+    fn checkpointer_advances_past_blocks_without_gaps() {
+        let path = path("advance");
+        let state = Checkpoint {
+            next_block: 0,
+            ..checkpoint()
+        };
+        let mut checkpointer = Checkpointer::new(path.clone(), Duration::from_secs(3600), state);
+        let now = Instant::now();
+        for block in [1, 2, 0, 4] {
+            checkpointer.complete(block, now);
+        }
+        assert!(!path.exists());
+        checkpointer.save(now);
+        let saved = Checkpoint::load(&path).unwrap().unwrap();
+        assert_eq!((saved.next_block, saved.bytes), (3, 3 * 4096));
+        checkpointer.finish();
+        assert!(!path.exists());
+    }
+}
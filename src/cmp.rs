@@ -0,0 +1,153 @@
+//! Block-by-block comparison of two files, reading one of them with a
+//! benchmark strategy.
+
+use crate::{
+    offsets::{Mode, Offsets},
+    progress::Progress,
+    read_file, validate,
+    worker::StartGate,
+    Stats, Strategy,
+};
+use anyhow::{bail, Context, Result};
+use humansize::{ISizeFormatter, BINARY};
+use std::{
+    fs,
+    io::{Seek, SeekFrom},
+    ops::Range,
+    os::unix::fs::FileExt,
+};
+use tracing::{info, warn};
+
+/// Ranges printed before the rest are only counted.
+const MAX_RANGES: usize = 20;
+
+/// Reads `a` with `strategy` and compares every block with the same block
+/// of `b`, which is read with `pread` as each block of `a` completes.
+pub async fn run(a: &str, b: &str, block_size: u64, strategy: Strategy) -> Result<()> {
+    validate::block_size(block_size)?;
+    let file_b = fs::File::open(b).with_context(|| format!("failed to open {b}"))?;
+    let len_a = len(a)?;
+    let len_b = len(b)?;
+    if len_a != len_b {
+        warn!(
+            "{a} is {len_a} bytes but {b} is {len_b} bytes, comparing the first {} bytes",
+            len_a.min(len_b)
+        );
+    }
+    let len = len_a.min(len_b);
+    let count = len / block_size;
+    info!("comparing {count} blocks of {block_size} bytes");
+
+    let mut other = vec![0; block_size as usize];
+    let mut read_err = None;
+    let mut differing = Vec::new();
+    let mut compare = |pos: u64, data: &[u8]| {
+        if read_err.is_some() {
+            return true;
+        }
+        if let Err(err) = file_b.read_exact_at(&mut other, pos) {
+            read_err = Some((pos, err));
+            return true;
+        }
+        let equal = data == other.as_slice();
+        if !equal {
+            differing.push(pos..pos + block_size);
+        }
+        equal
+    };
+
+    let offsets = Offsets::new(block_size, Mode::Sequential, 0, 0..count * block_size);
+    let mut progress = Progress::new(count * block_size);
+    let res = read_file(
+        a,
+        0,
+        &offsets,
+        0..count,
+        strategy,
+        None,
+        None,
+        Some(&mut compare),
+        &mut progress,
+        &mut StartGate::none(),
+    )
+    .await;
+    progress.finish(res.is_ok());
+    let stats = res.with_context(|| format!("failed to read {a}"))?;
+    if let Some((pos, err)) = read_err {
+        return Err(err).with_context(|| format!("failed to read {b} at offset {pos}"));
+    }
+    let tail = compare_tail(a, &file_b, count * block_size..len)?;
+    differing.extend(tail);
+
+    print(len, &stats);
+    // Some strategies complete blocks out of order.
+    differing.sort_by_key(|range| range.start);
+    let ranges = merge(differing);
+    for range in ranges.iter().take(MAX_RANGES) {
+        println!(
+            "differ: {}..{} ({} bytes)",
+            range.start,
+            range.end,
+            range.end - range.start
+        );
+    }
+    if ranges.len() > MAX_RANGES {
+        println!("... and {} more ranges", ranges.len() - MAX_RANGES);
+    }
+
+    let differ_bytes = ranges.iter().map(|r| r.end - r.start).sum::<u64>();
+    if differ_bytes > 0 {
+        bail!("files differ in {differ_bytes} bytes");
+    }
+    if len_a != len_b {
+        bail!("files differ in size");
+    }
+    Ok(())
+}
+
+fn len(path: &str) -> Result<u64> {
+    let mut file = fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+    // `metadata().len()` is 0 for block devices.
+    let len = file
+        .seek(SeekFrom::End(0))
+        .with_context(|| format!("failed to get the size of {path}"))?;
+    Ok(len)
+}
+
+/// Compares the bytes after the last full block.
+fn compare_tail(a: &str, file_b: &fs::File, tail: Range<u64>) -> Result<Option<Range<u64>>> {
+    if tail.is_empty() {
+        return Ok(None);
+    }
+    let mut data_a = vec![0; (tail.end - tail.start) as usize];
+    let mut data_b = data_a.clone();
+    fs::File::open(a)?
+        .read_exact_at(&mut data_a, tail.start)
+        .with_context(|| format!("failed to read {a}"))?;
+    file_b
+        .read_exact_at(&mut data_b, tail.start)
+        .context("failed to read the second file")?;
+    Ok((data_a != data_b).then_some(tail))
+}
+
+/// Joins adjacent ranges, `ranges` has to be sorted.
+fn merge(ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// `len` includes the bytes after the last full block, which are not timed.
+fn print(len: u64, stats: &Stats) {
+    let secs = stats.elapsed.as_secs_f64();
+    println!(
+        "compared {} bytes in {secs:.6} seconds @ {}/s",
+        len,
+        ISizeFormatter::new(stats.bytes as f64 / secs, BINARY),
+    );
+}
@@ -0,0 +1,11 @@
+use crate::Cmd;
+use anyhow::Result;
+use clap::CommandFactory;
+use clap_complete::Shell;
+use std::io;
+
+pub fn run(shell: Shell) -> Result<()> {
+    clap_complete::generate(shell, &mut Cmd::command(), "raio", &mut io::stdout());
+
+    Ok(())
+}
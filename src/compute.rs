@@ -0,0 +1,55 @@
+//! Simulated per-block CPU work, to see how well a strategy overlaps
+//! computation with the I/O it still has in flight.
+
+use crate::parse;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    hint::{black_box, spin_loop},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compute {
+    /// Busy-wait for a fixed time.
+    Spin(Duration),
+    /// Checksum the block's data.
+    Checksum,
+}
+
+impl Compute {
+    /// Does the work for one completed block.
+    pub fn run(self, data: &[u8]) {
+        match self {
+            Self::Spin(duration) => {
+                let until = Instant::now() + duration;
+                while Instant::now() < until {
+                    spin_loop();
+                }
+            }
+            Self::Checksum => {
+                black_box(xxhash_rust::xxh3::xxh3_64(black_box(data)));
+            }
+        }
+    }
+}
+
+impl fmt::Display for Compute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spin(duration) => write!(f, "{duration:?}"),
+            Self::Checksum => f.write_str("checksum"),
+        }
+    }
+}
+
+/// Parses `checksum` or a duration to spin for, e.g. `50us`.
+pub fn parse(s: &str) -> Result<Compute, String> {
+    if s == "checksum" {
+        return Ok(Compute::Checksum);
+    }
+    parse::duration(s)
+        .map(Compute::Spin)
+        .map_err(|err| format!("{err}, or `checksum`"))
+}
@@ -0,0 +1,363 @@
+//! File copies with reads and writes in flight at the same time, the
+//! realistic case of moving data between two devices, with statistics for
+//! each side.
+
+use crate::{
+    compute::Compute, latency::Latency, mem_aligned, mem_aligned_free, prefault,
+    progress::Progress, ring, validate,
+};
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use humansize::{ISizeFormatter, BINARY};
+use io_uring::{opcode, types};
+use std::{
+    fs,
+    io::{Seek, SeekFrom},
+    os::unix::{fs::FileExt, io::AsRawFd},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{info, trace, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Engine {
+    /// A reader thread and a writer thread with blocking std calls, passing
+    /// buffers through a bounded queue
+    Std,
+    /// One io_uring with reads and writes in flight together
+    #[value(name = "io_uring")]
+    IoUring,
+}
+
+/// Statistics of the reads or the writes of a copy.
+#[derive(Debug, Default)]
+pub struct Side {
+    pub bytes: u64,
+    pub latency: Latency,
+    /// Time with at least one operation of this side in flight.
+    pub busy: Duration,
+}
+
+impl Side {
+    fn record(&mut self, bytes: u64, latency: Duration) {
+        self.bytes += bytes;
+        self.latency.record(latency);
+    }
+}
+
+pub struct Copied {
+    pub elapsed: Duration,
+    pub read: Side,
+    pub write: Side,
+    /// Time spent in the transform, see [`ring`].
+    pub transform: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Free,
+    Read,
+    Write,
+}
+
+/// Copies `src` to `dst` with `engine` and `depth` buffers of `block_size`
+/// bytes and prints the throughput and how busy each side was.
+pub fn run(src: &str, dst: &str, block_size: u64, depth: usize, engine: Engine) -> Result<()> {
+    validate::block_size(block_size)?;
+    let (src_file, dst_file, len) = open(src, dst)?;
+    info!(
+        "copying {len} bytes in blocks of {block_size} bytes with {depth} buffers, engine {}",
+        engine
+            .to_possible_value()
+            .expect("no skipped engines")
+            .get_name()
+    );
+    let mut progress = Progress::new(len);
+    let res = match engine {
+        Engine::Std => threads(&src_file, &dst_file, len, block_size, depth, &mut progress),
+        Engine::IoUring => ring(
+            &src_file,
+            &dst_file,
+            len,
+            block_size,
+            depth,
+            None,
+            &mut progress,
+        ),
+    };
+    progress.finish(res.is_ok());
+    let copied = res?;
+
+    let secs = copied.elapsed.as_secs_f64();
+    println!(
+        "copied {len} bytes in {secs:.6} seconds @ {}/s",
+        ISizeFormatter::new(len as f64 / secs, BINARY),
+    );
+    let busy = |side: &Side| side.busy.as_secs_f64() / secs * 100.0;
+    for (name, side) in [("read", &copied.read), ("write", &copied.write)] {
+        println!(
+            "{name}: {} bytes, busy {:.1}% of the run, latency: {}",
+            side.bytes,
+            busy(side),
+            side.latency,
+        );
+    }
+    let (read, write) = (busy(&copied.read), busy(&copied.write));
+    if (read - write).abs() >= 10.0 {
+        println!(
+            "the {} side limits the copy, the other one waits for buffers",
+            if read > write { "read" } else { "write" }
+        );
+    }
+
+    Ok(())
+}
+
+/// Opens `src` and creates or truncates `dst`, returning both and the
+/// length of `src`.
+pub fn open(src: &str, dst: &str) -> Result<(fs::File, fs::File, u64)> {
+    let mut src_file = fs::File::open(src).with_context(|| format!("failed to open {src}"))?;
+    let len = src_file
+        .seek(SeekFrom::End(0))
+        .with_context(|| format!("failed to get the size of {src}"))?;
+    let dst_file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dst)
+        .with_context(|| format!("failed to open {dst}"))?;
+    Ok((src_file, dst_file, len))
+}
+
+/// Copies `len` bytes through one ring with `depth` buffers. Each buffer is
+/// read into, transformed and written out before it is reused, so at most
+/// `depth` blocks are between the two files at any time.
+pub fn ring(
+    src: &fs::File,
+    dst: &fs::File,
+    len: u64,
+    block_size: u64,
+    depth: usize,
+    transform: Option<Compute>,
+    progress: &mut Progress,
+) -> Result<Copied> {
+    let src_fd = types::Fd(src.as_raw_fd());
+    let dst_fd = types::Fd(dst.as_raw_fd());
+    let count = len.div_ceil(block_size);
+    // A file shorter than a block needs no more than its length.
+    let buf_size = block_size.min(len).max(1) as usize;
+    let mut ring = ring::new_ring((depth as u32).next_power_of_two())?;
+    let bufs = (0..depth)
+        .map(|_| {
+            let buf = mem_aligned(buf_size, 4096)?;
+            prefault(buf, buf_size);
+            Ok(buf)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut stages = vec![(Stage::Free, 0, 0, Instant::now()); depth];
+    let mut free = (0..depth).rev().collect::<Vec<_>>();
+    let mut read = Side::default();
+    let mut write = Side::default();
+    let mut transform_time = Duration::ZERO;
+
+    let mut next = 0;
+    let (mut reads, mut writes) = (0, 0);
+    // Submitted or queued operations whose completions were not reaped yet.
+    let mut in_flight = 0;
+    let start = Instant::now();
+    let mut woken = start;
+    let res = (|| {
+        while next < count || reads + writes > 0 {
+            while next < count {
+                let Some(slot) = free.pop() else {
+                    break;
+                };
+                let n = block_size.min(len - next * block_size);
+                let entry = opcode::Read::new(src_fd, bufs[slot], n as _)
+                    .offset(next * block_size)
+                    .build()
+                    .user_data(slot as u64);
+                // Note that the developer needs to ensure
+                // that the entry pushed into submission queue is valid (e.g. fd, buffer).
+                unsafe {
+                    ring.submission()
+                        .push(&entry)
+                        .expect("submission queue is full");
+                }
+                stages[slot] = (Stage::Read, next, 0, Instant::now());
+                next += 1;
+                reads += 1;
+                in_flight += 1;
+            }
+
+            ring.submit_and_wait(1)?;
+            let now = Instant::now();
+            if reads > 0 {
+                read.busy += now - woken;
+            }
+            if writes > 0 {
+                write.busy += now - woken;
+            }
+            woken = now;
+
+            let completed = ring.completion().collect::<Vec<_>>();
+            in_flight -= completed.len();
+            for cqe in completed {
+                let slot = cqe.user_data() as usize;
+                let (stage, block, bytes, issued) = stages[slot];
+                trace!("{stage:?} result: {} @ {block}", cqe.result());
+                let op = if stage == Stage::Read {
+                    "read"
+                } else {
+                    "write"
+                };
+                if cqe.result() < 0 {
+                    let err = std::io::Error::from_raw_os_error(-cqe.result());
+                    return Err(err).with_context(|| {
+                        format!(
+                            "{op} of block {block} (offset {}) failed",
+                            block * block_size
+                        )
+                    });
+                }
+                let result = cqe.result() as usize;
+
+                if stage == Stage::Read {
+                    read.record(result as u64, issued.elapsed());
+                    reads -= 1;
+                    let expected = block_size.min(len - block * block_size) as usize;
+                    if result != expected {
+                        return Err(anyhow!(
+                            "short read of block {block}: {result} of {expected} bytes"
+                        ));
+                    }
+                    if let Some(transform) = transform {
+                        let data = unsafe { std::slice::from_raw_parts(bufs[slot], result) };
+                        let started = Instant::now();
+                        transform.run(data);
+                        transform_time += started.elapsed();
+                    }
+
+                    let entry = opcode::Write::new(dst_fd, bufs[slot], result as _)
+                        .offset(block * block_size)
+                        .build()
+                        .user_data(slot as u64);
+                    unsafe {
+                        ring.submission()
+                            .push(&entry)
+                            .expect("submission queue is full");
+                    }
+                    stages[slot] = (Stage::Write, block, result, Instant::now());
+                    writes += 1;
+                    in_flight += 1;
+                } else {
+                    let latency = issued.elapsed();
+                    write.record(result as u64, latency);
+                    writes -= 1;
+                    if result != bytes {
+                        return Err(anyhow!(
+                            "short write of block {block}: {result} of {bytes} bytes"
+                        ));
+                    }
+                    progress.add(block, bytes as u64, latency);
+                    stages[slot].0 = Stage::Free;
+                    free.push(slot);
+                }
+            }
+        }
+        Ok(())
+    })();
+    let elapsed = start.elapsed();
+    // Operations may still be in flight after a failure, the kernel would
+    // write into their buffers after they were freed.
+    if let Err(err) = ring::drain(&mut ring, in_flight) {
+        warn!("{err:#}, leaking the copy buffers");
+        res?;
+        return Err(err);
+    }
+    for buf in bufs {
+        mem_aligned_free(buf, buf_size, 4096);
+    }
+    res?;
+
+    Ok(Copied {
+        elapsed,
+        read,
+        write,
+        transform: transform_time,
+    })
+}
+
+/// Copies `len` bytes with a reader thread and the calling thread as the
+/// writer, with `depth` buffers passed back and forth.
+fn threads(
+    src: &fs::File,
+    dst: &fs::File,
+    len: u64,
+    block_size: u64,
+    depth: usize,
+    progress: &mut Progress,
+) -> Result<Copied> {
+    let count = len.div_ceil(block_size);
+    let (free_tx, free_rx) = mpsc::sync_channel::<Vec<u8>>(depth);
+    let (full_tx, full_rx) = mpsc::sync_channel::<(u64, usize, Vec<u8>)>(depth);
+    for _ in 0..depth {
+        free_tx
+            .send(vec![0; block_size.min(len) as usize])
+            .expect("the queue has room for every buffer");
+    }
+
+    let start = Instant::now();
+    let (read, write) = thread::scope(|scope| {
+        let reader = scope.spawn(move || -> Result<Side> {
+            let mut side = Side::default();
+            for block in 0..count {
+                // The writer stopped after a failure.
+                let Ok(mut buf) = free_rx.recv() else {
+                    break;
+                };
+                let pos = block * block_size;
+                let n = block_size.min(len - pos) as usize;
+                let issued = Instant::now();
+                src.read_exact_at(&mut buf[..n], pos)
+                    .with_context(|| format!("read of block {block} (offset {pos}) failed"))?;
+                let latency = issued.elapsed();
+                side.record(n as u64, latency);
+                side.busy += latency;
+                if full_tx.send((block, n, buf)).is_err() {
+                    break;
+                }
+            }
+            Ok(side)
+        });
+
+        let mut side = Side::default();
+        let res = full_rx.iter().try_for_each(|(block, n, buf)| {
+            let pos = block * block_size;
+            let issued = Instant::now();
+            dst.write_all_at(&buf[..n], pos)
+                .with_context(|| format!("write of block {block} (offset {pos}) failed"))?;
+            let latency = issued.elapsed();
+            side.record(n as u64, latency);
+            side.busy += latency;
+            progress.add(block, n as u64, latency);
+            // The reader may be done and gone.
+            let _ = free_tx.send(buf);
+            Ok::<_, anyhow::Error>(())
+        });
+        // Lets the reader stop if the writer failed.
+        drop(full_rx);
+        drop(free_tx);
+        let read = reader.join().expect("reader thread panicked")?;
+        res?;
+        Ok::<_, anyhow::Error>((read, side))
+    })?;
+
+    Ok(Copied {
+        elapsed: start.elapsed(),
+        read,
+        write,
+        transform: Duration::ZERO,
+    })
+}
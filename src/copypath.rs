@@ -0,0 +1,273 @@
+//! Compares the ways of copying a file: through a userspace buffer, with
+//! O_DIRECT, and without copying through userspace at all.
+
+use crate::{mem_aligned, mem_aligned_free, validate};
+use anyhow::{anyhow, bail, Context, Result};
+use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+use std::{
+    fmt, fs, io,
+    os::unix::{
+        fs::{FileExt, OpenOptionsExt},
+        io::{AsRawFd, FromRawFd, OwnedFd},
+    },
+    time::{Duration, Instant},
+};
+use tracing::{debug, info};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Path {
+    Buffered,
+    Direct,
+    CopyFileRange,
+    Splice,
+}
+
+impl Path {
+    const ALL: [Self; 4] = [
+        Self::Buffered,
+        Self::Direct,
+        Self::CopyFileRange,
+        Self::Splice,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Buffered => "buffered",
+            Self::Direct => "O_DIRECT",
+            Self::CopyFileRange => "copy_file_range",
+            Self::Splice => "splice",
+        }
+    }
+
+    /// Whether the data passes through a userspace buffer.
+    fn copies_to_userspace(self) -> bool {
+        matches!(self, Self::Buffered | Self::Direct)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Copies `src` to `dst` once per copy path, each time including the final
+/// `fdatasync` of `dst`, and prints a table of the time, CPU time and bytes
+/// copied through userspace per path. Later paths may read `src` from the
+/// page cache filled by earlier ones, except for O_DIRECT.
+pub fn run(src: &str, dst: &str, block_size: u64) -> Result<()> {
+    validate::block_size(block_size)?;
+    let len = fs::metadata(src)
+        .with_context(|| format!("failed to stat {src}"))?
+        .len();
+    info!("copying {len} bytes from {src} to {dst} with every copy path");
+
+    println!(
+        "{:<16} {:>10} {:>12} {:>10} {:>10} {:>12}",
+        "path", "seconds", "speed", "cpu", "cpu/GiB", "userspace"
+    );
+    let mut failures = 0;
+    for path in Path::ALL {
+        match copy(path, src, dst, len, block_size) {
+            Ok((elapsed, cpu)) => {
+                let gib = len as f64 / (1u64 << 30) as f64;
+                let userspace = if path.copies_to_userspace() { len } else { 0 };
+                println!(
+                    "{:<16} {:>10.6} {:>12} {:>9.3}s {:>9.3}s {:>12}",
+                    path.as_str(),
+                    elapsed.as_secs_f64(),
+                    format!(
+                        "{}/s",
+                        ISizeFormatter::new(len as f64 / elapsed.as_secs_f64(), BINARY)
+                    ),
+                    cpu.as_secs_f64(),
+                    cpu.as_secs_f64() / gib,
+                    SizeFormatter::new(userspace, BINARY).to_string(),
+                );
+            }
+            Err(err) => {
+                failures += 1;
+                println!("{:<16} failed: {err:#}", path.as_str());
+            }
+        }
+    }
+
+    if failures > 0 {
+        bail!("{failures}/{} copy paths failed", Path::ALL.len());
+    }
+    Ok(())
+}
+
+/// Returns the wall clock and CPU time of one copy.
+fn copy(
+    path: Path,
+    src: &str,
+    dst: &str,
+    len: u64,
+    block_size: u64,
+) -> Result<(Duration, Duration)> {
+    let flags = if path == Path::Direct {
+        libc::O_DIRECT
+    } else {
+        0
+    };
+    let src = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(flags)
+        .open(src)
+        .with_context(|| format!("failed to open {src}"))?;
+    let dst = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(flags)
+        .open(dst)
+        .with_context(|| format!("failed to open {dst}"))?;
+
+    let cpu = cpu_time()?;
+    let start = Instant::now();
+    match path {
+        Path::Buffered | Path::Direct => copy_userspace(&src, &dst, len, block_size)?,
+        Path::CopyFileRange => copy_file_range(&src, &dst, len)?,
+        Path::Splice => splice(&src, &dst, len, block_size)?,
+    }
+    dst.sync_data().context("failed to sync")?;
+    let elapsed = start.elapsed();
+    let cpu = cpu_time()? - cpu;
+    debug!("{path}: {elapsed:?}, {cpu:?} CPU");
+
+    Ok((elapsed, cpu))
+}
+
+fn copy_userspace(src: &fs::File, dst: &fs::File, len: u64, block_size: u64) -> Result<()> {
+    let buf = mem_aligned(block_size as usize, 4096)?;
+    let slice = unsafe { std::slice::from_raw_parts_mut(buf, block_size as usize) };
+    let mut res = Ok(());
+    let mut pos = 0;
+    while pos < len {
+        let n = block_size.min(len - pos) as usize;
+        if n < block_size as usize {
+            // O_DIRECT needs aligned lengths, so the tail goes through the
+            // page cache.
+            res = clear_direct(src).and_then(|()| clear_direct(dst));
+            if res.is_err() {
+                break;
+            }
+        }
+        res = src
+            .read_exact_at(&mut slice[..n], pos)
+            .context("read failed")
+            .and_then(|()| dst.write_all_at(&slice[..n], pos).context("write failed"));
+        if res.is_err() {
+            break;
+        }
+        pos += n as u64;
+    }
+    mem_aligned_free(buf, block_size as usize, 4096);
+    res
+}
+
+fn clear_direct(file: &fs::File) -> Result<()> {
+    let fd = file.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_DIRECT) } < 0 {
+        return Err(io::Error::last_os_error()).context("failed to clear O_DIRECT");
+    }
+    Ok(())
+}
+
+fn copy_file_range(src: &fs::File, dst: &fs::File, len: u64) -> Result<()> {
+    let mut off_in = 0i64;
+    let mut off_out = 0i64;
+    while (off_in as u64) < len {
+        let remaining = (len - off_in as u64) as usize;
+        let n = unsafe {
+            libc::copy_file_range(
+                src.as_raw_fd(),
+                &mut off_in,
+                dst.as_raw_fd(),
+                &mut off_out,
+                remaining,
+                0,
+            )
+        };
+        match n {
+            n if n < 0 => return Err(io::Error::last_os_error()).context("copy_file_range failed"),
+            0 => return Err(anyhow!("copy_file_range stopped at offset {off_in}")),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Moves the data through a pipe with `splice`, `block_size` bytes at a time
+/// or less if the pipe cannot grow that large.
+fn splice(src: &fs::File, dst: &fs::File, len: u64, block_size: u64) -> Result<()> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error()).context("failed to create pipe");
+    }
+    let pipe = unsafe { [OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])] };
+    let [read_end, write_end] = fds;
+
+    let size = unsafe { libc::fcntl(write_end, libc::F_SETPIPE_SZ, block_size as libc::c_int) };
+    let chunk = if size > 0 {
+        size as usize
+    } else {
+        unsafe { libc::fcntl(write_end, libc::F_GETPIPE_SZ) }.max(4096) as usize
+    };
+    debug!("splicing {chunk} bytes at a time");
+
+    let mut off_in = 0i64;
+    let mut off_out = 0i64;
+    while (off_in as u64) < len {
+        let want = chunk.min((len - off_in as u64) as usize);
+        let n = unsafe {
+            libc::splice(
+                src.as_raw_fd(),
+                &mut off_in,
+                write_end,
+                std::ptr::null_mut(),
+                want,
+                libc::SPLICE_F_MOVE,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error()).context("splice from the source failed");
+        }
+        if n == 0 {
+            return Err(anyhow!("splice stopped at offset {off_in}"));
+        }
+        let mut pending = n as usize;
+        while pending > 0 {
+            let n = unsafe {
+                libc::splice(
+                    read_end,
+                    std::ptr::null_mut(),
+                    dst.as_raw_fd(),
+                    &mut off_out,
+                    pending,
+                    libc::SPLICE_F_MOVE,
+                )
+            };
+            if n <= 0 {
+                return Err(io::Error::last_os_error()).context("splice to the destination failed");
+            }
+            pending -= n as usize;
+        }
+    }
+    drop(pipe);
+    Ok(())
+}
+
+/// User and system CPU time of the process so far.
+fn cpu_time() -> Result<Duration> {
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } < 0 {
+        return Err(io::Error::last_os_error()).context("getrusage failed");
+    }
+    let time = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    Ok(time(usage.ru_utime) + time(usage.ru_stime))
+}
@@ -0,0 +1,194 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub name: String,
+    pub major: u32,
+    pub minor: u32,
+    pub sysfs: PathBuf,
+}
+
+impl Device {
+    /// Resolves the whole-disk block device backing `path`. `path` may be a
+    /// block device node, a regular file or a file that does not exist yet
+    /// (in which case its parent directory is used).
+    pub fn for_path(path: &str) -> Result<Option<Self>> {
+        let path = Path::new(path);
+        let meta = match fs::metadata(path) {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let parent = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+                fs::metadata(parent).context("failed to stat parent directory")?
+            }
+            Err(err) => return Err(err).context("failed to stat target"),
+        };
+        let dev = if meta.file_type().is_block_device() {
+            meta.rdev()
+        } else {
+            meta.dev()
+        };
+        let (major, minor) = unsafe { (libc::major(dev), libc::minor(dev)) };
+
+        // Filesystems without a backing device (tmpfs, overlayfs, ...) have no
+        // entry here.
+        let Ok(mut sysfs) = fs::canonicalize(format!("/sys/dev/block/{major}:{minor}")) else {
+            return Ok(None);
+        };
+        if sysfs.join("partition").exists() {
+            sysfs.pop();
+        }
+        let name = sysfs
+            .file_name()
+            .context("invalid sysfs path")?
+            .to_string_lossy()
+            .into_owned();
+        let (major, minor) = read_dev(&sysfs).unwrap_or((major, minor));
+
+        Ok(Some(Self {
+            name,
+            major,
+            minor,
+            sysfs,
+        }))
+    }
+
+    pub fn attr(&self, attr: &str) -> Option<String> {
+        let value = fs::read_to_string(self.sysfs.join(attr)).ok()?;
+        Some(value.trim().to_string())
+    }
+
+    pub fn attributes(&self) -> DeviceAttributes {
+        DeviceAttributes {
+            name: self.name.clone(),
+            scheduler: self.attr("queue/scheduler").map(|s| active_scheduler(&s)),
+            nr_requests: self.attr("queue/nr_requests").and_then(|s| s.parse().ok()),
+            rotational: self.attr("queue/rotational").map(|s| s == "1"),
+            write_cache: self.attr("queue/write_cache"),
+            queue_depth: self.attr("device/queue_depth").and_then(|s| s.parse().ok()),
+        }
+    }
+
+    /// Switches the I/O scheduler of the device. The previous scheduler is
+    /// restored when the returned guard is dropped.
+    pub fn set_scheduler(&self, scheduler: IoScheduler) -> Result<SchedulerGuard> {
+        let path = self.sysfs.join("queue/scheduler");
+        let available = fs::read_to_string(&path)
+            .with_context(|| format!("device {} has no I/O scheduler", self.name))?;
+        let previous = active_scheduler(&available);
+        let found = available
+            .split_whitespace()
+            .any(|s| s.trim_start_matches('[').trim_end_matches(']') == scheduler.as_str());
+        if !found {
+            bail!(
+                "scheduler {} is not available for device {} (available: {})",
+                scheduler,
+                self.name,
+                available.trim(),
+            );
+        }
+
+        fs::write(&path, scheduler.as_str()).with_context(|| {
+            format!(
+                "failed to set scheduler {} for device {} (requires root)",
+                scheduler, self.name,
+            )
+        })?;
+
+        Ok(SchedulerGuard { path, previous })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IoScheduler {
+    None,
+    MqDeadline,
+    Bfq,
+}
+
+impl IoScheduler {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::MqDeadline => "mq-deadline",
+            Self::Bfq => "bfq",
+        }
+    }
+}
+
+impl fmt::Display for IoScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct SchedulerGuard {
+    path: PathBuf,
+    previous: String,
+}
+
+impl Drop for SchedulerGuard {
+    fn drop(&mut self) {
+        if let Err(err) = fs::write(&self.path, &self.previous) {
+            warn!("failed to restore scheduler {}: {}", self.previous, err);
+        }
+    }
+}
+
+fn read_dev(sysfs: &Path) -> Option<(u32, u32)> {
+    let dev = fs::read_to_string(sysfs.join("dev")).ok()?;
+    let (major, minor) = dev.trim().split_once(':')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// The scheduler file lists all available schedulers with the active one in
+/// brackets, e.g. `none [mq-deadline] kyber bfq`.
+fn active_scheduler(s: &str) -> String {
+    s.split_whitespace()
+        .find_map(|s| s.strip_prefix('[')?.strip_suffix(']'))
+        .unwrap_or(s)
+        .to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceAttributes {
+    pub name: String,
+    pub scheduler: Option<String>,
+    pub nr_requests: Option<u64>,
+    pub rotational: Option<bool>,
+    pub write_cache: Option<String>,
+    pub queue_depth: Option<u64>,
+}
+
+impl fmt::Display for DeviceAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn opt<T: fmt::Display>(value: &Option<T>) -> String {
+            match value {
+                Some(value) => value.to_string(),
+                None => "-".to_string(),
+            }
+        }
+
+        write!(
+            f,
+            "device {}: scheduler={} nr_requests={} rotational={} write_cache={} queue_depth={}",
+            self.name,
+            opt(&self.scheduler),
+            opt(&self.nr_requests),
+            opt(&self.rotational),
+            opt(&self.write_cache),
+            opt(&self.queue_depth),
+        )
+    }
+}
@@ -0,0 +1,167 @@
+//! Compares two results files, for before/after analyses.
+
+use crate::{
+    latency::{LatencySummary, Short},
+    results::Results,
+};
+use anyhow::{Context, Result};
+use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+use serde_json::Value;
+use std::{collections::BTreeSet, path::Path, time::Duration};
+
+/// Prints the throughput and latency of `a` and `b` with the change from `a`
+/// to `b`, followed by the parameters and environment that differ.
+pub fn run(a: &Path, b: &Path) -> Result<()> {
+    let results = [Results::load(a)?, Results::load(b)?];
+    let [old, new] = &results;
+    println!("a: {} ({} {})", a.display(), old.op, old.job.strategy);
+    println!("b: {} ({} {})", b.display(), new.op, new.job.strategy);
+    println!();
+
+    println!("{:<14} {:>14} {:>14} {:>9}", "", "a", "b", "delta");
+    let speed = |r: &Results| format!("{}/s", ISizeFormatter::new(r.bytes_per_sec, BINARY));
+    row("throughput", &results, |r| r.bytes_per_sec, speed);
+    row("iops", &results, |r| r.iops, |r| format!("{:.0}", r.iops));
+    row(
+        "seconds",
+        &results,
+        |r| r.seconds,
+        |r| format!("{:.6}", r.seconds),
+    );
+    if let (Some(old), Some(new)) = (&old.latency, &new.latency) {
+        let latencies = [old, new];
+        let us = |us: f64| Short(Duration::from_secs_f64(us / 1e6)).to_string();
+        for (name, value) in [
+            ("mean", (|l| l.mean_us) as fn(&LatencySummary) -> f64),
+            ("p50", |l| l.p50_us),
+            ("p90", |l| l.p90_us),
+            ("p99", |l| l.p99_us),
+            ("p99.9", |l| l.p999_us),
+            ("max", |l| l.max_us),
+        ] {
+            row(
+                &format!("latency {name}"),
+                &latencies,
+                |l| value(l),
+                |l| us(value(l)),
+            );
+        }
+    }
+    if let (Some(old), Some(new)) = (&old.efficiency, &new.efficiency) {
+        let efficiencies = [old, new];
+        row(
+            "CPU per GiB",
+            &efficiencies,
+            |e| e.cpu_ms_per_gib,
+            |e| format!("{:.1}ms", e.cpu_ms_per_gib),
+        );
+        if let (Some(a), Some(b)) = (old.bytes_per_syscall, new.bytes_per_syscall) {
+            row(
+                "bytes/syscall",
+                &[a, b],
+                |&bytes| bytes,
+                |&bytes| SizeFormatter::new(bytes as u64, BINARY).to_string(),
+            );
+        }
+    }
+    if let (Some(old), Some(new)) = (old.extents, new.extents) {
+        row(
+            "extents",
+            &[old, new],
+            |e| e.count as f64,
+            |e| e.count.to_string(),
+        );
+    }
+
+    let old_job = serde_json::to_value(&old.job).context("failed to serialize job")?;
+    let new_job = serde_json::to_value(&new.job).context("failed to serialize job")?;
+    let mut changes = changed(&old_job, &new_job);
+    for (name, old, new) in [
+        ("version", &old.version, &new.version),
+        ("op", &old.op, &new.op),
+    ] {
+        if old != new {
+            changes.push((name.to_string(), old.clone(), new.clone()));
+        }
+    }
+    if old.verify != new.verify {
+        changes.push((
+            "verify".to_string(),
+            old.verify.to_string(),
+            new.verify.to_string(),
+        ));
+    }
+    if let (Some(old), Some(new)) = (old.pressure, new.pressure) {
+        for (name, old, new) in [
+            ("cpu pressure", old.cpu.some, new.cpu.some),
+            ("memory pressure", old.memory.some, new.memory.some),
+        ] {
+            // Stalls from other load vary between any two runs.
+            if (old - new).abs() >= 1.0 {
+                changes.push((name.to_string(), format!("{old:.1}%"), format!("{new:.1}%")));
+            }
+        }
+    }
+
+    println!();
+    if changes.is_empty() {
+        println!("parameters and environment are the same");
+    } else {
+        println!("changed:");
+        for (name, old, new) in changes {
+            println!("  {name}: {old} -> {new}");
+        }
+    }
+    Ok(())
+}
+
+fn row<T>(name: &str, values: &[T; 2], number: impl Fn(&T) -> f64, text: impl Fn(&T) -> String) {
+    let [old, new] = values;
+    let (a, b) = (number(old), number(new));
+    let delta = if a != 0.0 {
+        format!("{:+.1}%", (b - a) / a * 100.0)
+    } else {
+        "-".to_string()
+    };
+    println!("{name:<14} {:>14} {:>14} {delta:>9}", text(old), text(new));
+}
+
+/// Fields of two serialized jobs that differ, as `(field, old, new)`.
+fn changed(old: &Value, new: &Value) -> Vec<(String, String, String)> {
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        return Vec::new();
+    };
+    let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+    keys.into_iter()
+        .filter_map(|key| {
+            let old = old.get(key).unwrap_or(&Value::Null);
+            let new = new.get(key).unwrap_or(&Value::Null);
+            (old != new).then(|| (key.clone(), old.to_string(), new.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn changed_lists_differing_fields_by_name() {
+        let old = json!({"block_size": 4096, "strategy": "std", "direct": false});
+        let new = json!({"block_size": 4096, "strategy": "max", "seed": 7});
+        let change = |field: &str, old: &str, new: &str| {
+            (field.to_string(), old.to_string(), new.to_string())
+        };
+        assert_eq!(
+            changed(&old, &new),
+            [
+                change("direct", "false", "null"),
+                change("seed", "null", "7"),
+                change("strategy", "\"std\"", "\"max\""),
+            ]
+        );
+        assert!(changed(&old, &old).is_empty());
+        assert!(changed(&old, &json!(null)).is_empty());
+    }
+}
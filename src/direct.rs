@@ -0,0 +1,240 @@
+//! O_DIRECT alignment: the constraints a target reports, which of them a
+//! failed operation violated, and how a run can fall back instead of
+//! aborting.
+
+use crate::{device::Device, Job};
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{ffi::CString, fmt, io};
+
+/// What to do when an O_DIRECT read fails for misalignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fallback {
+    /// Repeat the run through the page cache.
+    Buffered,
+    /// Repeat the run with the block size and offsets rounded up to the
+    /// required alignment.
+    Align,
+}
+
+/// Alignment O_DIRECT requires of buffers, and of offsets and lengths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alignment {
+    pub memory: u64,
+    pub offset: u64,
+}
+
+impl Alignment {
+    /// The alignment `statx` reports for `path`, or the logical block size
+    /// of its device on kernels before 6.1, `None` if neither is known.
+    pub fn of(path: &str) -> Option<Self> {
+        let c_path = CString::new(path).ok()?;
+        let mut stx = unsafe { std::mem::zeroed::<libc::statx>() };
+        let res = unsafe {
+            libc::statx(
+                libc::AT_FDCWD,
+                c_path.as_ptr(),
+                0,
+                libc::STATX_DIOALIGN,
+                &mut stx,
+            )
+        };
+        if res == 0 && stx.stx_mask & libc::STATX_DIOALIGN != 0 && stx.stx_dio_offset_align > 0 {
+            return Some(Self {
+                memory: stx.stx_dio_mem_align as u64,
+                offset: stx.stx_dio_offset_align as u64,
+            });
+        }
+        let size = Device::for_path(path)
+            .ok()??
+            .attr("queue/logical_block_size")?
+            .parse::<u64>()
+            .ok()?;
+        Some(Self {
+            memory: size,
+            offset: size,
+        })
+    }
+}
+
+/// An O_DIRECT operation that failed with EINVAL, and which constraints it
+/// broke.
+#[derive(Debug, Clone, Copy)]
+pub struct Misaligned {
+    pub alignment: Alignment,
+    pub buffer: Option<usize>,
+    pub offset: Option<u64>,
+    pub length: Option<u64>,
+}
+
+impl Misaligned {
+    /// The constraints an operation on `buffer` at `offset` of `length`
+    /// bytes breaks.
+    pub fn check(alignment: Alignment, buffer: *const u8, offset: u64, length: u64) -> Self {
+        let buffer = buffer as usize;
+        Self {
+            alignment,
+            buffer: (!(buffer as u64).is_multiple_of(alignment.memory)).then_some(buffer),
+            offset: (!offset.is_multiple_of(alignment.offset)).then_some(offset),
+            length: (!length.is_multiple_of(alignment.offset)).then_some(length),
+        }
+    }
+
+    /// Adds the broken constraints to `err` if it is an EINVAL.
+    pub fn explain(
+        err: io::Error,
+        alignment: Option<Alignment>,
+        buffer: *const u8,
+        offset: u64,
+        length: u64,
+    ) -> anyhow::Error {
+        match alignment {
+            Some(alignment) if err.raw_os_error() == Some(libc::EINVAL) => {
+                anyhow::Error::new(err).context(Self::check(alignment, buffer, offset, length))
+            }
+            _ => err.into(),
+        }
+    }
+}
+
+impl fmt::Display for Misaligned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Alignment { memory, offset } = self.alignment;
+        let mut violations = Vec::new();
+        if let Some(buffer) = self.buffer {
+            violations.push(format!(
+                "buffer address {buffer:#x} is not a multiple of {memory}"
+            ));
+        }
+        if let Some(pos) = self.offset {
+            violations.push(format!("offset {pos} is not a multiple of {offset}"));
+        }
+        if let Some(length) = self.length {
+            violations.push(format!("length {length} is not a multiple of {offset}"));
+        }
+        if violations.is_empty() {
+            write!(
+                f,
+                "O_DIRECT rejected an operation that meets the reported alignment \
+                 ({memory} bytes of memory, {offset} bytes of offset and length)"
+            )
+        } else {
+            write!(f, "O_DIRECT misalignment: {}", violations.join(", "))
+        }
+    }
+}
+
+impl std::error::Error for Misaligned {}
+
+/// `job` adjusted for `fallback` after `misaligned`.
+pub fn adjust(job: &Job, fallback: Fallback, misaligned: &Misaligned) -> Result<Job> {
+    let mut job = job.clone();
+    match fallback {
+        Fallback::Buffered => job.direct = false,
+        Fallback::Align => {
+            if misaligned.buffer.is_some() {
+                bail!(
+                    "strategy {} allocates misaligned buffers, which --direct-fallback align \
+                     cannot change, use --direct-fallback buffered or another strategy",
+                    job.strategy
+                );
+            }
+            if job.offsets.is_some() {
+                bail!(
+                    "--direct-fallback align cannot move the offsets of an --offsets file, \
+                     use --direct-fallback buffered or align them in the file"
+                );
+            }
+            if misaligned.offset.is_none() && misaligned.length.is_none() {
+                bail!("nothing to align, the operation met the reported alignment");
+            }
+            let align = misaligned.alignment.offset;
+            job.block_size = job.block_size.next_multiple_of(align);
+            job.random_align = job.random_align.map(|a| a.next_multiple_of(align));
+        }
+    }
+    job.direct_fallback = None;
+    Ok(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTOR: Alignment = Alignment {
+        memory: 512,
+        offset: 512,
+    };
+
+    fn misaligned(offset: Option<u64>, length: Option<u64>) -> Misaligned {
+        Misaligned {
+            alignment: SECTOR,
+            buffer: None,
+            offset,
+            length,
+        }
+    }
+
+    #[test]
+    fn check_finds_each_constraint() {
+        let aligned = Misaligned::check(SECTOR, 0x1000 as *const u8, 4096, 4096);
+        assert_eq!(
+            (aligned.buffer, aligned.offset, aligned.length),
+            (None, None, None)
+        );
+        let broken = Misaligned::check(SECTOR, 0x1010 as *const u8, 1000, 100);
+        assert_eq!(
+            (broken.buffer, broken.offset, broken.length),
+            (Some(0x1010), Some(1000), Some(100))
+        );
+        assert_eq!(
+            broken.to_string(),
+            "O_DIRECT misalignment: buffer address 0x1010 is not a multiple of 512, \
+             offset 1000 is not a multiple of 512, length 100 is not a multiple of 512"
+        );
+    }
+
+    #[test]
+    fn align_rounds_block_size_and_random_align_up() {
+        let job = Job::parse(&[
+            "-f",
+            "x",
+            "-s",
+            "1000",
+            "--random",
+            "--random-align",
+            "100",
+            "--direct",
+            "--direct-fallback",
+            "align",
+        ]);
+        let adjusted = adjust(&job, Fallback::Align, &misaligned(Some(100), Some(1000))).unwrap();
+        assert_eq!(adjusted.block_size, 1024);
+        assert_eq!(adjusted.random_align, Some(512));
+        assert!(adjusted.direct);
+        assert_eq!(adjusted.direct_fallback, None);
+    }
+
+    #[test]
+    fn buffered_turns_off_direct() {
+        let job = Job::parse(&["-f", "x", "-s", "1000", "--direct"]);
+        let adjusted = adjust(&job, Fallback::Buffered, &misaligned(None, Some(1000))).unwrap();
+        assert!(!adjusted.direct);
+        assert_eq!(adjusted.block_size, 1000);
+    }
+
+    #[test]
+    fn align_cannot_fix_everything() {
+        let job = Job::parse(&["-f", "x", "-s", "1000", "--direct"]);
+        let buffer = Misaligned {
+            buffer: Some(0x1010),
+            ..misaligned(None, None)
+        };
+        assert!(adjust(&job, Fallback::Align, &buffer).is_err());
+        assert!(adjust(&job, Fallback::Align, &misaligned(None, None)).is_err());
+        let listed = Job::parse(&["-f", "x", "--offsets", "offsets.txt", "--direct"]);
+        assert!(adjust(&listed, Fallback::Align, &misaligned(Some(1000), None)).is_err());
+    }
+}
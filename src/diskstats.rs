@@ -0,0 +1,105 @@
+//! The kernel's I/O accounting for a device from `/proc/diskstats`, to
+//! cross-check raio's own numbers against what reached the device.
+
+use crate::device::Device;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs};
+
+/// Sectors in `/proc/diskstats` are always 512 bytes, whatever the device's
+/// logical block size.
+const SECTOR: u64 = 512;
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct DiskStats {
+    pub reads: u64,
+    pub read_merges: u64,
+    pub read_sectors: u64,
+    pub read_ms: u64,
+    pub writes: u64,
+    pub write_merges: u64,
+    pub write_sectors: u64,
+    pub write_ms: u64,
+    /// Requests in flight when the snapshot was taken, not a counter.
+    pub in_flight: u64,
+    /// Time the device had requests in flight.
+    pub io_ms: u64,
+    /// Time requests spent in the queue and on the device, summed over all
+    /// requests.
+    pub queue_ms: u64,
+}
+
+impl DiskStats {
+    /// Reads the line of `device`, or `None` if the kernel does not list it.
+    pub fn read(device: &Device) -> Result<Option<Self>> {
+        let data =
+            fs::read_to_string("/proc/diskstats").context("failed to read /proc/diskstats")?;
+        Ok(data.lines().find_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let major = fields.first()?.parse::<u32>().ok()?;
+            let minor = fields.get(1)?.parse::<u32>().ok()?;
+            if (major, minor) != (device.major, device.minor) {
+                return None;
+            }
+            let field = |i: usize| fields.get(i + 3)?.parse::<u64>().ok();
+            Some(Self {
+                reads: field(0)?,
+                read_merges: field(1)?,
+                read_sectors: field(2)?,
+                read_ms: field(3)?,
+                writes: field(4)?,
+                write_merges: field(5)?,
+                write_sectors: field(6)?,
+                write_ms: field(7)?,
+                in_flight: field(8)?,
+                io_ms: field(9)?,
+                queue_ms: field(10)?,
+            })
+        }))
+    }
+
+    /// What happened between `before` and `self`.
+    pub fn since(&self, before: &Self) -> Self {
+        Self {
+            reads: self.reads.saturating_sub(before.reads),
+            read_merges: self.read_merges.saturating_sub(before.read_merges),
+            read_sectors: self.read_sectors.saturating_sub(before.read_sectors),
+            read_ms: self.read_ms.saturating_sub(before.read_ms),
+            writes: self.writes.saturating_sub(before.writes),
+            write_merges: self.write_merges.saturating_sub(before.write_merges),
+            write_sectors: self.write_sectors.saturating_sub(before.write_sectors),
+            write_ms: self.write_ms.saturating_sub(before.write_ms),
+            in_flight: self.in_flight,
+            io_ms: self.io_ms.saturating_sub(before.io_ms),
+            queue_ms: self.queue_ms.saturating_sub(before.queue_ms),
+        }
+    }
+
+    pub fn read_bytes(&self) -> u64 {
+        self.read_sectors * SECTOR
+    }
+
+    pub fn write_bytes(&self) -> u64 {
+        self.write_sectors * SECTOR
+    }
+}
+
+impl fmt::Display for DiskStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reads={} ({} merged, {} bytes, {}ms) writes={} ({} merged, {} bytes, {}ms) \
+             busy={}ms in_queue={}ms",
+            self.reads,
+            self.read_merges,
+            self.read_bytes(),
+            self.read_ms,
+            self.writes,
+            self.write_merges,
+            self.write_bytes(),
+            self.write_ms,
+            self.io_ms,
+            self.queue_ms,
+        )
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{device::Device, ring};
+use anyhow::Result;
+use humansize::{SizeFormatter, BINARY};
+use io_uring::IoUring;
+use std::{fmt, fs, io::ErrorKind, os::unix::fs::OpenOptionsExt, path::Path};
+
+/// Registered buffers for a handful of in-flight 1 MiB blocks.
+const RECOMMENDED_MEMLOCK: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Ok,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug)]
+struct Finding {
+    level: Level,
+    check: &'static str,
+    message: String,
+    hint: Option<String>,
+}
+
+impl Finding {
+    fn new(level: Level, check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            check,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            Level::Ok => "ok",
+            Level::Warn => "warn",
+            Level::Fail => "FAIL",
+        };
+        write!(f, "[{level:>4}] {}: {}", self.check, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       -> {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the environment for problems that would make a benchmark fail or
+/// produce misleading numbers. `file` is the intended benchmark target.
+pub fn run(file: Option<&str>) -> Result<()> {
+    let mut findings = vec![check_io_uring(), check_memlock()];
+    if let Some(file) = file {
+        findings.push(check_o_direct(Path::new(file)));
+        findings.push(check_device(file));
+    }
+    findings.push(check_drop_caches());
+
+    for finding in &findings {
+        println!("{finding}");
+    }
+
+    let failed = findings.iter().filter(|f| f.level == Level::Fail).count();
+    let warned = findings.iter().filter(|f| f.level == Level::Warn).count();
+    println!(
+        "{} checks, {failed} failed, {warned} warnings",
+        findings.len()
+    );
+
+    Ok(())
+}
+
+fn check_io_uring() -> Finding {
+    const CHECK: &str = "io_uring";
+
+    let disabled = fs::read_to_string("/proc/sys/kernel/io_uring_disabled")
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok());
+    let seccomp = fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Seccomp:"))
+                .and_then(|s| s.trim().parse::<u32>().ok())
+        })
+        .unwrap_or(0);
+
+    match IoUring::new(2) {
+        Ok(_) => Finding::new(Level::Ok, CHECK, "rings can be created"),
+        Err(err) if err.raw_os_error() == Some(libc::ENOSYS) => {
+            Finding::new(Level::Fail, CHECK, "not supported by this kernel")
+                .hint("io_uring requires Linux 5.1+, use the std or seq strategies")
+        }
+        Err(err) if disabled.is_some_and(|disabled| disabled > 0) => Finding::new(
+            Level::Fail,
+            CHECK,
+            format!("disabled by sysctl kernel.io_uring_disabled ({err})"),
+        )
+        .hint("run `sysctl kernel.io_uring_disabled=0` as root"),
+        Err(err) if err.raw_os_error() == Some(libc::EPERM) && seccomp > 0 => Finding::new(
+            Level::Fail,
+            CHECK,
+            format!("blocked by a seccomp filter ({err})"),
+        )
+        .hint(
+            "container runtimes block io_uring by default, \
+             e.g. run docker with `--security-opt seccomp=unconfined`",
+        ),
+        Err(err) => Finding::new(
+            Level::Fail,
+            CHECK,
+            format!("failed to create a ring: {err}"),
+        ),
+    }
+}
+
+fn check_memlock() -> Finding {
+    const CHECK: &str = "memlock";
+
+    let (soft, hard) = match ring::memlock_limit() {
+        Ok(limits) => limits,
+        Err(err) => return Finding::new(Level::Warn, CHECK, format!("{err:#}")),
+    };
+    let format = |value: Option<u64>| match value {
+        Some(value) => SizeFormatter::new(value, BINARY).to_string(),
+        None => "unlimited".to_string(),
+    };
+    let message = format!(
+        "RLIMIT_MEMLOCK is {} (hard limit {})",
+        format(soft),
+        format(hard)
+    );
+
+    if hard.is_none_or(|hard| hard >= RECOMMENDED_MEMLOCK) {
+        Finding::new(Level::Ok, CHECK, message)
+    } else {
+        Finding::new(Level::Warn, CHECK, message).hint(format!(
+            "registering more than {} of buffers will fail, \
+             raise the `memlock` setting in /etc/security/limits.conf",
+            format(hard),
+        ))
+    }
+}
+
+fn check_o_direct(path: &Path) -> Finding {
+    const CHECK: &str = "O_DIRECT";
+
+    // Probe with a scratch file next to the target if it does not exist yet.
+    let (probe, scratch) = if path.exists() {
+        (path.to_path_buf(), false)
+    } else {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        (dir.join(".raio-doctor-probe"), true)
+    };
+
+    let res = fs::OpenOptions::new()
+        .read(true)
+        .write(scratch)
+        .create(scratch)
+        .custom_flags(libc::O_DIRECT)
+        .open(&probe);
+    if scratch {
+        let _ = fs::remove_file(&probe);
+    }
+
+    match res {
+        Ok(_) => Finding::new(Level::Ok, CHECK, format!("supported on {}", path.display())),
+        Err(err) if err.raw_os_error() == Some(libc::EINVAL) => Finding::new(
+            Level::Warn,
+            CHECK,
+            format!("not supported by the filesystem of {}", path.display()),
+        )
+        .hint("results will include page cache effects, use a target on a disk-backed filesystem"),
+        Err(err) => Finding::new(
+            Level::Warn,
+            CHECK,
+            format!("failed to probe {}: {err}", probe.display()),
+        ),
+    }
+}
+
+fn check_device(file: &str) -> Finding {
+    const CHECK: &str = "device";
+
+    match Device::for_path(file) {
+        Ok(Some(device)) => Finding::new(Level::Ok, CHECK, device.attributes().to_string()),
+        Ok(None) => Finding::new(
+            Level::Warn,
+            CHECK,
+            format!("{file} is not backed by a block device"),
+        )
+        .hint("tmpfs and overlay filesystems measure memory rather than storage performance"),
+        Err(err) => Finding::new(Level::Warn, CHECK, format!("{err:#}")),
+    }
+}
+
+fn check_drop_caches() -> Finding {
+    const CHECK: &str = "drop_caches";
+
+    // Opening does not drop anything, only writing to the file does.
+    match fs::OpenOptions::new()
+        .write(true)
+        .open("/proc/sys/vm/drop_caches")
+    {
+        Ok(_) => Finding::new(Level::Ok, CHECK, "page cache can be dropped"),
+        Err(err) => {
+            let hint = match err.kind() {
+                ErrorKind::PermissionDenied => "run as root to drop the page cache between runs",
+                _ => "/proc/sys is read-only, e.g. inside a container",
+            };
+            Finding::new(
+                Level::Warn,
+                CHECK,
+                format!("page cache cannot be dropped: {err}"),
+            )
+            .hint(format!("{hint}, or use O_DIRECT for cold-cache reads"))
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! `--dry-run`: the operations a run would issue, printed instead of issued,
+//! as a last look at the target and range before a destructive run.
+
+use crate::{
+    offsets::{self, Mode, Offsets},
+    validate::{Space, Target},
+    worker, Job,
+};
+use anyhow::Result;
+use humansize::{SizeFormatter, BINARY};
+use std::{ops::Range, path::Path};
+
+/// Offsets listed per worker.
+const PREVIEW: u64 = 4;
+
+/// Prints the target, blocks, offsets and bytes of the run of `job`,
+/// starting at block `blocks.start` unless it runs on workers.
+pub fn print(job: &Job, write: bool, mode: Mode, target: Target, blocks: Range<u64>) -> Result<()> {
+    let Job {
+        file,
+        block_size,
+        count,
+        strategy,
+        jobs,
+        ..
+    } = job;
+    let size = |bytes: u64| SizeFormatter::new(bytes, BINARY);
+    let op = if write { "write" } else { "read" };
+    let end = match &mode {
+        Mode::List { offsets } => offsets.iter().max().map_or(0, |max| max + block_size),
+        _ => count * block_size,
+    };
+
+    println!("dry run of a {op}, no I/O is issued");
+    match target {
+        Target::Missing => println!("target: {file}, does not exist yet"),
+        Target::File(len) => println!("target: {file}, regular file of {}", size(len)),
+        Target::BlockDevice(len) => println!("target: {file}, block device of {}", size(len)),
+        Target::Other => println!("target: {file}"),
+    }
+    println!("strategy {strategy}: {}", strategy.describe(write));
+    let ops = blocks.end - blocks.start;
+    println!(
+        "operations: {ops} {op}s of {} ({} in total)",
+        size(*block_size),
+        size(ops * block_size),
+    );
+    match &mode {
+        Mode::Sequential => println!("offsets: sequential"),
+        Mode::List { .. } => println!(
+            "offsets: listed in {}",
+            job.offsets.as_deref().unwrap_or(Path::new("")).display()
+        ),
+        Mode::Random { seed, align } => println!(
+            "offsets: random with seed {seed}, {} slots {align} bytes apart",
+            offsets::slots(*block_size, end, *align),
+        ),
+        Mode::RandomMap { seed, align } => println!(
+            "offsets: random without repeats with seed {seed}, {} slots {align} bytes apart",
+            offsets::slots(*block_size, end, *align),
+        ),
+    }
+    if *jobs > 1 {
+        for worker in 0..*jobs {
+            let (blocks, region) = worker::share(job, worker);
+            let offsets = Offsets::new(*block_size, mode.clone(), blocks.start, region.clone());
+            // Listed offsets are absolute, whatever the share of the worker.
+            let region = listed(&mode, &blocks, *block_size).unwrap_or(region);
+            println!(
+                "worker {worker}: blocks {blocks:?} within {region:?}, {}",
+                preview(&offsets, &blocks),
+            );
+        }
+    } else {
+        let offsets = Offsets::new(*block_size, mode.clone(), 0, 0..end);
+        let region = match mode {
+            Mode::Sequential => blocks.start * block_size..end,
+            Mode::List { .. } => listed(&mode, &blocks, *block_size).unwrap_or(0..end),
+            _ => 0..end,
+        };
+        println!(
+            "blocks {blocks:?} within {region:?}, {}",
+            preview(&offsets, &blocks)
+        );
+    }
+
+    let len = match target {
+        Target::Missing => Some(0),
+        Target::File(len) => Some(len),
+        Target::BlockDevice(_) | Target::Other => None,
+    };
+    if let Some(len) = len.filter(|_| write) {
+        match end.checked_sub(len).filter(|&growth| growth > 0) {
+            Some(growth) => println!(
+                "file growth: {}{}, from {} to {}",
+                if mode == Mode::Sequential {
+                    ""
+                } else {
+                    "up to "
+                },
+                size(growth),
+                size(len),
+                size(end),
+            ),
+            None => println!("file growth: none, stays at {}", size(len)),
+        }
+        let space = Space::of(file)?;
+        println!(
+            "free space: {} of {}",
+            size(space.available),
+            size(space.size)
+        );
+    }
+    Ok(())
+}
+
+/// The bytes from the lowest to the end of the highest offset listed for
+/// `blocks`, `None` unless `mode` lists them.
+fn listed(mode: &Mode, blocks: &Range<u64>, block_size: u64) -> Option<Range<u64>> {
+    let Mode::List { offsets } = mode else {
+        return None;
+    };
+    let listed = &offsets[blocks.start as usize..blocks.end as usize];
+    let start = *listed.iter().min()?;
+    let end = *listed.iter().max()?;
+    Some(start..end + block_size)
+}
+
+/// The first offsets of `blocks`.
+fn preview(offsets: &Offsets, blocks: &Range<u64>) -> String {
+    let mut preview = (blocks.start..blocks.end.min(blocks.start + PREVIEW))
+        .map(|i| offsets.get(i).to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if blocks.end - blocks.start > PREVIEW {
+        preview.push_str(", ...");
+    }
+    format!("first offsets {preview}")
+}
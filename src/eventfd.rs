@@ -0,0 +1,56 @@
+//! Completion notification through an eventfd, the way event loops integrate
+//! an io_uring: the loop waits on the eventfd with the rest of its I/O
+//! instead of blocking in `io_uring_enter`.
+
+use crate::usage;
+use anyhow::{Context, Result};
+use io_uring::IoUring;
+use monoio::fs::File;
+use std::{fs, io, os::unix::io::FromRawFd};
+
+#[derive(Debug)]
+pub struct EventFd {
+    file: File,
+    buf: Option<Vec<u8>>,
+}
+
+impl EventFd {
+    /// Creates an eventfd and registers it with `ring`, so that the kernel
+    /// signals it for every completion.
+    pub fn register(ring: &IoUring) -> Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("failed to create eventfd");
+        }
+        let file = unsafe { fs::File::from_raw_fd(fd) };
+        ring.submitter()
+            .register_eventfd(fd)
+            .context("failed to register eventfd")?;
+        Ok(Self {
+            file: File::from_std(file).context("failed to add eventfd to the runtime")?,
+            buf: Some(vec![0; 8]),
+        })
+    }
+}
+
+/// Submits the queued entries and waits until at least one completion is
+/// ready, either in `io_uring_enter` or on `eventfd` through the runtime.
+pub async fn wait(ring: &mut IoUring, eventfd: Option<&mut EventFd>) -> Result<()> {
+    let Some(eventfd) = eventfd else {
+        ring.submit_and_wait(1)?;
+        usage::entered();
+        return Ok(());
+    };
+
+    ring.submit()?;
+    usage::entered();
+    // The counter may still be set by completions that were already reaped,
+    // so a wakeup does not guarantee a new completion.
+    while ring.completion().is_empty() {
+        let buf = eventfd.buf.take().expect("eventfd buffer is not in use");
+        let (res, buf) = eventfd.file.read_at(buf, 0).await;
+        eventfd.buf = Some(buf);
+        res.context("failed to read eventfd")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! JSON-lines event log for external monitoring of long runs.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static SINK: OnceLock<Mutex<BufWriter<fs::File>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Setup,
+    Run,
+    Report,
+    Done,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Start {
+        op: &'static str,
+        file: String,
+        strategy: String,
+        block_size: u64,
+        count: u64,
+    },
+    Phase {
+        phase: Phase,
+    },
+    Progress {
+        bytes: u64,
+        total: u64,
+        bytes_per_sec: f64,
+    },
+    /// No operation completed for `seconds`, reported when one completes again.
+    Stall {
+        bytes: u64,
+        seconds: f64,
+    },
+    Throttled {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+    Finished {
+        bytes: u64,
+        ops: u64,
+        seconds: f64,
+        bytes_per_sec: f64,
+    },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    ts: f64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+pub fn init(path: &Path) -> Result<()> {
+    let file = fs::File::create(path)
+        .with_context(|| format!("failed to create events file {}", path.display()))?;
+    SINK.set(Mutex::new(BufWriter::new(file)))
+        .ok()
+        .context("events file already initialized")
+}
+
+pub fn enabled() -> bool {
+    SINK.get().is_some()
+}
+
+/// Appends an event. Every line is flushed so the file can be followed while
+/// the benchmark runs. Write errors are ignored, the event log must never
+/// fail a run.
+pub fn emit(event: Event) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let mut sink = sink.lock().unwrap_or_else(|err| err.into_inner());
+    let _ = serde_json::to_writer(&mut *sink, &Record { ts, event: &event });
+    let _ = sink.write_all(b"\n");
+    let _ = sink.flush();
+}
@@ -0,0 +1,140 @@
+//! Extents of a written file from FIEMAP, since how a file system allocates
+//! differs between overwriting and truncating and rewriting, and a
+//! fragmented file reads slower afterwards.
+
+use anyhow::{bail, Context, Result};
+use humansize::{SizeFormatter, BINARY};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, os::unix::io::AsRawFd};
+
+/// `_IOWR('f', 11, struct fiemap)`.
+const FS_IOC_FIEMAP: libc::c_ulong = 0xc020_660b;
+/// Flushes delayed allocations first, so that their extents are placed.
+const FIEMAP_FLAG_SYNC: u32 = 0x1;
+const FIEMAP_EXTENT_LAST: u32 = 0x1;
+const FIEMAP_EXTENT_UNWRITTEN: u32 = 0x800;
+/// Extents asked for per ioctl.
+const BATCH: usize = 512;
+
+/// `struct fiemap` from `linux/fiemap.h`, followed by `BATCH` extents.
+#[repr(C)]
+struct Fiemap {
+    start: u64,
+    length: u64,
+    flags: u32,
+    mapped_extents: u32,
+    extent_count: u32,
+    reserved: u32,
+    extents: [FiemapExtent; BATCH],
+}
+
+/// `struct fiemap_extent`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FiemapExtent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    reserved64: [u64; 2],
+    flags: u32,
+    reserved: [u32; 3],
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Extents {
+    pub count: u64,
+    pub bytes: u64,
+    pub largest: u64,
+    /// Extents that do not start on disk where the one before ended, one
+    /// less than the number of contiguous runs a sequential read sees.
+    pub discontiguous: u64,
+    /// Allocated but never written, e.g. by `fallocate`.
+    pub unwritten: u64,
+}
+
+impl Extents {
+    /// The extents of the first `len` bytes of `path`.
+    pub fn of(path: &str, len: u64) -> Result<Self> {
+        let file = fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+        let mut extents = Self {
+            count: 0,
+            bytes: 0,
+            largest: 0,
+            discontiguous: 0,
+            unwritten: 0,
+        };
+        let mut map = Box::new(Fiemap {
+            start: 0,
+            length: 0,
+            flags: 0,
+            mapped_extents: 0,
+            extent_count: 0,
+            reserved: 0,
+            extents: [FiemapExtent {
+                logical: 0,
+                physical: 0,
+                length: 0,
+                reserved64: [0; 2],
+                flags: 0,
+                reserved: [0; 3],
+            }; BATCH],
+        });
+        let mut next_physical = None;
+        let mut pos = 0;
+        while pos < len {
+            map.start = pos;
+            map.length = len - pos;
+            map.flags = FIEMAP_FLAG_SYNC;
+            map.mapped_extents = 0;
+            map.extent_count = BATCH as u32;
+            if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP, &mut *map) } < 0 {
+                let err = io::Error::last_os_error();
+                if matches!(err.raw_os_error(), Some(libc::EOPNOTSUPP | libc::ENOTTY)) {
+                    bail!("the file system of {path} does not support FIEMAP");
+                }
+                return Err(err).with_context(|| format!("FIEMAP of {path} failed"));
+            }
+            let mapped = &map.extents[..map.mapped_extents as usize];
+            let Some(last) = mapped.last() else {
+                break;
+            };
+            for extent in mapped {
+                extents.count += 1;
+                extents.bytes += extent.length;
+                extents.largest = extents.largest.max(extent.length);
+                if next_physical.is_some_and(|next| next != extent.physical) {
+                    extents.discontiguous += 1;
+                }
+                if extent.flags & FIEMAP_EXTENT_UNWRITTEN != 0 {
+                    extents.unwritten += 1;
+                }
+                next_physical = Some(extent.physical + extent.length);
+            }
+            if last.flags & FIEMAP_EXTENT_LAST != 0 {
+                break;
+            }
+            pos = last.logical + last.length;
+        }
+        Ok(extents)
+    }
+}
+
+impl fmt::Display for Extents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let size = |bytes: u64| SizeFormatter::new(bytes, BINARY);
+        write!(f, "{} extents over {}", self.count, size(self.bytes))?;
+        if let Some(mean) = self.bytes.checked_div(self.count) {
+            write!(
+                f,
+                " (largest {}, mean {}), {} physically discontiguous",
+                size(self.largest),
+                size(mean),
+                self.discontiguous,
+            )?;
+        }
+        if self.unwritten > 0 {
+            write!(f, ", {} unwritten", self.unwritten)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+//! Throughput of the test data generator and checker by themselves, to rule
+//! them out as the bottleneck on fast devices with small blocks.
+
+use crate::{latency::Short, make_block, make_block_mem_aligned, mem_aligned_free, verify_block};
+use anyhow::{bail, Result};
+use humansize::{ISizeFormatter, BINARY};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+/// Blocks generated between two clock reads.
+const BATCH: u64 = 64;
+
+/// Runs each generator on `block_size` blocks for `duration` and prints a
+/// table of how many blocks per second it produces.
+pub fn run(block_size: u64, duration: Duration) -> Result<()> {
+    println!(
+        "{:<22} {:>12} {:>14} {:>10}  used by",
+        "generator", "blocks/s", "speed", "per block"
+    );
+    bench(
+        "make_block",
+        "std, seq, async, async2",
+        block_size,
+        duration,
+        |idx| {
+            black_box(make_block(block_size, idx));
+            Ok(())
+        },
+    )?;
+    bench(
+        "make_block_mem_aligned",
+        "io_uring strategies",
+        block_size,
+        duration,
+        |idx| {
+            let buf = make_block_mem_aligned(block_size, idx)?;
+            mem_aligned_free(black_box(buf), block_size as usize, 4096);
+            Ok(())
+        },
+    )?;
+    let block = make_block(block_size, 0);
+    bench("verify_block", "--verify", block_size, duration, |_| {
+        if !verify_block(black_box(&block), 0) {
+            bail!("verify_block rejected a generated block");
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+fn bench(
+    name: &str,
+    used_by: &str,
+    block_size: u64,
+    duration: Duration,
+    mut generate: impl FnMut(u64) -> Result<()>,
+) -> Result<()> {
+    let mut blocks = 0;
+    let start = Instant::now();
+    let elapsed = loop {
+        for _ in 0..BATCH {
+            generate(blocks * block_size / 64)?;
+            blocks += 1;
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= duration {
+            break elapsed;
+        }
+    };
+
+    let secs = elapsed.as_secs_f64();
+    println!(
+        "{name:<22} {:>12.0} {:>14} {:>10}  {used_by}",
+        blocks as f64 / secs,
+        format!(
+            "{}/s",
+            ISizeFormatter::new((blocks * block_size) as f64 / secs, BINARY)
+        ),
+        Short(Duration::from_secs_f64(secs / blocks as f64)).to_string(),
+    );
+    Ok(())
+}
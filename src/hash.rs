@@ -0,0 +1,185 @@
+//! Content hashes of files, so that the data produced by two runs can be
+//! compared without keeping both files around, and `raio hash`, which
+//! computes them with each read strategy as a read-plus-CPU workload.
+
+use crate::{
+    offsets::{Mode, Offsets},
+    progress::Progress,
+    read_file, validate,
+    worker::StartGate,
+    Strategy,
+};
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use humansize::{ISizeFormatter, BINARY};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{Read, Seek, SeekFrom},
+    os::unix::fs::FileExt,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use xxhash_rust::xxh3::Xxh3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Sha256,
+    /// 128-bit XXH3, much faster but not cryptographic
+    Xxh3,
+}
+
+impl HashAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Xxh3 => "xxh3",
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A running hash of data fed in order.
+enum Hasher {
+    Sha256(Sha256),
+    Xxh3(Box<Xxh3>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Xxh3 => Self::Xxh3(Box::new(Xxh3::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(sha256) => sha256.update(data),
+            Self::Xxh3(xxh3) => xxh3.update(data),
+        }
+    }
+
+    /// The digest as `<algorithm>:<hex>`.
+    fn finish(self) -> String {
+        let (algorithm, digest) = match self {
+            Self::Sha256(sha256) => (HashAlgorithm::Sha256, sha256.finalize().to_vec()),
+            Self::Xxh3(xxh3) => (HashAlgorithm::Xxh3, xxh3.digest128().to_be_bytes().to_vec()),
+        };
+        let hex = digest
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        format!("{algorithm}:{hex}")
+    }
+}
+
+/// Hashes the first `len` bytes of `path` and returns the digest as
+/// `<algorithm>:<hex>`.
+pub fn file(path: &str, len: u64, algorithm: HashAlgorithm) -> Result<String> {
+    let file = fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+    let mut reader = file.take(len);
+    let mut buf = vec![0; 1 << 20];
+    let mut hasher = Hasher::new(algorithm);
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .with_context(|| format!("failed to read {path}"))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Hashes `path` once with each of `strategies` and prints the digest, the
+/// throughput and the share of the run spent hashing for each of them.
+pub async fn run(
+    path: &str,
+    block_size: u64,
+    algorithm: HashAlgorithm,
+    strategies: &[Strategy],
+) -> Result<()> {
+    validate::block_size(block_size)?;
+    let mut file = fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+    // `metadata().len()` is 0 for block devices.
+    let len = file
+        .seek(SeekFrom::End(0))
+        .with_context(|| format!("failed to get the size of {path}"))?;
+    let count = len / block_size;
+    info!("hashing {len} bytes of {path} with {algorithm} in blocks of {block_size} bytes");
+
+    let mut digests = Vec::with_capacity(strategies.len());
+    for &strategy in strategies {
+        let mut hasher = Hasher::new(algorithm);
+        let mut hash_time = Duration::ZERO;
+        // Some strategies complete blocks out of order, which are held back
+        // until the blocks before them are hashed.
+        let mut next = 0;
+        let mut pending = BTreeMap::new();
+        let mut feed = |pos: u64, data: &[u8]| {
+            if pos != next {
+                pending.insert(pos, data.to_vec());
+                return true;
+            }
+            let started = Instant::now();
+            hasher.update(data);
+            next += block_size;
+            while let Some(data) = pending.remove(&next) {
+                hasher.update(&data);
+                next += block_size;
+            }
+            hash_time += started.elapsed();
+            true
+        };
+
+        let offsets = Offsets::new(block_size, Mode::Sequential, 0, 0..count * block_size);
+        let mut progress = Progress::new(count * block_size);
+        let res = read_file(
+            path,
+            0,
+            &offsets,
+            0..count,
+            strategy,
+            None,
+            None,
+            Some(&mut feed),
+            &mut progress,
+            &mut StartGate::none(),
+        )
+        .await;
+        progress.finish(res.is_ok());
+        let stats = res.with_context(|| format!("failed to read {path} with {strategy}"))?;
+
+        // The bytes after the last full block are not timed.
+        let mut tail = vec![0; (len - count * block_size) as usize];
+        file.read_exact_at(&mut tail, count * block_size)
+            .with_context(|| format!("failed to read {path}"))?;
+        hasher.update(&tail);
+        let digest = hasher.finish();
+
+        let secs = stats.elapsed.as_secs_f64();
+        println!(
+            "{strategy}: {digest} in {secs:.6} seconds @ {}/s, hashing {:.1}% of the run",
+            ISizeFormatter::new(stats.bytes_per_sec(), BINARY),
+            hash_time.as_secs_f64() / secs * 100.0,
+        );
+        digests.push((strategy, digest));
+    }
+
+    let (first, digest) = &digests[0];
+    if let Some((strategy, other)) = digests.iter().find(|(_, other)| other != digest) {
+        warn!("{first} read {digest} but {strategy} read {other}");
+        bail!("strategies read different data");
+    }
+    Ok(())
+}
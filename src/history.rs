@@ -0,0 +1,216 @@
+//! A local history of every run, appended to one JSON line per run, for
+//! following a machine's storage performance over months without keeping
+//! track of results files.
+
+use crate::{device::DeviceAttributes, latency::Short, results::Results, Strategy};
+use anyhow::{bail, Context, Result};
+use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, warn};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub host: String,
+    pub kernel: String,
+    #[serde(default)]
+    pub device: Option<DeviceAttributes>,
+    pub results: Results,
+}
+
+/// Which entries `raio history` shows.
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub op: Option<String>,
+    /// Part of the target path.
+    pub file: Option<String>,
+    pub strategy: Option<Strategy>,
+    pub host: Option<String>,
+    pub since: Option<Duration>,
+}
+
+impl Filter {
+    fn matches(&self, entry: &Entry, now: u64) -> bool {
+        let results = &entry.results;
+        self.op.as_ref().is_none_or(|op| *op == results.op)
+            && self
+                .file
+                .as_ref()
+                .is_none_or(|file| results.job.file.contains(file.as_str()))
+            && self
+                .strategy
+                .is_none_or(|strategy| strategy == results.job.strategy)
+            && self.host.as_ref().is_none_or(|host| *host == entry.host)
+            && self
+                .since
+                .is_none_or(|since| entry.time + since.as_secs() >= now)
+    }
+}
+
+/// `$RAIO_HISTORY`, or `history.jsonl` in the XDG data directory.
+pub fn path() -> Result<PathBuf> {
+    if let Some(path) = env::var_os("RAIO_HISTORY") {
+        return Ok(path.into());
+    }
+    let data = match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local/share"),
+            None => bail!("neither $RAIO_HISTORY, $XDG_DATA_HOME nor $HOME is set"),
+        },
+    };
+    Ok(data.join("raio/history.jsonl"))
+}
+
+/// Appends `results` with the host, kernel and `device` of the run. Each
+/// entry is a single write to a file opened for appending, so concurrent
+/// runs do not interleave their lines.
+pub fn record(results: &Results, device: Option<&DeviceAttributes>) -> Result<()> {
+    let path = path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let entry = Entry {
+        time: now(),
+        host: proc_value("sys/kernel/hostname"),
+        kernel: proc_value("sys/kernel/osrelease"),
+        device: device.cloned(),
+        results: results.clone(),
+    };
+    let mut line = serde_json::to_vec(&entry).context("failed to serialize the entry")?;
+    line.push(b'\n');
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&line))
+        .with_context(|| format!("failed to append to {}", path.display()))?;
+    debug!("recorded the run in {}", path.display());
+    Ok(())
+}
+
+/// Prints the entries matching `filter`, the last `last` of them if given,
+/// as a table or as the JSON lines they are stored as.
+pub fn run(filter: &Filter, last: Option<usize>, json: bool) -> Result<()> {
+    let path = path()?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            println!("no runs recorded yet in {}", path.display());
+            return Ok(());
+        }
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read {}", path.display()));
+        }
+    };
+
+    let now = now();
+    let mut lines = Vec::new();
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Entry>(line) {
+            Ok(entry) if filter.matches(&entry, now) => {
+                lines.push(line);
+                entries.push(entry);
+            }
+            Ok(_) => {}
+            // A run killed while appending leaves a partial line.
+            Err(err) => warn!("skipping {}:{}: {err}", path.display(), i + 1),
+        }
+    }
+    let skip = last.map_or(0, |last| entries.len().saturating_sub(last));
+
+    if json {
+        for line in &lines[skip..] {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+    let entries = &entries[skip..];
+    if entries.is_empty() {
+        println!("no recorded runs match");
+        return Ok(());
+    }
+    println!(
+        "{:<16} {:<12} {:<5} {:<16} {:>8} {:>14} {:>9}  target",
+        "time (UTC)", "host", "op", "strategy", "block", "throughput", "p99"
+    );
+    for entry in entries {
+        let results = &entry.results;
+        let p99 = match &results.latency {
+            Some(latency) => Short(Duration::from_secs_f64(latency.p99_us / 1e6)).to_string(),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<16} {:<12} {:<5} {:<16} {:>8} {:>14} {p99:>9}  {}",
+            utc(entry.time),
+            entry.host,
+            results.op,
+            results.job.strategy.as_str(),
+            SizeFormatter::new(results.job.block_size, BINARY).to_string(),
+            format!("{}/s", ISizeFormatter::new(results.bytes_per_sec, BINARY)),
+            results.job.file,
+        );
+    }
+    if entries.len() > 1 {
+        let mut rates = entries
+            .iter()
+            .map(|entry| entry.results.bytes_per_sec)
+            .collect::<Vec<_>>();
+        rates.sort_by(f64::total_cmp);
+        let rate = |rate: f64| format!("{}/s", ISizeFormatter::new(rate, BINARY));
+        println!(
+            "{} runs from {} to {}, throughput min {}, median {}, max {}",
+            entries.len(),
+            utc(entries[0].time),
+            utc(entries[entries.len() - 1].time),
+            rate(rates[0]),
+            rate(rates[rates.len() / 2]),
+            rate(rates[rates.len() - 1]),
+        );
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn proc_value(name: &str) -> String {
+    fs::read_to_string(format!("/proc/{name}"))
+        .map(|value| value.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// `time` as `YYYY-MM-DD HH:MM` in UTC.
+fn utc(time: u64) -> String {
+    // Howard Hinnant's `civil_from_days`.
+    let days = (time / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let secs = time % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
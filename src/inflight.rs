@@ -0,0 +1,112 @@
+//! How many operations were in flight over the course of a run, to check
+//! that a strategy sustains its queue depth instead of collapsing to one
+//! operation at a time, e.g. because of submission stalls.
+
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+/// Upper ends of the depth buckets, the last bucket holds everything above.
+const BUCKETS: [u64; 8] = [0, 1, 2, 4, 8, 16, 32, 64];
+
+/// The span of every operation, from its submission to its completion as
+/// seen by the strategy, in nanoseconds since the start of the run. 16 bytes
+/// per operation.
+#[derive(Debug, Clone, Default)]
+pub struct Spans {
+    spans: Vec<(u64, u64)>,
+}
+
+impl Spans {
+    pub fn add(&mut self, completed: Duration, latency: Duration) {
+        let end = completed.as_nanos() as u64;
+        self.spans
+            .push((end.saturating_sub(latency.as_nanos() as u64), end));
+    }
+
+    /// Adds `other`, which started at about the same time, e.g. another
+    /// worker.
+    pub fn merge(&mut self, other: &Self) {
+        self.spans.extend_from_slice(&other.spans);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Share of the time from the first submission to the last completion
+    /// spent at each depth.
+    pub fn summary(&self) -> InFlight {
+        let mut events = self
+            .spans
+            .iter()
+            .flat_map(|&(start, end)| [(start, 1), (end, -1)])
+            .collect::<Vec<(u64, i64)>>();
+        // Completions first, so back-to-back operations do not overlap.
+        events.sort_unstable();
+        let mut time = vec![0u64; BUCKETS.len() + 1];
+        let mut depth = 0i64;
+        let mut busy = 0u128;
+        for pair in events.windows(2) {
+            let ((at, change), (next, _)) = (pair[0], pair[1]);
+            depth += change;
+            let bucket = BUCKETS
+                .iter()
+                .position(|&upper| depth as u64 <= upper)
+                .unwrap_or(BUCKETS.len());
+            time[bucket] += next - at;
+            busy += depth as u128 * (next - at) as u128;
+        }
+        let total = time.iter().sum::<u64>().max(1);
+        InFlight {
+            mean: busy as f64 / total as f64,
+            max: peak(&events),
+            shares: time
+                .iter()
+                .enumerate()
+                .map(|(i, &time)| (bucket_label(i), time as f64 / total as f64 * 100.0))
+                .collect(),
+        }
+    }
+}
+
+fn peak(events: &[(u64, i64)]) -> u64 {
+    events
+        .iter()
+        .scan(0i64, |depth, &(_, change)| {
+            *depth += change;
+            Some(*depth)
+        })
+        .max()
+        .unwrap_or(0) as u64
+}
+
+fn bucket_label(i: usize) -> String {
+    match (i.checked_sub(1).map(|i| BUCKETS[i]), BUCKETS.get(i)) {
+        (None, Some(upper)) => upper.to_string(),
+        (Some(lower), Some(&upper)) if lower + 1 == upper => upper.to_string(),
+        (Some(lower), Some(upper)) => format!("{}-{upper}", lower + 1),
+        (Some(lower), None) => format!(">{lower}"),
+        (None, None) => unreachable!(),
+    }
+}
+
+/// Time-weighted distribution of the operations in flight.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InFlight {
+    pub mean: f64,
+    pub max: u64,
+    /// Share of the time in percent per bucket of depths, e.g. `3-4`.
+    pub shares: Vec<(String, f64)>,
+}
+
+impl fmt::Display for InFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mean={:.2} max={}", self.mean, self.max)?;
+        for (depth, share) in &self.shares {
+            if *share >= 0.05 {
+                write!(f, " {depth}={share:.1}%")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+//! Injected faults: artificial delays added to every strategy's operations,
+//! to see how a pipeline behaves on slow or erratic storage without having
+//! such a device, and error patterns for the `faulty` strategy, to exercise
+//! error handling.
+
+use crate::{offsets::splitmix64, parse};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InjectLatency {
+    /// The same delay for every operation.
+    Fixed(Duration),
+    /// A delay drawn uniformly from `min..=max` for every operation.
+    Random { min: Duration, max: Duration },
+    /// `delay` for `len` consecutive operations out of every `every`.
+    Burst {
+        delay: Duration,
+        every: u64,
+        len: u64,
+    },
+}
+
+impl InjectLatency {
+    /// The delay of the `i`th block, the same for the same block in every
+    /// run.
+    pub fn delay(self, i: u64) -> Duration {
+        match self {
+            Self::Fixed(delay) => delay,
+            Self::Random { min, max } => {
+                let span = (max - min).as_nanos() as u64;
+                let x = splitmix64(i);
+                min + Duration::from_nanos(((x as u128 * (span as u128 + 1)) >> 64) as u64)
+            }
+            Self::Burst { delay, every, len } if i % every < len => delay,
+            Self::Burst { .. } => Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Display for InjectLatency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(delay) => write!(f, "fixed {delay:?}"),
+            Self::Random { min, max } => write!(f, "random {min:?}..{max:?}"),
+            Self::Burst { delay, every, len } => {
+                write!(f, "{delay:?} for {len} of every {every} operations")
+            }
+        }
+    }
+}
+
+/// The injected delay of `block`, zero without `inject`.
+pub fn delay(inject: Option<InjectLatency>, block: u64) -> Duration {
+    inject.map_or(Duration::ZERO, |inject| inject.delay(block))
+}
+
+/// Completions a strategy holds back until their injected delay is due,
+/// while it keeps submitting with the slots that are free, the way it would
+/// on storage that takes that much longer.
+#[derive(Debug)]
+pub struct Held<T> {
+    inject: Option<InjectLatency>,
+    /// Completions without a delay, in the order they completed.
+    ready: VecDeque<T>,
+    delayed: BinaryHeap<Reverse<Due<T>>>,
+    /// Orders completions that are due at the same time by when they
+    /// completed.
+    seq: u64,
+}
+
+#[derive(Debug)]
+struct Due<T> {
+    at: Instant,
+    seq: u64,
+    item: T,
+}
+
+impl<T> PartialEq for Due<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl<T> Eq for Due<T> {}
+
+impl<T> PartialOrd for Due<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Due<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+impl<T> Held<T> {
+    pub fn new(inject: Option<InjectLatency>) -> Self {
+        Self {
+            inject,
+            ready: VecDeque::new(),
+            delayed: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+
+    /// Holds `item`, the completion of `block` at `now`, for its delay.
+    pub fn hold(&mut self, block: u64, now: Instant, item: T) {
+        let delay = delay(self.inject, block);
+        if delay.is_zero() {
+            self.ready.push_back(item);
+            return;
+        }
+        self.delayed.push(Reverse(Due {
+            at: now + delay,
+            seq: self.seq,
+            item,
+        }));
+        self.seq += 1;
+    }
+
+    /// The next completion whose delay is over at `now`.
+    pub fn release(&mut self, now: Instant) -> Option<T> {
+        if let Some(item) = self.ready.pop_front() {
+            return Some(item);
+        }
+        match self.delayed.peek() {
+            Some(Reverse(due)) if due.at <= now => self.delayed.pop().map(|Reverse(due)| due.item),
+            _ => None,
+        }
+    }
+
+    /// When the next delayed completion is due, `None` if none is held.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.delayed.peek().map(|Reverse(due)| due.at)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ready.len() + self.delayed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ready.is_empty() && self.delayed.is_empty()
+    }
+}
+
+/// Results the `faulty` strategy reports instead of doing I/O.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Faults {
+    /// Every `eio`th operation fails with EIO.
+    pub eio: Option<u64>,
+    /// Share of the operations that transfer only half of their block.
+    pub short: f64,
+}
+
+impl Faults {
+    /// The result of the `i`th block as an io_uring completion would report
+    /// it, the same for the same block in every run.
+    pub fn result(self, i: u64, block_size: u64) -> i32 {
+        if self.eio.is_some_and(|every| (i + 1).is_multiple_of(every)) {
+            return -libc::EIO;
+        }
+        // Another stream than the random delays, so that both can be mixed.
+        let x = splitmix64(i ^ 0x5555_5555_5555_5555);
+        if (x as f64) < self.short * u64::MAX as f64 {
+            return (block_size / 2) as i32;
+        }
+        block_size as i32
+    }
+}
+
+impl fmt::Display for Faults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.eio {
+            Some(every) => write!(f, "EIO every {every} operations")?,
+            None => f.write_str("no EIO")?,
+        }
+        write!(f, ", {:.2}% short transfers", self.short * 100.0)
+    }
+}
+
+/// Parses a comma-separated list of `eio:EVERY` and `short:RATIO`, e.g.
+/// `eio:100,short:0.01`.
+pub fn parse_faults(s: &str) -> Result<Faults, String> {
+    let mut faults = Faults {
+        eio: None,
+        short: 0.0,
+    };
+    for fault in s.split(',') {
+        match fault.split_once(':') {
+            Some(("eio", every)) => match every.parse::<u64>() {
+                Ok(every) if every > 0 => faults.eio = Some(every),
+                _ => return Err(format!("invalid EIO interval `{every}`, expected e.g. 100")),
+            },
+            Some(("short", ratio)) => faults.short = parse::ratio(ratio)?,
+            _ => {
+                return Err(format!(
+                    "invalid fault `{fault}`, expected eio:EVERY or short:RATIO"
+                ))
+            }
+        }
+    }
+    Ok(faults)
+}
+
+/// Parses `fixed:DELAY`, `random:MIN-MAX` or `burst:DELAY:EVERY:LEN`, e.g.
+/// `fixed:2ms`, `random:100us-5ms` or `burst:50ms:1000:10`.
+pub fn parse_latency(s: &str) -> Result<InjectLatency, String> {
+    let usage = || {
+        format!("invalid latency injection `{s}`, expected fixed:DELAY, random:MIN-MAX or burst:DELAY:EVERY:LEN")
+    };
+    let (kind, args) = s.split_once(':').ok_or_else(usage)?;
+    match kind {
+        "fixed" => Ok(InjectLatency::Fixed(parse::duration(args)?)),
+        "random" => {
+            let (min, max) = args.split_once('-').ok_or_else(usage)?;
+            let (min, max) = (parse::duration(min)?, parse::duration(max)?);
+            if min > max {
+                return Err(format!(
+                    "minimum delay {min:?} is above the maximum {max:?}"
+                ));
+            }
+            Ok(InjectLatency::Random { min, max })
+        }
+        "burst" => {
+            let mut args = args.split(':');
+            let (Some(delay), Some(every), Some(len), None) =
+                (args.next(), args.next(), args.next(), args.next())
+            else {
+                return Err(usage());
+            };
+            let delay = parse::duration(delay)?;
+            let every = every.parse::<u64>().map_err(|_| usage())?;
+            let len = len.parse::<u64>().map_err(|_| usage())?;
+            if len == 0 || len > every {
+                return Err(format!(
+                    "a burst needs 0 < LEN <= EVERY, got {len} of every {every}"
+                ));
+            }
+            Ok(InjectLatency::Burst { delay, every, len })
+        }
+        _ => Err(usage()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn held_completion_is_released_after_its_delay() {
+        let mut held = Held::new(Some(InjectLatency::Fixed(10 * MS)));
+        let now = Instant::now();
+        held.hold(0, now, "a");
+        held.hold(1, now + MS, "b");
+        assert_eq!(held.release(now), None);
+        assert_eq!(held.release(now + 9 * MS), None);
+        assert_eq!(held.next_due(), Some(now + 10 * MS));
+        assert_eq!(held.release(now + 10 * MS), Some("a"));
+        assert_eq!(held.release(now + 10 * MS), None);
+        assert_eq!(held.release(now + 11 * MS), Some("b"));
+        assert_eq!((held.len(), held.next_due()), (0, None));
+    }
+
+    #[test]
+    fn completions_without_a_delay_pass_the_held_ones() {
+        let burst = InjectLatency::Burst {
+            delay: 10 * MS,
+            every: 4,
+            len: 2,
+        };
+        let mut held = Held::new(Some(burst));
+        let now = Instant::now();
+        for block in 0..4 {
+            held.hold(block, now, block);
+        }
+        assert_eq!(held.len(), 4);
+        assert_eq!(held.release(now), Some(2));
+        assert_eq!(held.release(now), Some(3));
+        assert_eq!(held.release(now), None);
+        assert_eq!(held.release(now + 10 * MS), Some(0));
+        assert_eq!(held.release(now + 10 * MS), Some(1));
+    }
+
+    #[test]
+    fn nothing_is_held_without_injection() {
+        let mut held = Held::new(None);
+        let now = Instant::now();
+        held.hold(0, now, ());
+        assert_eq!(held.next_due(), None);
+        assert_eq!(held.release(now), Some(()));
+    }
+}
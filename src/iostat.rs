@@ -0,0 +1,158 @@
+//! Device utilization sampled from `/proc/diskstats` on a background thread
+//! while a benchmark runs, like `iostat -x` would show it.
+
+use crate::{device::Device, diskstats::DiskStats};
+use humansize::{ISizeFormatter, BINARY};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// One interval, with the same meaning as the columns of `iostat -x`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Sample {
+    /// End of the interval, in seconds since the start of the run.
+    pub seconds: f64,
+    pub reads_per_sec: f64,
+    pub writes_per_sec: f64,
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    /// Share of the interval the device was busy, in percent.
+    pub util: f64,
+    /// Average number of requests in the queue or on the device.
+    pub queue_depth: f64,
+    /// Average time from queueing a request to its completion, in
+    /// milliseconds, or 0 without requests.
+    pub await_ms: f64,
+}
+
+impl Sample {
+    fn new(delta: &DiskStats, interval: Duration, seconds: f64) -> Self {
+        let secs = interval.as_secs_f64();
+        let ms = secs * 1000.0;
+        let ios = delta.reads + delta.writes;
+        Self {
+            seconds,
+            reads_per_sec: delta.reads as f64 / secs,
+            writes_per_sec: delta.writes as f64 / secs,
+            read_bytes_per_sec: delta.read_bytes() as f64 / secs,
+            write_bytes_per_sec: delta.write_bytes() as f64 / secs,
+            util: (delta.io_ms as f64 / ms * 100.0).min(100.0),
+            queue_depth: delta.queue_ms as f64 / ms,
+            await_ms: if ios > 0 {
+                (delta.read_ms + delta.write_ms) as f64 / ios as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "r/s={:.0} w/s={:.0} read={}/s write={}/s util={:.1}% aqu-sz={:.2} await={:.2}ms",
+            self.reads_per_sec,
+            self.writes_per_sec,
+            ISizeFormatter::new(self.read_bytes_per_sec, BINARY),
+            ISizeFormatter::new(self.write_bytes_per_sec, BINARY),
+            self.util,
+            self.queue_depth,
+            self.await_ms,
+        )
+    }
+}
+
+/// Samples a device until [`Sampler::finish`], printing every sample to
+/// stderr.
+#[derive(Debug)]
+pub struct Sampler {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<Vec<Sample>>,
+}
+
+impl Sampler {
+    pub fn start(device: Device, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let start = Instant::now();
+            let mut samples = Vec::new();
+            let Some(mut last) = read(&device) else {
+                return samples;
+            };
+            let mut last_time = start;
+            loop {
+                let done = match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => true,
+                };
+                let now = Instant::now();
+                let Some(stats) = read(&device) else {
+                    break;
+                };
+                // The last interval is cut short by the end of the run.
+                if !done || now - last_time >= interval / 10 {
+                    let sample = Sample::new(
+                        &stats.since(&last),
+                        now - last_time,
+                        (now - start).as_secs_f64(),
+                    );
+                    eprintln!("[{:>8.2}s] {}: {sample}", sample.seconds, device.name);
+                    samples.push(sample);
+                }
+                if done {
+                    break;
+                }
+                last = stats;
+                last_time = now;
+            }
+            samples
+        });
+        Self { stop, thread }
+    }
+
+    pub fn finish(self) -> Vec<Sample> {
+        // The thread only exits on its own if the device disappeared.
+        let _ = self.stop.send(());
+        self.thread.join().expect("iostat thread panicked")
+    }
+}
+
+fn read(device: &Device) -> Option<DiskStats> {
+    match DiskStats::read(device) {
+        Ok(Some(stats)) => Some(stats),
+        Ok(None) => {
+            warn!(
+                "{} is not listed in /proc/diskstats, stopping iostat",
+                device.name
+            );
+            None
+        }
+        Err(err) => {
+            warn!("stopping iostat: {err:#}");
+            None
+        }
+    }
+}
+
+/// Mean and maximum of utilization, queue depth and await over `samples`.
+pub fn summary(samples: &[Sample]) -> String {
+    let stat = |value: fn(&Sample) -> f64| {
+        let mean = samples.iter().map(value).sum::<f64>() / samples.len() as f64;
+        let max = samples.iter().map(value).fold(0.0, f64::max);
+        (mean, max)
+    };
+    let (util, util_max) = stat(|s| s.util);
+    let (depth, depth_max) = stat(|s| s.queue_depth);
+    let (wait, wait_max) = stat(|s| s.await_ms);
+    format!(
+        "{} samples, util mean={util:.1}% max={util_max:.1}%, \
+         aqu-sz mean={depth:.2} max={depth_max:.2}, await mean={wait:.2}ms max={wait_max:.2}ms",
+        samples.len(),
+    )
+}
@@ -0,0 +1,256 @@
+//! Per-operation latency histograms.
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hdrhistogram::{
+    serialization::{Deserializer, Serializer, V2DeflateSerializer},
+    Histogram,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+/// Completion latencies in nanoseconds.
+#[derive(Debug, Clone)]
+pub struct Latency {
+    histogram: Histogram<u64>,
+    /// Subtracted from every recorded latency.
+    correction: Duration,
+}
+
+impl Latency {
+    /// A histogram that keeps `digits` significant decimal digits. Without a
+    /// `max` it grows to fit the largest latency, otherwise larger latencies
+    /// are recorded as `max`.
+    pub fn new(digits: u8, max: Option<Duration>) -> Result<Self> {
+        let histogram = match max {
+            Some(max) => Histogram::new_with_max((max.as_nanos() as u64).max(2), digits),
+            None => Histogram::new(digits),
+        };
+        Ok(Self {
+            histogram: histogram.context("invalid latency histogram settings")?,
+            correction: Duration::ZERO,
+        })
+    }
+
+    /// Subtracts `correction` from every latency recorded from now on, e.g.
+    /// the cost of measuring it.
+    pub fn with_correction(mut self, correction: Duration) -> Self {
+        self.correction = correction;
+        self
+    }
+
+    /// An empty histogram with the same precision, range and correction.
+    pub fn empty_like(&self) -> Self {
+        let mut latency = self.clone();
+        latency.histogram.reset();
+        latency
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = latency.saturating_sub(self.correction).as_nanos() as u64;
+        if self.histogram.is_auto_resize() {
+            // Never fails, the histogram grows to fit the value.
+            let _ = self.histogram.record(nanos);
+        } else {
+            self.histogram.saturating_record(nanos);
+        }
+    }
+
+    /// Bytes used by the histogram's counters.
+    pub fn memory(&self) -> usize {
+        self.histogram.distinct_values() * std::mem::size_of::<u64>()
+    }
+
+    /// The histogram in the compressed HdrHistogram V2 format, base64 encoded
+    /// as in HdrHistogram interval logs.
+    pub fn encode(&self) -> String {
+        let mut buf = Vec::new();
+        V2DeflateSerializer::new()
+            .serialize(&self.histogram, &mut buf)
+            .expect("histogram serializes into memory");
+        STANDARD.encode(buf)
+    }
+
+    /// Reads a histogram written by [`Latency::encode`].
+    pub fn decode(encoded: &str) -> Result<Self> {
+        let data = STANDARD
+            .decode(encoded)
+            .context("invalid base64 in latency histogram")?;
+        let histogram = Deserializer::new()
+            .deserialize(&mut data.as_slice())
+            .context("invalid latency histogram")?;
+        Ok(Self {
+            histogram,
+            correction: Duration::ZERO,
+        })
+    }
+
+    /// Operations per power-of-two bucket from 1us up to the maximum, keyed
+    /// by the upper end of the bucket.
+    pub fn buckets(&self) -> Vec<(Duration, u64)> {
+        self.histogram
+            .iter_log(1000, 2.0)
+            .map(|bucket| {
+                (
+                    Duration::from_nanos(bucket.value_iterated_to()),
+                    bucket.count_since_last_iteration(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.histogram.is_empty()
+    }
+
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        Duration::from_nanos(self.histogram.value_at_percentile(percentile))
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.histogram.max())
+    }
+
+    pub fn mean(&self) -> Duration {
+        Duration::from_nanos(self.histogram.mean() as u64)
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.histogram
+            .add(&other.histogram)
+            .expect("histogram ranges match");
+    }
+
+    pub fn reset(&mut self) {
+        self.histogram.reset();
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        let us = |latency: Duration| latency.as_secs_f64() * 1e6;
+        LatencySummary {
+            mean_us: us(self.mean()),
+            p50_us: us(self.percentile(50.0)),
+            p90_us: us(self.percentile(90.0)),
+            p99_us: us(self.percentile(99.0)),
+            p999_us: us(self.percentile(99.9)),
+            max_us: us(self.max()),
+            histogram: self.encode(),
+        }
+    }
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Self {
+            histogram: Histogram::new(3).expect("valid histogram precision"),
+            correction: Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mean={} p50={} p99={} p99.9={} max={}",
+            Short(self.mean()),
+            Short(self.percentile(50.0)),
+            Short(self.percentile(99.0)),
+            Short(self.percentile(99.9)),
+            Short(self.max()),
+        )
+    }
+}
+
+/// Percentiles of a [`Latency`] histogram as stored in results files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub mean_us: f64,
+    pub p50_us: f64,
+    pub p90_us: f64,
+    pub p99_us: f64,
+    pub p999_us: f64,
+    pub max_us: f64,
+    /// See [`Latency::encode`].
+    pub histogram: String,
+}
+
+/// Formats a latency with a unit that keeps it short, e.g. `85.3us`.
+pub struct Short(pub Duration);
+
+impl fmt::Display for Short {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs_f64();
+        if secs < 1e-6 {
+            write!(f, "{}ns", self.0.as_nanos())
+        } else if secs < 1e-3 {
+            write!(f, "{:.1}us", secs * 1e6)
+        } else if secs < 1.0 {
+            write!(f, "{:.2}ms", secs * 1e3)
+        } else {
+            write!(f, "{secs:.3}s")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const US: Duration = Duration::from_micros(1);
+
+    fn latency(values: impl IntoIterator<Item = u32>) -> Latency {
+        let mut latency = Latency::default();
+        for value in values {
+            latency.record(value * US);
+        }
+        latency
+    }
+
+    #[test]
+    fn percentiles_of_recorded_latencies() {
+        // Three significant digits resolve whole microseconds up to 1ms.
+        let latency = latency(1..=100);
+        assert_eq!(latency.percentile(50.0).as_micros(), 50);
+        assert_eq!(latency.percentile(99.0).as_micros(), 99);
+        assert_eq!(latency.max().as_micros(), 100);
+        assert_eq!(latency.mean().as_micros(), 50);
+    }
+
+    #[test]
+    fn merge_and_reset() {
+        let mut latency = latency([10, 20]);
+        latency.merge(&self::latency([30, 40]));
+        assert_eq!(latency.max().as_micros(), 40);
+        assert_eq!(latency.percentile(50.0).as_micros(), 20);
+        latency.reset();
+        assert!(latency.is_empty());
+    }
+
+    #[test]
+    fn short_picks_a_unit() {
+        let short = |latency| Short(latency).to_string();
+        assert_eq!(short(Duration::from_nanos(850)), "850ns");
+        assert_eq!(short(Duration::from_nanos(85_300)), "85.3us");
+        assert_eq!(short(Duration::from_micros(2_500)), "2.50ms");
+        assert_eq!(short(Duration::from_millis(1_250)), "1.250s");
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let latency = latency([5, 50, 500, 5000]);
+        let decoded = Latency::decode(&latency.encode()).unwrap();
+        assert_eq!(decoded.max(), latency.max());
+        assert_eq!(decoded.percentile(50.0), latency.percentile(50.0));
+        assert!(Latency::decode("not base64!").is_err());
+        assert!(Latency::decode("aGVsbG8=").is_err());
+    }
+
+    #[test]
+    fn hist_max_saturates() {
+        let mut latency = Latency::new(2, Some(Duration::from_millis(1))).unwrap();
+        latency.record(Duration::from_secs(1));
+        assert_eq!(latency.max().as_millis(), 1);
+        assert!(Latency::new(6, None).is_err());
+    }
+}
@@ -0,0 +1,35 @@
+use anyhow::{Context, Result};
+use std::{fs, io, path::Path};
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+
+/// Installs the global subscriber. Without an explicit `level`, `-v` enables
+/// info and `-vv` debug events. Events go to stderr, or to `file` through a
+/// background writer so that logging does not stall the benchmark thread.
+///
+/// The returned guard flushes the background writer when dropped.
+pub fn init(verbose: u8, level: Option<LevelFilter>, file: Option<&Path>) -> Result<WorkerGuard> {
+    let level = level.unwrap_or(match verbose {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        _ => LevelFilter::DEBUG,
+    });
+
+    let (writer, guard) = match file {
+        Some(file) => {
+            let file = fs::File::create(file)
+                .with_context(|| format!("failed to create log file {}", file.display()))?;
+            tracing_appender::non_blocking(file)
+        }
+        None => tracing_appender::non_blocking(io::stderr()),
+    };
+
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(writer)
+        .with_ansi(file.is_none())
+        .with_target(false)
+        .init();
+
+    Ok(guard)
+}
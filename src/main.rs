@@ -1,149 +1,1725 @@
-#![allow(unused)] // Remove this line to enable warnings.
+mod autobs;
+mod calibrate;
+mod cgroup;
+mod checkpoint;
+mod cmp;
+mod completions;
+mod compute;
+mod copy;
+mod copypath;
+mod device;
+mod diff;
+mod direct;
+mod diskstats;
+mod doctor;
+mod dryrun;
+mod eventfd;
+mod events;
+mod extents;
+mod genbench;
+mod hash;
+mod history;
+mod inflight;
+mod inject;
+mod iostat;
+mod latency;
+mod log;
+mod matrix;
+mod memlock;
+mod msgring;
+mod offsets;
+mod parse;
+mod pipeline;
+mod prio;
+mod progress;
+mod psi;
+mod rapl;
+mod recv;
+mod report;
+mod results;
+mod ring;
+mod scratch;
+mod selftest;
+mod serve;
+mod smart;
+mod soak;
+mod stream;
+mod suite;
+mod timeline;
+mod trim;
+mod usage;
+mod validate;
+mod watch;
+mod worker;
 
 use anyhow::{Context, Ok, Result};
+use calibrate::Overhead;
+use cgroup::IoLimits;
+use checkpoint::{Checkpoint, Checkpointer};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use compute::Compute;
+use device::{Device, IoScheduler};
+use direct::{Alignment, Fallback, Misaligned};
+use diskstats::DiskStats;
+use eventfd::EventFd;
+use events::{Event, Phase};
+use extents::Extents;
+use hash::HashAlgorithm;
 use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+use inflight::Spans;
+use inject::{Faults, Held, InjectLatency};
 use io_uring::{opcode, squeue::Flags, types, IoUring};
+use iostat::Sampler;
+use latency::{Latency, Short};
 use monoio::fs::{File, OpenOptions};
+use offsets::{Mode, Offsets};
+use prio::{CpuPriority, IoPriority};
+use progress::Progress;
+use psi::Pressures;
+use results::Results;
+use ring::RingCounters;
+use serde::{Deserialize, Serialize};
+use smart::{Health, Wear};
+use soak::Soak;
 use std::{
+    cell::Cell,
     collections::VecDeque,
-    default, fs,
-    io::{Read, Write},
-    os::unix::{fs::FileExt, io::AsRawFd},
+    fmt, fs,
+    future::Future,
+    io,
+    ops::Range,
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
     rc::Rc,
-    str::FromStr,
+    thread,
     time::{Duration, Instant},
 };
+use timeline::Timeline;
+use tracing::{debug, info, level_filters::LevelFilter, trace, warn, Level};
+use usage::{Efficiency, Usage};
+use worker::{PageFaults, StartGate};
 
-#[monoio::main]
+#[monoio::main(timer_enabled = true)]
 async fn main() -> Result<()> {
-    let cmd = Cmd::from_env().context("failed to parse args")?;
+    let cmd = Cmd::parse();
+    let _guard = log::init(cmd.verbose, cmd.log_level, cmd.log_file.as_deref())?;
+    if let Some(path) = &cmd.events {
+        events::init(path)?;
+    }
     cmd.run().await?;
 
     Ok(())
 }
 
-#[derive(Debug)]
+/// Benchmark file I/O strategies.
+#[derive(Debug, Parser)]
+#[command(version)]
 struct Cmd {
+    #[command(subcommand)]
     sub: SubCmd,
-    verbose: bool,
+
+    /// Print setup details and progress (-v) and every failed operation (-vv)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Log level (off, error, warn, info, debug, trace), overrides -v
+    #[arg(long, global = true)]
+    log_level: Option<LevelFilter>,
+
+    /// Write log events to this file instead of stderr
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Write run lifecycle events as JSON lines to this file
+    #[arg(long, global = true)]
+    events: Option<PathBuf>,
+
+    /// Switch the I/O scheduler of the target device for the run (requires root)
+    #[arg(long, global = true, value_enum)]
+    scheduler: Option<IoScheduler>,
+
+    /// I/O priority as class:level, e.g. `idle`, `be:4` or `rt:0`
+    #[arg(long, global = true)]
+    ioprio: Option<IoPriority>,
+
+    /// Niceness of the benchmark thread
+    #[arg(
+        long,
+        global = true,
+        allow_hyphen_values = true,
+        value_parser = clap::value_parser!(i32).range(-20..=19),
+        conflicts_with = "sched_fifo",
+    )]
+    nice: Option<i32>,
+
+    /// Run the benchmark thread with SCHED_FIFO at this priority
+    #[arg(long, global = true, value_parser = clap::value_parser!(i32).range(1..=99))]
+    sched_fifo: Option<i32>,
+
+    /// Lock the I/O buffers into memory, to keep page faults and swapping
+    /// out of the run. Buffers that monoio strategies allocate are not
+    /// locked
+    #[arg(long, global = true)]
+    mlock: bool,
+
+    /// Do not record runs in the history, see `raio history`
+    #[arg(long, global = true)]
+    no_history: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Subcommand)]
 enum SubCmd {
-    Write {
+    /// Benchmark writing blocks to a file
+    Write(Job),
+    /// Benchmark reading blocks from a file
+    Read {
+        #[command(flatten)]
+        job: Job,
+
+        /// Verify that blocks contain the pattern written by `raio write`
+        #[arg(long)]
+        verify: bool,
+    },
+    /// Run the same job against several targets, e.g. a file on each of
+    /// several disks, once per strategy and compare them in one table. `-f`
+    /// takes the targets separated by commas
+    Matrix {
+        #[arg(value_enum)]
+        op: matrix::Op,
+
+        /// Strategies to run against each target, separated by commas,
+        /// instead of --strategy
+        #[arg(long, value_delimiter = ',')]
+        strategies: Vec<Strategy>,
+
+        /// Verify that blocks contain the pattern written by `raio write`
+        #[arg(long)]
+        verify: bool,
+
+        #[command(flatten)]
+        job: Job,
+    },
+    /// Benchmark writes interleaved with discards of previously written blocks
+    #[command(name = "trim-write")]
+    TrimWrite {
+        /// Target file or block device
+        #[arg(short, long)]
         file: String,
+
+        /// Block size, with an optional suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "64k", value_parser = parse::size)]
+        block_size: u64,
+
+        /// Number of blocks to write
+        #[arg(short, long, default_value_t = 1)]
+        count: u64,
+
+        /// Discards per write, e.g. 0.5 discards the oldest written block
+        /// after every second write
+        #[arg(long, default_value = "0.5", value_parser = parse::ratio)]
+        trim_ratio: f64,
+
+        /// Operations in flight
+        #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u64).range(1..=4096))]
+        depth: u64,
+    },
+    /// Stream a file to another one through a processing stage
+    Pipeline {
+        src: String,
+        dst: String,
+
+        /// Block size, with an optional suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "128k", value_parser = parse::size)]
+        block_size: u64,
+
+        /// Buffers between the two files, each one either being read,
+        /// transformed or written
+        #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u64).range(1..=4096))]
+        depth: u64,
+
+        /// CPU work to do for each block between reading and writing it,
+        /// either a duration to spin for or `checksum`
+        #[arg(long, value_name = "DURATION|checksum", value_parser = compute::parse)]
+        transform: Option<Compute>,
+    },
+    /// Copy a file with reads and writes in flight at the same time, with
+    /// separate statistics for both sides
+    Copy {
+        src: String,
+        dst: String,
+
+        /// Block size, with an optional suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "1M", value_parser = parse::size)]
         block_size: u64,
+
+        /// Buffers between the two files, each one either being read or
+        /// written
+        #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u64).range(1..=4096))]
+        depth: u64,
+
+        /// How to read and write
+        #[arg(long, value_enum, default_value_t = copy::Engine::IoUring)]
+        engine: copy::Engine,
+    },
+    /// Benchmark IORING_OP_MSG_RING messages between rings on two threads
+    #[command(name = "msg-ring")]
+    MsgRing {
+        /// Round trips, each one a message to the other ring and its echo
+        #[arg(short, long, default_value_t = 100_000, value_parser = clap::value_parser!(u64).range(1..))]
         count: u64,
+
+        /// Messages on their way at once, 1 measures the wakeup latency
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=4096))]
+        depth: u64,
+    },
+    /// Benchmark openat, read and close through io_uring for every request,
+    /// the way a static file server serves small files
+    Serve {
+        /// Directory for the served files, which are removed afterwards
+        dir: PathBuf,
+
+        /// Files to serve round-robin
+        #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+        files: u64,
+
+        /// Size of each file, with an optional suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "16k", value_parser = parse::size)]
+        size: u64,
+
+        /// Requests to serve
+        #[arg(short, long, default_value_t = 100_000, value_parser = clap::value_parser!(u64).range(1..))]
+        count: u64,
+
+        /// Requests in flight at once
+        #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u64).range(1..=4096))]
+        depth: u64,
+
+        /// Submit each operation after the previous one completed, even if
+        /// direct descriptors would allow linking them
+        #[arg(long)]
+        unlinked: bool,
+    },
+    /// Benchmark receiving from many TCP connections over loopback with
+    /// one-shot recv against multishot recv with provided buffers
+    Recv {
+        /// Connections to receive from at once
+        #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u64).range(1..=4096))]
+        connections: u64,
+
+        /// Size of the messages and of the receive buffers, with an optional
+        /// suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "4k", value_parser = parse::size)]
+        size: u64,
+
+        /// Bytes to receive over all connections, with an optional suffix
+        /// (k, M, G)
+        #[arg(long, default_value = "1G", value_parser = parse::size)]
+        bytes: u64,
+
+        /// Provided buffers that the multishot receives of all connections
+        /// share, a power of two
+        #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u64).range(1..=32768))]
+        buffers: u64,
+    },
+    /// Copy a file with buffered I/O, O_DIRECT, copy_file_range and splice
+    /// and compare the time and CPU each one takes
+    #[command(name = "copy-paths")]
+    CopyPaths {
+        src: String,
+        dst: String,
+
+        /// Buffer size for the copies through userspace and splice
+        #[arg(short = 's', long, default_value = "1M", value_parser = parse::size)]
+        block_size: u64,
+    },
+    /// Compare two files block by block
+    Cmp {
+        a: String,
+        b: String,
+
+        /// Block size, with an optional suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "1M", value_parser = parse::size)]
+        block_size: u64,
+
+        /// I/O strategy used to read the first file
+        #[arg(long, value_enum, default_value_t = Strategy::IOUring8)]
         strategy: Strategy,
     },
-    Read {
+    /// Hash a file with each read strategy, for a digest and a read-plus-CPU
+    /// benchmark in one
+    Hash {
+        #[arg(short, long)]
         file: String,
+
+        #[arg(long, value_enum, default_value_t = HashAlgorithm::Xxh3)]
+        algo: HashAlgorithm,
+
+        /// Comma-separated I/O strategies to read the file with [default: all
+        /// but async]
+        #[arg(long, value_enum, value_delimiter = ',')]
+        strategy: Vec<Strategy>,
+
+        /// Block size, with an optional suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "1M", value_parser = parse::size)]
+        block_size: u64,
+    },
+    /// Attach a loop device backed by a sparse temp file as a disposable
+    /// target, run a command with it and detach it again
+    Scratch {
+        /// Size of the device, with an optional suffix (k, M, G)
+        #[arg(long, default_value = "1G", value_parser = parse::size)]
+        size: u64,
+
+        /// Directory for the backing file [default: the temp directory]
+        #[arg(long)]
+        dir: Option<PathBuf>,
+
+        /// Command to run, `{}` is replaced with the device and RAIO_SCRATCH
+        /// is set to it. Without a command, waits for Enter
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+    /// Repeat a run with the parameters and seed from its results file
+    Rerun {
+        /// Results file written with --output
+        results: PathBuf,
+
+        /// Write the results of the rerun to this file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Measure how fast the test data is generated and verified, without
+    /// any I/O
+    #[command(name = "gen-bench")]
+    GenBench {
+        /// Block size, with an optional suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "4k", value_parser = parse::size)]
         block_size: u64,
+
+        /// How long to run each generator
+        #[arg(long, default_value = "1s", value_parser = parse::duration)]
+        duration: Duration,
+    },
+    /// List the recorded runs, see `--no-history`. Runs are appended to
+    /// $RAIO_HISTORY or $XDG_DATA_HOME/raio/history.jsonl
+    History {
+        /// Only writes, reads or the mixed runs of `raio suite`
+        #[arg(long, value_parser = ["write", "read", "mixed"])]
+        op: Option<String>,
+
+        /// Only runs against targets whose path contains this
+        #[arg(short, long)]
+        file: Option<String>,
+
+        #[arg(long, value_enum)]
+        strategy: Option<Strategy>,
+
+        /// Only runs on this host
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Only runs within this long ago, e.g. 12h or 30d
+        #[arg(long, value_parser = parse::duration)]
+        since: Option<Duration>,
+
+        /// Only the most recent runs
+        #[arg(short = 'n', long)]
+        last: Option<usize>,
+
+        /// Print the entries as stored, one JSON object per line
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run a battery of preset workloads against a target and print one
+    /// table of their results. Writes to the target
+    Suite {
+        #[arg(short, long)]
+        file: String,
+
+        /// Bytes of the target to use, with an optional suffix (k, M, G)
+        #[arg(long, default_value = "1G", value_parser = parse::size)]
+        size: u64,
+
+        /// Operations of each random preset, at most as many as fit in
+        /// `--size`
+        #[arg(long, default_value_t = 50_000, value_parser = clap::value_parser!(u64).range(1..))]
+        ops: u64,
+
+        /// Comma-separated presets to run [default: all]
+        #[arg(long, value_enum, value_delimiter = ',')]
+        presets: Vec<suite::Preset>,
+
+        /// Write a self-contained HTML report of all presets to this file
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+    /// Compare the throughput, latency and parameters of two results files
+    Diff {
+        /// Results file of the baseline run
+        a: PathBuf,
+        /// Results file of the run to compare with the baseline
+        b: PathBuf,
+    },
+    /// Render results files, e.g. of a sweep, into one HTML report
+    Report {
+        /// Results files written with --output
+        #[arg(required = true)]
+        results: Vec<PathBuf>,
+
+        /// The HTML file to write
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Time a few random reads at an interval, as a lightweight latency
+    /// monitor
+    Watch {
+        /// File or block device to probe
+        #[arg(short, long)]
+        file: String,
+
+        /// Time between the starts of two probes
+        #[arg(long, default_value = "1m", value_parser = parse::duration)]
+        interval: Duration,
+
+        /// Reads per probe
+        #[arg(short, long, default_value_t = 256, value_parser = clap::value_parser!(u64).range(1..))]
         count: u64,
+
+        /// Block size, with an optional suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "4k", value_parser = parse::size)]
+        block_size: u64,
+
+        /// I/O strategy
+        #[arg(long, value_enum, default_value_t = Strategy::IOUring8)]
         strategy: Strategy,
+
+        /// Read through the page cache instead of with O_DIRECT
+        #[arg(long)]
+        buffered: bool,
+
+        /// Append one JSON line per probe to this file
+        #[arg(long)]
+        log: Option<PathBuf>,
+
+        /// Stop after this many probes instead of running until interrupted
+        #[arg(long)]
+        probes: Option<u64>,
     },
+    /// Check the environment for problems that affect benchmarks
+    Doctor {
+        /// Intended benchmark target
+        #[arg(short, long)]
+        file: Option<String>,
+    },
+    /// Check that all strategies write and read identical data
+    #[command(name = "selftest")]
+    SelfTest {
+        /// Block size, with an optional suffix (k, M, G)
+        #[arg(short = 's', long, default_value = "4k", value_parser = parse::size)]
+        block_size: u64,
+
+        /// Number of blocks
+        #[arg(short, long, default_value_t = 256)]
+        count: u64,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+#[derive(Debug, Clone, Args, Serialize, Deserialize)]
+struct Job {
+    /// Target file or block device, or `-` for stdout when writing and stdin
+    /// when reading
+    #[arg(short, long)]
+    file: String,
+
+    /// Block size, with an optional suffix (k, M, G)
+    #[arg(short = 's', long, default_value = "32", value_parser = parse::size)]
+    block_size: u64,
+
+    /// Number of blocks
+    #[arg(short, long, default_value_t = 1)]
+    count: u64,
+
+    /// I/O strategy
+    #[arg(long, value_enum, default_value_t)]
+    strategy: Strategy,
+
+    /// Transfer blocks at random offsets within the first `count` blocks
+    #[arg(long)]
+    random: bool,
+
+    /// Seed for random offsets, picked at random by default
+    #[arg(long, requires = "random")]
+    seed: Option<u64>,
+
+    /// Alignment of random offsets, defaults to the block size
+    #[arg(long, requires = "random", value_parser = parse::size)]
+    random_align: Option<u64>,
+
+    /// Track random offsets so that every block is transferred exactly once
+    /// per pass, instead of drawing each offset independently
+    #[arg(long, requires = "random")]
+    #[serde(default)]
+    random_map: bool,
+
+    /// Transfer the offsets listed in this file of `offset size` pairs in
+    /// order instead, which sets the block size and count
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["random", "auto_bs"])]
+    #[serde(default)]
+    offsets: Option<PathBuf>,
+
+    /// Number of worker threads to split the blocks between
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(default = "default_jobs")]
+    jobs: u64,
+
+    /// Let every worker use the whole range instead of its own share
+    #[arg(long)]
+    #[serde(default)]
+    overlap: bool,
+
+    /// Write the parameters and results of the run to this JSON file
+    #[arg(short, long)]
+    #[serde(skip)]
+    output: Option<PathBuf>,
+
+    /// Write a self-contained HTML report of the run to this file
+    #[arg(long, value_name = "PATH")]
+    #[serde(skip)]
+    report: Option<PathBuf>,
+
+    /// Print a status line with throughput and p99 latency to stderr at this
+    /// interval
+    #[arg(long, value_parser = parse::duration)]
+    #[serde(skip)]
+    status_interval: Option<Duration>,
+
+    /// Sample utilization, queue depth and await of the target device at
+    /// this interval and print them to stderr, like `iostat -x`
+    #[arg(long, value_name = "INTERVAL", value_parser = parse::duration)]
+    #[serde(skip)]
+    iostat: Option<Duration>,
+
+    /// Summarize throughput and tail latency per window of this length, and
+    /// how they drift between the first and the last window of a long run
+    #[arg(long, value_name = "WINDOW", value_parser = parse::duration)]
+    #[serde(default)]
+    soak: Option<Duration>,
+
+    /// Report how many operations were in flight over the course of the run,
+    /// which keeps 16 bytes per operation
+    #[arg(long)]
+    #[serde(skip)]
+    in_flight: bool,
+
+    /// Significant digits kept by the latency histogram
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(1..=5))]
+    #[serde(default = "default_hist_digits")]
+    hist_digits: u8,
+
+    /// Largest latency the histogram tracks, larger ones are recorded as this
+    /// value. Grows to fit any latency by default
+    #[arg(long, value_parser = parse::duration)]
+    #[serde(default)]
+    hist_max: Option<Duration>,
+
+    /// Periodically save the blocks reached to this file
+    #[arg(long)]
+    #[serde(skip)]
+    checkpoint: Option<PathBuf>,
+
+    /// Time between checkpoints
+    #[arg(long, default_value = "10s", value_parser = parse::duration)]
+    #[serde(skip)]
+    checkpoint_interval: Duration,
+
+    /// Continue from the checkpoint instead of starting from the first block
+    #[arg(long, requires = "checkpoint")]
+    #[serde(skip)]
+    resume: bool,
+
+    /// Open the file with O_NOATIME so that reads do not update its access
+    /// time
+    #[arg(long)]
+    #[serde(default)]
+    noatime: bool,
+
+    /// Read with O_DIRECT, bypassing the page cache
+    #[arg(long)]
+    #[serde(default)]
+    direct: bool,
+
+    /// Repeat the run through the page cache or with aligned blocks instead
+    /// of failing when O_DIRECT rejects a misaligned read
+    #[arg(long, value_enum, requires = "direct")]
+    #[serde(default)]
+    direct_fallback: Option<Fallback>,
+
+    /// Fsync the file after a write, which also flushes the device's write
+    /// cache, and report how long it takes until all data is durable
+    #[arg(long)]
+    #[serde(default)]
+    durable: bool,
+
+    /// Hash the written range of the file after a write, outside the timed
+    /// part of the run
+    #[arg(long, value_name = "ALGORITHM")]
+    #[serde(default)]
+    hash: Option<HashAlgorithm>,
+
+    /// Report the extents of the written range after a write, which show
+    /// how fragmented the file system allocated it
+    #[arg(long)]
+    #[serde(default)]
+    extents: bool,
+
+    /// CPU work to do for each completed block, either a duration to spin
+    /// for or `checksum` to checksum the block's data
+    #[arg(long, value_name = "DURATION|checksum", value_parser = compute::parse)]
+    #[serde(default)]
+    compute_per_block: Option<Compute>,
+
+    /// Subtract the measured cost of reading the clock from every latency
+    #[arg(long)]
+    #[serde(default)]
+    subtract_overhead: bool,
+
+    /// Delay every operation by `fixed:DELAY`, `random:MIN-MAX` or
+    /// `burst:DELAY:EVERY:LEN`, to emulate slow or erratic storage. Completed
+    /// operations are held while the strategy keeps submitting; monoio
+    /// strategies hold them on the runtime's timer, which rounds up to
+    /// milliseconds
+    #[arg(long, value_name = "PATTERN", value_parser = inject::parse_latency)]
+    #[serde(default)]
+    inject_latency: Option<InjectLatency>,
+
+    /// Results for `--strategy faulty` to report, a comma-separated list of
+    /// `eio:EVERY` (every EVERYth operation fails with EIO) and `short:RATIO`
+    /// (that share of operations transfers half a block)
+    #[arg(long, value_name = "PATTERN", value_parser = inject::parse_faults)]
+    #[serde(default)]
+    faults: Option<Faults>,
+
+    /// Read the SMART or NVMe health attributes of the target device before
+    /// and after the run and report what changed, e.g. the media wear
+    /// (requires root)
+    #[arg(long)]
+    #[serde(skip)]
+    smart: bool,
+
+    /// Measure the energy used by the CPU packages and DRAM during the run
+    /// with RAPL (requires root)
+    #[arg(long)]
+    #[serde(skip)]
+    energy: bool,
+
+    /// Check the parameters and target and print the planned operations
+    /// instead of running them
+    #[arg(long)]
+    #[serde(skip)]
+    dry_run: bool,
+
+    /// Write the first blocks before the run and print how long the run
+    /// takes at their rate
+    #[arg(long, conflicts_with = "dry_run")]
+    #[serde(skip)]
+    estimate: bool,
+
+    /// Instead of the run, measure block sizes from 4 KiB to 16 MiB on the
+    /// job's range and print the smallest one within WITHIN of the peak
+    /// throughput
+    #[arg(
+        long,
+        value_name = "WITHIN",
+        num_args = 0..=1,
+        default_missing_value = "5%",
+        value_parser = parse::percent,
+        conflicts_with_all = ["dry_run", "estimate"],
+    )]
+    #[serde(skip)]
+    auto_bs: Option<f64>,
+}
+
+impl Job {
+    /// Extra flags to open the file with for reads.
+    fn read_flags(&self) -> i32 {
+        let mut flags = 0;
+        if self.noatime {
+            flags |= libc::O_NOATIME;
+        }
+        if self.direct {
+            flags |= libc::O_DIRECT;
+        }
+        flags
+    }
+
+    /// Bytes of the buffers in use at once, summed over all workers.
+    fn buffer_bytes(&self) -> u64 {
+        let depth = self.strategy.queue().map_or(1, |(_, depth)| depth) as u64;
+        depth * self.block_size.next_multiple_of(4096) * self.jobs
+    }
+
+    /// Whether the results include throughput over time.
+    fn records_timeline(&self) -> bool {
+        self.output.is_some() || self.report.is_some()
+    }
+}
+
+#[cfg(test)]
+impl Job {
+    /// The job of `raio write` with `args`.
+    fn parse(args: &[&str]) -> Self {
+        let cmd = Cmd::try_parse_from(["raio", "write"].iter().chain(args)).unwrap();
+        let SubCmd::Write(job) = cmd.sub else {
+            unreachable!()
+        };
+        job
+    }
+}
+
+fn default_jobs() -> u64 {
+    1
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+fn default_hist_digits() -> u8 {
+    3
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum Strategy {
+    /// Blocking std::fs calls, one block at a time
     #[default]
     Std,
+    /// monoio, one block at a time
+    #[value(name = "seq")]
+    #[serde(rename = "seq")]
     Sequential,
+    /// monoio, all blocks in flight at once
     Async,
+    /// monoio, two blocks in flight
     Async2,
+    /// Raw io_uring, one block at a time
+    #[value(name = "io_uring")]
+    #[serde(rename = "io_uring")]
     IOUring,
+    /// Raw io_uring, two blocks in flight
+    #[value(name = "io_uring2")]
+    #[serde(rename = "io_uring2")]
     IOUring2,
+    /// Raw io_uring, eight blocks in flight
+    #[value(name = "io_uring8")]
+    #[serde(rename = "io_uring8")]
     IOUring8,
+    /// Like io_uring8, but waits for completions on an eventfd through the
+    /// runtime instead of in io_uring_enter
+    #[value(name = "io_uring_eventfd")]
+    #[serde(rename = "io_uring_eventfd")]
+    IOUringEventfd,
+    /// Like io_uring8, but submits NOPs instead of doing any I/O, to measure
+    /// raio's own overhead
+    Null,
+    /// Like null, but reports the results of the --faults pattern instead, to
+    /// exercise error handling without a failing device
+    Faulty,
+    /// Raw io_uring with every optimization the kernel supports: 32 blocks
+    /// in flight on fixed buffers and a registered file, submitted in
+    /// batches to a ring with SQPOLL, or DEFER_TASKRUN on a single CPU
+    Max,
 }
 
-impl FromStr for Strategy {
-    type Err = anyhow::Error;
+impl Strategy {
+    /// The strategies that transfer data, i.e. all but `Null` and `Faulty`.
+    const ALL: [Self; 9] = [
+        Self::Std,
+        Self::Sequential,
+        Self::Async,
+        Self::Async2,
+        Self::IOUring,
+        Self::IOUring2,
+        Self::IOUring8,
+        Self::IOUringEventfd,
+        Self::Max,
+    ];
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "std" => Ok(Self::Std),
-            "seq" => Ok(Self::Sequential),
-            "async" => Ok(Self::Async),
-            "async2" => Ok(Self::Async2),
-            "io_uring" => Ok(Self::IOUring),
-            "io_uring2" => Ok(Self::IOUring2),
-            "io_uring8" => Ok(Self::IOUring8),
-            _ => Err(anyhow::anyhow!("Invalid strategy")),
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Std => "std",
+            Self::Sequential => "seq",
+            Self::Async => "async",
+            Self::Async2 => "async2",
+            Self::IOUring => "io_uring",
+            Self::IOUring2 => "io_uring2",
+            Self::IOUring8 => "io_uring8",
+            Self::IOUringEventfd => "io_uring_eventfd",
+            Self::Null => "null",
+            Self::Faulty => "faulty",
+            Self::Max => "max",
         }
     }
+
+    fn describe(self, write: bool) -> &'static str {
+        match (self, write) {
+            (Self::Std, _) => "std::fs::File, one 4096-byte aligned buffer per block",
+            (Self::Sequential, _) => "monoio file, one Vec buffer per block, awaited one by one",
+            (Self::Async, _) => "monoio file, one task per block, all spawned up front",
+            (Self::Async2, _) => "monoio file, one task per block, two in flight",
+            (Self::IOUring, _) => "io_uring with 8 entries, one operation in flight",
+            (Self::IOUring2, true) => "io_uring with 8 entries, two IO_DRAIN writes in flight",
+            (Self::IOUring2, false) => "io_uring with 8 entries, two reads in flight",
+            (Self::IOUring8, true) => "io_uring with 32 entries, eight IO_DRAIN writes in flight",
+            (Self::IOUring8, false) => "io_uring with 32 entries, eight reads in flight",
+            (Self::IOUringEventfd, true) => {
+                "io_uring with 32 entries, eight IO_DRAIN writes in flight, completions awaited on an eventfd"
+            }
+            (Self::IOUringEventfd, false) => {
+                "io_uring with 32 entries, eight reads in flight, completions awaited on an eventfd"
+            }
+            (Self::Null, true) => "io_uring with 32 entries, eight IO_DRAIN NOPs in flight, no I/O",
+            (Self::Null, false) => "io_uring with 32 entries, eight NOPs in flight, no I/O",
+            (Self::Faulty, true) => {
+                "io_uring with 32 entries, eight IO_DRAIN NOPs in flight, results from --faults"
+            }
+            (Self::Faulty, false) => {
+                "io_uring with 32 entries, eight NOPs in flight, results from --faults"
+            }
+            (Self::Max, _) => {
+                "io_uring with 64 entries, 32 operations in flight on fixed buffers and a registered file, SQPOLL with more than one CPU, otherwise DEFER_TASKRUN, as supported"
+            }
+        }
+    }
+
+    /// Whether writes create the target if it does not exist.
+    fn creates_file(self) -> bool {
+        matches!(self, Self::Sequential | Self::Async | Self::Async2)
+    }
+
+    /// Whether the strategy submits to its own ring rather than going
+    /// through std or monoio.
+    fn uses_io_uring(self) -> bool {
+        !matches!(
+            self,
+            Self::Std | Self::Sequential | Self::Async | Self::Async2
+        )
+    }
+
+    /// The submission entries of the strategy's ring and the most operations
+    /// it keeps in flight, `None` for strategies without a ring of their own.
+    fn queue(self) -> Option<(u32, usize)> {
+        match self {
+            Self::Std | Self::Sequential | Self::Async | Self::Async2 => None,
+            Self::IOUring => Some((8, 1)),
+            Self::IOUring2 => Some((8, 2)),
+            Self::IOUring8 | Self::IOUringEventfd | Self::Null | Self::Faulty => Some((32, 8)),
+            Self::Max => Some((2 * MAX_DEPTH as u32, MAX_DEPTH)),
+        }
+    }
+
+    /// Whether all system calls of the timed phase are counted, which monoio
+    /// makes impossible by submitting to its ring on its own.
+    fn counts_syscalls(self) -> bool {
+        !matches!(self, Self::Sequential | Self::Async | Self::Async2)
+    }
 }
 
-impl Cmd {
-    fn from_env() -> Result<Self> {
-        let mut args = pico_args::Arguments::from_env();
-        let sub = match args.subcommand()?.as_deref() {
-            Some("write") => SubCmd::Write {
-                file: args.value_from_str(["-f", "--file"])?,
-                block_size: args
-                    .opt_value_from_str(["-s", "--block-size"])?
-                    .unwrap_or(32),
-                count: args.opt_value_from_str(["-c", "--count"])?.unwrap_or(1),
-                strategy: args.opt_value_from_str("--strategy")?.unwrap_or_default(),
-            },
-            Some("read") => SubCmd::Read {
-                file: args.value_from_str(["-f", "--file"])?,
-                block_size: args
-                    .opt_value_from_str(["-s", "--block-size"])?
-                    .unwrap_or(32),
-                count: args.opt_value_from_str(["-c", "--count"])?.unwrap_or(1),
-                strategy: args.opt_value_from_str("--strategy")?.unwrap_or_default(),
-            },
-            _ => return Err(anyhow::anyhow!("Invalid subcommand")),
-        };
-        let verbose = args.contains(["-v", "--verbose"]);
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
 
-        Ok(Self { sub, verbose })
+impl Cmd {
+    fn cpu_priority(&self) -> Option<CpuPriority> {
+        match (self.nice, self.sched_fifo) {
+            (Some(nice), _) => Some(CpuPriority::Nice(nice)),
+            (None, Some(prio)) => Some(CpuPriority::Fifo(prio)),
+            (None, None) => None,
+        }
     }
 
     async fn run(self) -> Result<()> {
-        match self.sub {
-            SubCmd::Write {
+        let res = match &self.sub {
+            SubCmd::Write(job) => self.bench(job, true, false).await.map(drop),
+            SubCmd::Read { job, verify } => self.bench(job, false, *verify).await.map(drop),
+            SubCmd::Matrix {
+                op,
+                strategies,
+                verify,
+                job,
+            } => matrix::run(&self, *op, strategies, *verify, job).await,
+            SubCmd::TrimWrite {
                 file,
                 block_size,
                 count,
+                trim_ratio,
+                depth,
+            } => trim::run(file, *block_size, *count, *trim_ratio, *depth as usize),
+            SubCmd::Pipeline {
+                src,
+                dst,
+                block_size,
+                depth,
+                transform,
+            } => pipeline::run(src, dst, *block_size, *depth as usize, *transform),
+            SubCmd::Copy {
+                src,
+                dst,
+                block_size,
+                depth,
+                engine,
+            } => copy::run(src, dst, *block_size, *depth as usize, *engine),
+            SubCmd::MsgRing { count, depth } => msgring::run(*count, *depth),
+            SubCmd::Serve {
+                dir,
+                files,
+                size,
+                count,
+                depth,
+                unlinked,
+            } => serve::run(dir, *files, *size, *count, *depth, *unlinked),
+            SubCmd::Recv {
+                connections,
+                size,
+                bytes,
+                buffers,
+            } => recv::run(*connections, *size, *bytes, *buffers),
+            SubCmd::CopyPaths {
+                src,
+                dst,
+                block_size,
+            } => copypath::run(src, dst, *block_size),
+            SubCmd::Cmp {
+                a,
+                b,
+                block_size,
                 strategy,
-            } => write_file(&file, block_size, count, strategy, self.verbose).await?,
-            SubCmd::Read {
+            } => cmp::run(a, b, *block_size, *strategy).await,
+            SubCmd::Hash {
                 file,
+                algo,
+                strategy,
+                block_size,
+            } => {
+                let strategies = match strategy.as_slice() {
+                    // `async` has every block in flight at once, which would
+                    // hold the whole file in memory.
+                    [] => Strategy::ALL
+                        .into_iter()
+                        .filter(|&strategy| strategy != Strategy::Async)
+                        .collect(),
+                    strategies => strategies.to_vec(),
+                };
+                hash::run(file, *block_size, *algo, &strategies).await
+            }
+            SubCmd::Scratch { size, dir, command } => scratch::run(*size, dir.as_deref(), command),
+            SubCmd::Rerun { results, output } => self.rerun(results, output.clone()).await,
+            SubCmd::GenBench {
                 block_size,
+                duration,
+            } => genbench::run(*block_size, *duration),
+            SubCmd::History {
+                op,
+                file,
+                strategy,
+                host,
+                since,
+                last,
+                json,
+            } => {
+                let filter = history::Filter {
+                    op: op.clone(),
+                    file: file.clone(),
+                    strategy: *strategy,
+                    host: host.clone(),
+                    since: *since,
+                };
+                history::run(&filter, *last, *json)
+            }
+            SubCmd::Suite {
+                file,
+                size,
+                ops,
+                presets,
+                report,
+            } => {
+                let presets = match presets.as_slice() {
+                    [] => &suite::Preset::ALL[..],
+                    presets => presets,
+                };
+                suite::run(&self, file, *size, *ops, presets, report.as_deref()).await
+            }
+            SubCmd::Diff { a, b } => diff::run(a, b),
+            SubCmd::Report { results, output } => report::run(results, output),
+            SubCmd::Watch {
+                file,
+                interval,
                 count,
+                block_size,
                 strategy,
-            } => read_file(&file, block_size, count, strategy, self.verbose).await?,
+                buffered,
+                log,
+                probes,
+            } => {
+                watch::run(
+                    file,
+                    *interval,
+                    *count,
+                    *block_size,
+                    *strategy,
+                    *buffered,
+                    log.as_deref(),
+                    *probes,
+                )
+                .await
+            }
+            SubCmd::Doctor { file } => doctor::run(file.as_deref()),
+            SubCmd::SelfTest { block_size, count } => selftest::run(*block_size, *count).await,
+            SubCmd::Completions { shell } => completions::run(*shell),
+        };
+        if let Err(err) = &res {
+            events::emit(Event::Error {
+                message: format!("{err:#}"),
+            });
         }
+        res
+    }
+
+    async fn rerun(&self, path: &Path, output: Option<PathBuf>) -> Result<()> {
+        let original = Results::load(path)?;
+        let job = Job {
+            output,
+            ..original.job.clone()
+        };
+        self.bench(&job, original.is_write(), original.verify)
+            .await?;
+        println!(
+            "original run: {} bytes in {:.6} seconds @ {}/s",
+            original.bytes,
+            original.seconds,
+            ISizeFormatter::new(original.bytes_per_sec, BINARY),
+        );
 
         Ok(())
     }
+
+    /// Runs `job` and returns its results, `None` for dry runs and streams.
+    /// With `--direct-fallback`, a job that is misaligned for O_DIRECT, found
+    /// up front or by a failed run after it drained its ring, is run once
+    /// more with the adjusted job.
+    async fn bench(&self, job: &Job, write: bool, verify: bool) -> Result<Option<Results>> {
+        let err = match self.bench_once(job, write, verify).await {
+            Err(err) => err,
+            res => return res,
+        };
+        let (Some(fallback), Some(misaligned)) = (
+            job.direct_fallback,
+            err.downcast_ref::<Misaligned>().copied(),
+        ) else {
+            return Err(err);
+        };
+        let adjusted = match direct::adjust(job, fallback, &misaligned) {
+            Err(adjust) => return Err(err.context(adjust.to_string())),
+            adjusted => adjusted?,
+        };
+        warn!(
+            "{err:#}, falling back to {}",
+            match fallback {
+                Fallback::Buffered => "reads through the page cache".to_string(),
+                Fallback::Align => format!("blocks of {} bytes", adjusted.block_size),
+            }
+        );
+        self.bench_once(&adjusted, write, verify).await
+    }
+
+    async fn bench_once(&self, job: &Job, write: bool, verify: bool) -> Result<Option<Results>> {
+        let mut job = job.clone();
+        let listed = match &job.offsets {
+            Some(path) => {
+                let (size, offsets) = offsets::load(path)?;
+                info!(
+                    "{} offsets of {size} bytes from {}",
+                    offsets.len(),
+                    path.display()
+                );
+                job.block_size = size;
+                job.count = offsets.len() as u64;
+                Some(offsets)
+            }
+            None => None,
+        };
+        let job = &job;
+        events::emit(Event::Start {
+            op: if write { "write" } else { "read" },
+            file: job.file.clone(),
+            strategy: job.strategy.to_string(),
+            block_size: job.block_size,
+            count: job.count,
+        });
+        events::emit(Event::Phase {
+            phase: Phase::Setup,
+        });
+        if job.file == stream::STDIO {
+            stream::run(job, write, verify)?;
+            return Ok(None);
+        }
+        let device = Device::for_path(&job.file).context("failed to resolve target device")?;
+        let target = validate::job(job, write, verify, listed.as_deref(), device.as_ref())?;
+        let _scheduler = match (self.scheduler, &device) {
+            (Some(scheduler), Some(device)) if !job.dry_run => {
+                Some(device.set_scheduler(scheduler)?)
+            }
+            (Some(_), None) => {
+                return Err(anyhow::anyhow!(
+                    "--scheduler requires a block device target"
+                ))
+            }
+            _ => None,
+        };
+        let attributes = device.as_ref().map(Device::attributes);
+        let limits = match &device {
+            Some(device) => IoLimits::for_device(device.major, device.minor)
+                .context("failed to read cgroup I/O limits")?,
+            None => None,
+        };
+        if let Some(ioprio) = self.ioprio {
+            ioprio.apply()?;
+        }
+        if let Some(cpu_priority) = self.cpu_priority() {
+            cpu_priority.apply()?;
+        }
+        let mlock = self.mlock.then(|| {
+            let res = memlock::enable(job.buffer_bytes());
+            if let Err(err) = &res {
+                warn!("failed to lock the buffers, continuing without: {err:#}");
+            }
+            res
+        });
+
+        let mut job = job.clone();
+        let saved = match (&job.checkpoint, job.resume) {
+            (Some(path), true) => Checkpoint::load(path)?,
+            _ => None,
+        };
+        if job.random && job.seed.is_none() {
+            // A resumed run has to continue with the offsets it started with.
+            let saved_seed = saved.as_ref().and_then(|checkpoint| checkpoint.seed);
+            job.seed = Some(saved_seed.unwrap_or_else(offsets::random_seed));
+        }
+        let Job {
+            file,
+            block_size,
+            count,
+            strategy,
+            random,
+            seed,
+            random_align,
+            random_map,
+            ..
+        } = job.clone();
+        let align = random_align.unwrap_or(block_size);
+        let mode = match (listed, seed) {
+            (Some(offsets), _) => Mode::List { offsets },
+            (None, Some(seed)) if random_map => Mode::RandomMap { seed, align },
+            (None, Some(seed)) if random => Mode::Random { seed, align },
+            _ => Mode::Sequential,
+        };
+        let mut resumed = Checkpoint {
+            op: if write { "write" } else { "read" }.to_string(),
+            file: file.clone(),
+            strategy: strategy.to_string(),
+            seed,
+            random_align,
+            random_map,
+            block_size,
+            count,
+            next_block: 0,
+            bytes: 0,
+            seconds: 0.0,
+        };
+        match (saved, &job.checkpoint) {
+            (Some(checkpoint), _) => {
+                checkpoint.ensure_matches(&resumed)?;
+                info!("resuming at block {}/{count}", checkpoint.next_block);
+                resumed = checkpoint;
+            }
+            (None, Some(path)) if job.resume => {
+                info!("no checkpoint at {}, starting over", path.display())
+            }
+            _ => {}
+        }
+        let first = resumed.next_block;
+
+        info!(
+            "{} {count} blocks of {block_size} bytes ({}) {} {file}",
+            if write { "writing" } else { "reading" },
+            SizeFormatter::new(block_size * count, BINARY),
+            if write { "to" } else { "from" },
+        );
+        info!("strategy {strategy}: {}", strategy.describe(write));
+        if let Some(inject) = job.inject_latency {
+            info!("injecting latency: {inject}");
+        }
+        if let Some(faults) = job.faults {
+            info!("injecting faults: {faults}");
+        }
+        if job.jobs > 1 {
+            info!(
+                "{} workers, {}",
+                job.jobs,
+                if job.overlap {
+                    "each using the whole range"
+                } else {
+                    "each using its own share of the range"
+                },
+            );
+        }
+        if job.dry_run {
+            dryrun::print(&job, write, mode.clone(), target, first..count)?;
+            return Ok(None);
+        }
+        if job.estimate {
+            estimate(&job, mode.clone(), first..count).await?;
+        }
+        if let Some(within) = job.auto_bs {
+            autobs::run(&job, write, mode.clone(), within).await?;
+            return Ok(None);
+        }
+        events::emit(Event::Phase { phase: Phase::Run });
+        let health_before = match &device {
+            Some(device) if job.smart => Some(Health::read(device)?),
+            _ => None,
+        };
+        let disk_before = match &device {
+            Some(device) => DiskStats::read(device)?,
+            None => None,
+        };
+        let sampler = device
+            .clone()
+            .zip(job.iostat)
+            .map(|(device, interval)| Sampler::start(device, interval));
+        let offsets = Offsets::new(block_size, mode.clone(), 0, 0..block_size * count);
+        let mut latency = Latency::new(job.hist_digits, job.hist_max)?;
+        let overhead = Overhead::measure(&latency, job.subtract_overhead);
+        if job.subtract_overhead {
+            latency = latency.with_correction(overhead.clock());
+        }
+        debug!(
+            "latency histogram uses {} per worker{}",
+            SizeFormatter::new(latency.memory(), BINARY),
+            if job.hist_max.is_none() {
+                " and grows with the largest latency"
+            } else {
+                ""
+            },
+        );
+        let mut progress = Progress::new(block_size * count)
+            .starting_at(resumed.bytes)
+            .with_latency(latency.empty_like())
+            .with_injected_latency(job.inject_latency);
+        if job.records_timeline() {
+            progress = progress.with_timeline();
+        }
+        if let Some(window) = job.soak {
+            progress = progress.with_soak(window, &latency);
+        }
+        if job.in_flight {
+            progress = progress.with_in_flight();
+        }
+        if let Some(interval) = job.status_interval {
+            progress = progress.with_status(interval, None);
+        }
+        if let Some(path) = &job.checkpoint {
+            let checkpointer =
+                Checkpointer::new(path.clone(), job.checkpoint_interval, resumed.clone());
+            progress = progress.with_checkpoint(checkpointer);
+        }
+        let meter = job.energy.then(rapl::Meter::start).transpose()?;
+        let res = if job.jobs > 1 {
+            worker::run(&job, mode, &latency, write, verify)
+        } else if write {
+            let gate = &mut StartGate::none();
+            let compute = job.compute_per_block;
+            write_file(
+                &file,
+                &offsets,
+                first..count,
+                strategy,
+                compute,
+                job.faults,
+                &mut progress,
+                gate,
+            )
+            .await
+        } else {
+            read_file(
+                &file,
+                job.read_flags(),
+                &offsets,
+                first..count,
+                strategy,
+                job.compute_per_block,
+                job.faults,
+                verify.then_some(&mut matches_pattern),
+                &mut progress,
+                &mut StartGate::none(),
+            )
+            .await
+        };
+        let res = res.and_then(Stats::verified);
+        progress.finish(res.is_ok());
+        let mut stats = res?;
+        let durable = job
+            .durable
+            .then(|| {
+                let started = Instant::now();
+                fs::File::open(&file)
+                    .and_then(|file| file.sync_all())
+                    .with_context(|| format!("failed to sync {file}"))?;
+                Ok(started.elapsed())
+            })
+            .transpose()?;
+        let iostat = sampler.map(Sampler::finish).unwrap_or_default();
+        let energy = meter.map(|meter| meter.finish(stats.bytes)).transpose()?;
+        let wear = match (&device, health_before) {
+            (Some(device), Some(before)) => Some(Wear {
+                device: device.name.clone(),
+                before,
+                after: Health::read(device)?,
+            }),
+            _ => None,
+        };
+        let disk = match (&device, disk_before) {
+            (Some(device), Some(before)) => {
+                DiskStats::read(device)?.map(|after| after.since(&before))
+            }
+            _ => None,
+        };
+        // The CPU time and system calls are of this invocation only.
+        let efficiency = Efficiency::new(stats.usage, stats.bytes, job.strategy.counts_syscalls());
+        stats.bytes += resumed.bytes;
+        stats.transferred += resumed.bytes;
+        stats.ops += first;
+        stats.elapsed += Duration::from_secs_f64(resumed.seconds);
+        events::emit(Event::Finished {
+            bytes: stats.bytes,
+            ops: stats.ops,
+            seconds: stats.elapsed.as_secs_f64(),
+            bytes_per_sec: stats.bytes_per_sec(),
+        });
+        events::emit(Event::Phase {
+            phase: Phase::Report,
+        });
+        let hash = match job.hash {
+            Some(algorithm) => {
+                let started = Instant::now();
+                let hash = hash::file(&file, block_size * count, algorithm)
+                    .context("failed to hash the written file")?;
+                debug!("hashed {} in {:?}", file, started.elapsed());
+                Some(hash)
+            }
+            None => None,
+        };
+        // The results of the run are kept if the file system cannot map them.
+        let extents = job
+            .extents
+            .then(|| Extents::of(&file, block_size * count))
+            .and_then(|res| {
+                res.inspect_err(|err| {
+                    warn!("failed to map the extents of the written file: {err:#}")
+                })
+                .ok()
+            });
+
+        println!(
+            "{} {}/{} bytes in {:.6} seconds @ {}/s",
+            if write { "writen" } else { "read" },
+            stats.transferred,
+            stats.bytes,
+            stats.elapsed.as_secs_f64(),
+            ISizeFormatter::new(stats.bytes_per_sec(), BINARY),
+        );
+        if !stats.latency.is_empty() {
+            if first > 0 {
+                // The histogram is not part of the checkpoint.
+                println!("latency (blocks {first}..{count} only): {}", stats.latency);
+            } else {
+                println!("latency: {}", stats.latency);
+            }
+        }
+        if !stats.workers.is_empty() {
+            for worker in &stats.workers {
+                println!("  {worker}");
+            }
+            if let Some(skew) = worker::skew(&stats.workers) {
+                println!("worker skew: {skew}");
+            }
+        }
+        let soak = stats.soak.windows();
+        if !soak.is_empty() {
+            println!("soak windows of {}:", Short(stats.soak.window()));
+            for window in &soak {
+                println!("  {window}");
+            }
+            if let Some(drift) = soak::drift(&soak, stats.soak.window()) {
+                println!("soak drift from the first to the last full window: {drift}");
+            }
+        }
+        if let Some(durable) = durable {
+            let end_to_end = stats.elapsed + durable;
+            println!(
+                "durable {} after the last write completed, {:.6} seconds end to end @ {}/s",
+                Short(durable),
+                end_to_end.as_secs_f64(),
+                ISizeFormatter::new(stats.bytes as f64 / end_to_end.as_secs_f64(), BINARY),
+            );
+        }
+        println!(
+            "timing overhead: {} per clock read{}, {} per recorded operation",
+            Short(overhead.clock()),
+            if overhead.subtracted {
+                " (subtracted from latencies)"
+            } else {
+                ""
+            },
+            Short(overhead.record()),
+        );
+        if let Some(compute) = job.compute_per_block {
+            let share =
+                stats.compute.as_secs_f64() / (stats.elapsed.as_secs_f64() * job.jobs as f64);
+            println!(
+                "compute ({compute} per block): {:.6} seconds, {:.1}% of the run",
+                stats.compute.as_secs_f64(),
+                share * 100.0,
+            );
+        }
+        if verify && !write {
+            println!("verified {} blocks", count - first);
+        }
+        if let Some(inject) = job.inject_latency {
+            println!("injected latency: {inject}");
+        }
+        println!(
+            "page faults: {} minor, {} major",
+            stats.faults.minor, stats.faults.major
+        );
+        println!("efficiency: {efficiency}");
+        let in_flight = (!stats.in_flight.is_empty()).then(|| stats.in_flight.summary());
+        if let Some(in_flight) = &in_flight {
+            println!("in flight: {in_flight}");
+        }
+        if let Some(ring) = &stats.ring {
+            println!("io_uring: {ring}");
+            if let Some(warning) = ring.loss_warning() {
+                warn!("{warning}");
+            }
+        }
+        if let Some(seed) = seed {
+            println!("seed: {seed}");
+        }
+        if let Some(extents) = &extents {
+            println!("extents: {extents}");
+        }
+        if let Some(hash) = &hash {
+            println!("hash: {hash}");
+        }
+        if let Some(energy) = &energy {
+            println!("energy: {energy}");
+        }
+
+        if let Some(attributes) = &attributes {
+            println!("{attributes}");
+        }
+        if let (Some(device), Some(disk)) = (&device, &disk) {
+            println!("diskstats {}: {disk}", device.name);
+        }
+        if let Some(wear) = &wear {
+            println!("smart {}: {wear}", wear.device);
+        }
+        if let (Some(device), false) = (&device, iostat.is_empty()) {
+            println!("iostat {}: {}", device.name, iostat::summary(&iostat));
+        }
+        if let Some(pressure) = &stats.pressure {
+            println!("pressure: {pressure}");
+            if let Some(warning) = pressure.starvation_warning(stats.elapsed) {
+                warn!("{warning}");
+            }
+        }
+        if let Some(ioprio) = self.ioprio {
+            println!("ioprio: {ioprio}");
+        }
+        if let Some(cpu_priority) = self.cpu_priority() {
+            println!("cpu priority: {cpu_priority}");
+        }
+        match (&mlock, memlock::outcome()) {
+            (Some(Err(err)), _) => println!("mlock: failed, {err:#}"),
+            (Some(_), Some(outcome)) => println!("mlock: {outcome}"),
+            _ => {}
+        }
+        if let Some(limits) = limits {
+            println!("{limits}");
+            if let Some(warning) =
+                limits.throttle_warning(write, stats.bytes_per_sec(), stats.iops())
+            {
+                warn!("{warning}");
+                events::emit(Event::Throttled { message: warning });
+            }
+        }
+
+        let results = Results {
+            hash,
+            extents,
+            durable_seconds: durable.map(|durable| durable.as_secs_f64()),
+            overhead: Some(overhead),
+            diskstats: disk,
+            smart: wear,
+            ring: stats.ring,
+            in_flight,
+            iostat,
+            soak,
+            pressure: stats.pressure,
+            energy,
+            efficiency: Some(efficiency),
+            ..Results::new(&job, write, verify, &stats)
+        };
+        if !self.no_history {
+            if let Err(err) = history::record(&results, attributes.as_ref()) {
+                warn!("failed to record the run in the history: {err:#}");
+            }
+        }
+        if let Some(path) = &job.output {
+            results.save(path)?;
+        }
+        if let Some(path) = &job.report {
+            report::write(path, &[(file.clone(), results.clone())])?;
+        }
+
+        events::emit(Event::Phase { phase: Phase::Done });
+        Ok(Some(results))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Stats {
+    bytes: u64,
+    transferred: u64,
+    ops: u64,
+    elapsed: Duration,
+    latency: Latency,
+    timeline: Timeline,
+    soak: Soak,
+    in_flight: Spans,
+    /// Overflow counters of the strategy's ring, `None` without one.
+    ring: Option<RingCounters>,
+    /// Blocks that failed verification.
+    mismatches: u64,
+    /// Time spent in `--compute-per-block` work, summed over all workers.
+    compute: Duration,
+    /// Page faults in the timed phase, summed over all workers.
+    faults: PageFaults,
+    /// CPU time, context switches and system calls of the timed phase,
+    /// summed over all workers.
+    usage: Usage,
+    /// Each worker's share, empty for single-threaded runs.
+    workers: Vec<worker::Worker>,
+    /// Stalls on CPU, memory and I/O in the timed phase, machine-wide.
+    pressure: Option<Pressures>,
 }
 
+impl Stats {
+    fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn iops(&self) -> f64 {
+        self.ops as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Fails if any block failed verification.
+    fn verified(self) -> Result<Self> {
+        if self.mismatches > 0 {
+            return Err(anyhow::anyhow!(
+                "{}/{} blocks failed verification{}",
+                self.mismatches,
+                self.ops,
+                if tracing::enabled!(Level::DEBUG) {
+                    ""
+                } else {
+                    " (use -vv for details)"
+                },
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// Checks the content of a block read from the given offset, `false` if it is
+/// wrong.
+type Verify<'a> = &'a mut dyn FnMut(u64, &[u8]) -> bool;
+
+#[allow(clippy::too_many_arguments)]
 async fn write_file(
     path: &str,
-    block_size: u64,
-    count: u64,
+    offsets: &Offsets,
+    blocks: Range<u64>,
     strategy: Strategy,
-    verbose: bool,
-) -> Result<()> {
+    compute: Option<Compute>,
+    faults: Option<Faults>,
+    progress: &mut Progress,
+    gate: &mut StartGate<'_>,
+) -> Result<Stats> {
     // let block = &*Vec::leak(vec![0u8; block_size as usize]);
+    let block_size = offsets.block_size();
+    let Range { start: first, end } = blocks;
+    let count = end - first;
+    let (entries, _) = strategy.queue().unwrap_or_default();
     let mut written = 0;
-    let start = Instant::now();
+    let mut errors = 0;
+    let mut counters = None;
+    let mut compute_time = Duration::ZERO;
+    let inject = progress.injected_latency();
+    // When the gate opened, for the progress clocks to start then.
+    let opened = Cell::new(None);
+    let open = |gate: &mut StartGate| {
+        let start = gate.open();
+        opened.set(Some(start));
+        start
+    };
+    let mut complete = |i: u64, data: &[u8], latency: Duration| {
+        if let Some(compute) = compute {
+            let started = Instant::now();
+            compute.run(data);
+            compute_time += started.elapsed();
+        }
+        if let Some(start) = opened.take() {
+            progress.restart(start);
+        }
+        progress.add(i, data.len() as u64, latency);
+    };
+    let mut start = Instant::now();
     match strategy {
         Strategy::Std => {
-            let mut file = fs::OpenOptions::new()
+            let file = fs::OpenOptions::new()
                 .read(true)
                 .write(true)
                 // .create(true)
                 // .truncate(true)
                 .open(path)?;
+            start = open(gate);
 
-            for i in 0..count {
-                let pos = i * block_size;
-                let buf = make_block_mem_aligned(block_size, i * block_size / 64)?;
+            for i in first..end {
+                let pos = offsets.get(i);
+                let buf = make_block_mem_aligned(block_size, pos / 64)?;
                 let slice = unsafe { std::slice::from_raw_parts_mut(buf, block_size as usize) };
-                file.write_all_at(slice, 0)?;
+                let issued = Instant::now();
+                usage::write_all_at(&file, slice, pos)
+                    .map_err(|err| op_error("write", i, offsets, err))?;
+                thread::sleep(inject::delay(inject, i));
+                let latency = issued.elapsed();
+                complete(i, slice, latency);
                 mem_aligned_free(buf, block_size as usize, 4096);
             }
         }
@@ -154,11 +1730,16 @@ async fn write_file(
                 .open(path)
                 .await?;
             let file = Rc::new(file);
+            start = open(gate);
 
-            for i in 0..count {
-                let pos = i * block_size;
-                let block = make_block(block_size, i * block_size / 64);
-                file.write_all_at(block, /*pos*/ 0).await.0?;
+            for i in first..end {
+                let pos = offsets.get(i);
+                let block = make_block(block_size, pos / 64);
+                let delay = inject::delay(inject, i);
+                let ((res, block), latency) =
+                    timed(delayed(file.write_all_at(block, pos), delay)).await;
+                res.map_err(|err| op_error("write", i, offsets, err))?;
+                complete(i, &block, latency);
             }
         }
         Strategy::Async => {
@@ -168,18 +1749,22 @@ async fn write_file(
                 .open(path)
                 .await?;
             let file = Rc::new(file);
+            start = open(gate);
 
             let mut handles = Vec::with_capacity(count as usize);
-            for i in 0..count {
+            for i in first..end {
                 let file = Rc::clone(&file);
+                let pos = offsets.get(i);
+                let delay = inject::delay(inject, i);
                 handles.push(monoio::spawn(async move {
-                    let pos = i * block_size;
-                    let block = make_block(block_size, i * block_size / 64);
-                    file.write_at(block, /*pos*/ 0).await.0
+                    let block = make_block(block_size, pos / 64);
+                    timed(delayed(file.write_at(block, pos), delay)).await
                 }));
             }
-            for handle in handles {
-                written += handle.await?;
+            for (i, handle) in (first..).zip(handles) {
+                let ((res, block), latency) = handle.await;
+                written += res.map_err(|err| op_error("write", i, offsets, err))?;
+                complete(i, &block, latency);
             }
         }
         Strategy::Async2 => {
@@ -189,42 +1774,52 @@ async fn write_file(
                 .open(path)
                 .await?;
             let file = Rc::new(file);
+            start = open(gate);
 
             if count > 0 {
                 let mut current = monoio::spawn({
                     let file = Rc::clone(&file);
+                    let pos = offsets.get(first);
+                    let delay = inject::delay(inject, first);
                     async move {
-                        let block = make_block(block_size, 0);
-                        file.write_at(block, 0).await.0
+                        let block = make_block(block_size, pos / 64);
+                        timed(delayed(file.write_at(block, pos), delay)).await
                     }
                 });
-                for i in 1..count {
+                for i in first + 1..end {
                     let file = Rc::clone(&file);
+                    let pos = offsets.get(i);
+                    let delay = inject::delay(inject, i);
                     let next = monoio::spawn(async move {
-                        let pos = i * block_size;
-                        let block = make_block(block_size, i * block_size / 64);
-                        file.write_at(block, /*pos*/ 0).await.0
+                        let block = make_block(block_size, pos / 64);
+                        timed(delayed(file.write_at(block, pos), delay)).await
                     });
-                    written += current.await?;
+                    let ((res, block), latency) = current.await;
+                    written += res.map_err(|err| op_error("write", i - 1, offsets, err))?;
+                    complete(i - 1, &block, latency);
                     current = next;
                 }
-                written += current.await?;
+                let ((res, block), latency) = current.await;
+                written += res.map_err(|err| op_error("write", end - 1, offsets, err))?;
+                complete(end - 1, &block, latency);
             }
         }
         Strategy::IOUring => {
-            let mut ring = IoUring::new(8)?;
+            let mut ring = ring::new_ring(entries)?;
 
             let file = fs::OpenOptions::new()
-                .append(true)
+                .write(true)
                 // .create(true)
                 // .truncate(true)
                 .open(path)?;
             let fd = types::Fd(file.as_raw_fd());
+            start = open(gate);
 
-            for i in 0..count {
+            for i in first..end {
                 // let mut buf = make_block(block_size, i * block_size / 64);
-                let buf = make_block_mem_aligned(block_size, i * block_size / 64)?;
+                let buf = make_block_mem_aligned(block_size, offsets.get(i) / 64)?;
                 let write_e = opcode::Write::new(fd, buf, block_size as _)
+                    .offset(offsets.get(i))
                     .build()
                     .user_data(0x42);
 
@@ -236,29 +1831,48 @@ async fn write_file(
                         .expect("submission queue is full");
                 }
 
+                let issued = Instant::now();
                 ring.submit_and_wait(1)?;
+                usage::entered();
 
                 let cqe = ring.completion().next().expect("completion queue is empty");
 
                 assert_eq!(cqe.user_data(), 0x42);
-                assert!(cqe.result() >= 0, "write error: {}", cqe.result());
+                if cqe.result() < 0 {
+                    // The write completed, so its buffer is no longer in use.
+                    mem_aligned_free(buf, block_size as usize, 4096);
+                    let err = std::io::Error::from_raw_os_error(-cqe.result());
+                    return Err(op_error("write", i, offsets, err));
+                }
 
+                // With one write in flight there is nothing to submit while
+                // it is held.
+                thread::sleep(inject::delay(inject, i));
+                let latency = issued.elapsed();
+                complete(
+                    i,
+                    unsafe { std::slice::from_raw_parts(buf, block_size as usize) },
+                    latency,
+                );
                 mem_aligned_free(buf, block_size as usize, 4096);
             }
+            counters = Some(RingCounters::of(&mut ring));
         }
         Strategy::IOUring2 => {
             if count > 0 {
-                let mut ring = IoUring::new(8)?;
+                let mut ring = ring::new_ring(entries)?;
 
                 let file = fs::OpenOptions::new()
-                    .append(true)
+                    .write(true)
                     // .create(true)
                     // .truncate(true)
                     .open(path)?;
                 let fd = types::Fd(file.as_raw_fd());
+                start = open(gate);
 
-                let mut write = |ring: &mut IoUring, buf: *mut u8| {
+                let write = |ring: &mut IoUring, pos: u64, buf: *mut u8| {
                     let write_e = opcode::Write::new(fd, buf, block_size as _)
+                        .offset(pos)
                         .build()
                         .flags(Flags::IO_DRAIN)
                         .user_data(0x42);
@@ -273,46 +1887,78 @@ async fn write_file(
 
                     Ok(())
                 };
-                let wait = |ring: &mut IoUring| {
+                let wait = |ring: &mut IoUring, i: u64| {
                     ring.submit_and_wait(1)?;
+                    usage::entered();
 
                     let cqe = ring.completion().next().expect("completion queue is empty");
 
                     assert_eq!(cqe.user_data(), 0x42);
-                    assert!(cqe.result() >= 0, "write error: {}", cqe.result());
+                    if cqe.result() < 0 {
+                        let err = std::io::Error::from_raw_os_error(-cqe.result());
+                        return Err(op_error("write", i, offsets, err));
+                    }
 
                     Ok(())
                 };
 
-                let mut current = make_block_mem_aligned(block_size, 0)?;
-                write(&mut ring, current)?;
+                let mut current = make_block_mem_aligned(block_size, offsets.get(first) / 64)?;
+                let mut issued = Instant::now();
+                write(&mut ring, offsets.get(first), current)?;
 
-                for i in 1..count {
-                    let next = make_block_mem_aligned(block_size, i * block_size / 64)?;
-                    write(&mut ring, next)?;
-                    wait(&mut ring)?;
+                for i in first + 1..end {
+                    let next = make_block_mem_aligned(block_size, offsets.get(i) / 64)?;
+                    let next_issued = Instant::now();
+                    write(&mut ring, offsets.get(i), next)?;
+                    wait(&mut ring, i - 1)?;
+                    // The next write is in flight already, holding the
+                    // current one leaves nothing to submit.
+                    thread::sleep(inject::delay(inject, i - 1));
+                    let latency = issued.elapsed();
+                    complete(
+                        i - 1,
+                        unsafe { std::slice::from_raw_parts(current, block_size as usize) },
+                        latency,
+                    );
                     mem_aligned_free(current, block_size as usize, 4096);
                     current = next;
+                    issued = next_issued;
                 }
-                wait(&mut ring)?;
+                wait(&mut ring, end - 1)?;
+                thread::sleep(inject::delay(inject, end - 1));
+                let latency = issued.elapsed();
+                complete(
+                    end - 1,
+                    unsafe { std::slice::from_raw_parts(current, block_size as usize) },
+                    latency,
+                );
                 mem_aligned_free(current, block_size as usize, 4096);
+                counters = Some(RingCounters::of(&mut ring));
             }
         }
-        Strategy::IOUring8 => {
-            let mut ring = IoUring::new(32)?;
+        Strategy::IOUring8 | Strategy::IOUringEventfd | Strategy::Null | Strategy::Faulty => {
+            let mut ring = ring::new_ring(entries)?;
+            let mut notify = (strategy == Strategy::IOUringEventfd)
+                .then(|| EventFd::register(&ring))
+                .transpose()?;
 
             let file = fs::OpenOptions::new()
-                .append(true)
+                .write(true)
                 // .create(true)
                 // .truncate(true)
                 .open(path)?;
             let fd = types::Fd(file.as_raw_fd());
+            start = open(gate);
 
-            let mut write = |ring: &mut IoUring, i: u64, buf: *mut u8| {
-                let write_e = opcode::Write::new(fd, buf, block_size as _)
-                    .build()
-                    .flags(Flags::IO_DRAIN)
-                    .user_data(i);
+            let write = |ring: &mut IoUring, i: u64, buf: *mut u8| {
+                let write_e = if matches!(strategy, Strategy::Null | Strategy::Faulty) {
+                    opcode::Nop::new().build()
+                } else {
+                    opcode::Write::new(fd, buf, block_size as _)
+                        .offset(offsets.get(i))
+                        .build()
+                };
+                let write_e = write_e.flags(Flags::IO_DRAIN).user_data(i);
 
                 // Note that the developer needs to ensure
                 // that the entry pushed into submission queue is valid (e.g. fd, buffer).
@@ -324,14 +1970,24 @@ async fn write_file(
 
                 Ok(())
             };
-            let wait = |ring: &mut IoUring, want: usize| {
-                ring.submit_and_wait(want)?;
-
+            // Reaps `want` completions that are ready.
+            let mut reap = |ring: &mut IoUring, want: usize| -> Result<usize> {
                 for _ in 0..want {
                     let cqe = ring.completion().next().expect("completion queue is empty");
-                    // println!("write result: {} @ {}", cqe.result(), cqe.user_data());
-                    if cqe.result() < 0 {
-                        println!("write error: {} @ {}", cqe.result(), cqe.user_data());
+                    let block = cqe.user_data();
+                    let result = match faults {
+                        Some(faults) => faults.result(block, block_size),
+                        None => cqe.result(),
+                    };
+                    trace!("write result: {result} @ {block}");
+                    if result < 0 {
+                        let err = std::io::Error::from_raw_os_error(-result);
+                        op_error("write", block, offsets, err);
+                        errors += 1;
+                    } else if result as u64 != block_size && strategy != Strategy::Null {
+                        let err = anyhow::anyhow!("short write of {result} bytes");
+                        op_error("write", block, offsets, err);
+                        errors += 1;
                     }
                     // assert_eq!(cqe.user_data(), 0x42);
                     // assert!(cqe.result() >= 0, "write error: {}", cqe.result());
@@ -340,85 +1996,719 @@ async fn write_file(
                 Ok(want)
             };
 
+            // Writes complete in order, the queue holds them until then and
+            // `held` until their injected delay is due.
             let mut queue = VecDeque::with_capacity(8);
-            for i in 0..u64::min(7, count) {
-                let buf = make_block_mem_aligned(block_size, i * block_size / 64)?;
-                write(&mut ring, i, buf)?;
-                queue.push_back(buf);
-            }
-            for i in 7..count {
-                let buf = make_block_mem_aligned(block_size, i * block_size / 64)?;
-                write(&mut ring, i, buf)?;
-                queue.push_back(buf);
-
-                for _ in 0..wait(&mut ring, 1)? {
-                    mem_aligned_free(queue.pop_front().unwrap(), block_size as usize, 4096);
+            let mut held = Held::new(inject);
+            let mut next = first;
+            while next < end || !queue.is_empty() || !held.is_empty() {
+                if next < end && queue.len() + held.len() < 8 {
+                    let buf = make_block_mem_aligned(block_size, offsets.get(next) / 64)?;
+                    write(&mut ring, next, buf)?;
+                    queue.push_back((next, buf, Instant::now()));
+                    next += 1;
+                    continue;
+                }
+
+                match held.next_due() {
+                    None => eventfd::wait(&mut ring, notify.as_mut()).await?,
+                    due => ring::wait(&mut ring, !queue.is_empty(), due)?,
+                }
+                let ready = ring.completion().len().min(1);
+                let now = Instant::now();
+                for _ in 0..reap(&mut ring, ready)? {
+                    let write = queue.pop_front().unwrap();
+                    held.hold(write.0, now, write);
+                }
+                while let Some((block, buf, issued)) = held.release(Instant::now()) {
+                    let latency = issued.elapsed();
+                    complete(
+                        block,
+                        unsafe { std::slice::from_raw_parts(buf, block_size as usize) },
+                        latency,
+                    );
+                    mem_aligned_free(buf, block_size as usize, 4096);
                 }
             }
-            while !queue.is_empty() {
-                for _ in 0..wait(&mut ring, 1)? {
-                    mem_aligned_free(queue.pop_front().unwrap(), block_size as usize, 4096);
+            counters = Some(RingCounters::of(&mut ring));
+        }
+        Strategy::Max => {
+            let (mut ring, setup) = ring::new_max_ring(entries)?;
+            let file = fs::OpenOptions::new().write(true).open(path)?;
+            ring.submitter()
+                .register_files(&[file.as_raw_fd()])
+                .context("failed to register the file")?;
+            let bufs = fixed_buffers(&ring, block_size)?;
+            debug!("max: {setup}, {MAX_DEPTH} fixed buffers, registered file");
+
+            let mut slots = vec![(0, Instant::now()); MAX_DEPTH];
+            let mut free = (0..MAX_DEPTH).rev().collect::<Vec<_>>();
+            let mut held = Held::new(inject);
+            start = open(gate);
+
+            let mut next = first;
+            let mut done = 0;
+            while done < count {
+                while next < end {
+                    let Some(slot) = free.pop() else {
+                        break;
+                    };
+                    let pos = offsets.get(next);
+                    let slice =
+                        unsafe { std::slice::from_raw_parts_mut(bufs[slot], block_size as usize) };
+                    fill_block(slice, pos / 64);
+                    let write_e = opcode::WriteFixed::new(
+                        types::Fixed(0),
+                        bufs[slot],
+                        block_size as _,
+                        slot as u16,
+                    )
+                    .offset(pos)
+                    .build()
+                    .user_data(slot as u64);
+
+                    // The buffer stays registered and unused until the
+                    // write completes.
+                    unsafe {
+                        ring.submission()
+                            .push(&write_e)
+                            .expect("submission queue is full");
+                    }
+                    slots[slot] = (next, Instant::now());
+                    next += 1;
                 }
+
+                let in_flight = MAX_DEPTH - free.len() > held.len();
+                ring::wait(&mut ring, in_flight, held.next_due())?;
+
+                let now = Instant::now();
+                for cqe in ring.completion() {
+                    held.hold(slots[cqe.user_data() as usize].0, now, cqe);
+                }
+                while let Some(cqe) = held.release(Instant::now()) {
+                    let slot = cqe.user_data() as usize;
+                    let (block, issued) = slots[slot];
+                    let result = cqe.result();
+                    trace!("write result: {result} @ {block}");
+                    if result < 0 {
+                        let err = std::io::Error::from_raw_os_error(-result);
+                        op_error("write", block, offsets, err);
+                        errors += 1;
+                    } else if result as u64 != block_size {
+                        let err = anyhow::anyhow!("short write of {result} bytes");
+                        op_error("write", block, offsets, err);
+                        errors += 1;
+                    }
+                    written += result.max(0) as usize;
+                    let slice =
+                        unsafe { std::slice::from_raw_parts(bufs[slot], block_size as usize) };
+                    complete(block, slice, issued.elapsed());
+                    free.push(slot);
+                    done += 1;
+                }
+            }
+
+            counters = Some(RingCounters::of(&mut ring));
+            drop(ring);
+            for buf in bufs {
+                mem_aligned_free(buf, block_size as usize, 4096);
             }
         }
     }
 
-    let elapsed = start.elapsed().as_secs_f64();
+    if errors > 0 {
+        return Err(anyhow::anyhow!(
+            "{errors}/{count} writes failed{}",
+            if tracing::enabled!(Level::DEBUG) {
+                ""
+            } else {
+                " (use -vv for details)"
+            },
+        ));
+    }
 
-    let speed = (block_size * count) as f64 / elapsed;
-    println!(
-        "writen {}/{} bytes in {:.6} seconds @ {}/s",
-        written,
-        block_size * count,
+    let elapsed = start.elapsed();
+    Ok(Stats {
+        bytes: block_size * count,
+        transferred: written as u64,
+        ops: count,
         elapsed,
-        ISizeFormatter::new(speed, BINARY),
-    );
-
-    Ok(())
+        latency: progress.take_latency(),
+        timeline: progress.take_timeline(),
+        soak: progress.take_soak(),
+        in_flight: progress.take_in_flight(),
+        ring: counters,
+        mismatches: 0,
+        compute: compute_time,
+        faults: gate.faults(),
+        usage: gate.usage(),
+        workers: Vec::new(),
+        pressure: gate.pressure(elapsed),
+    })
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn read_file(
-    file: &str,
-    block_size: u64,
-    count: u64,
+    path: &str,
+    flags: i32,
+    offsets: &Offsets,
+    blocks: Range<u64>,
     strategy: Strategy,
-    verbose: bool,
-) -> Result<()> {
+    compute: Option<Compute>,
+    faults: Option<Faults>,
+    mut verify: Option<Verify<'_>>,
+    progress: &mut Progress,
+    gate: &mut StartGate<'_>,
+) -> Result<Stats> {
+    let block_size = offsets.block_size();
+    let Range { start: first, end } = blocks;
+    let count = end - first;
+    let (entries, depth) = strategy.queue().unwrap_or_default();
+    let mut read = 0;
+    let mut mismatches = 0;
+    let mut counters = None;
+    let mut compute_time = Duration::ZERO;
+    let inject = progress.injected_latency();
+    // When the gate opened, for the progress clocks to start then.
+    let opened = Cell::new(None);
+    let open = |gate: &mut StartGate| {
+        let start = gate.open();
+        opened.set(Some(start));
+        start
+    };
+    let mut check = |i: u64, data: &[u8], latency: Duration| {
+        let pos = offsets.get(i);
+        if verify.as_mut().is_some_and(|verify| !verify(pos, data)) {
+            debug!("verification of block {i} (offset {pos}) failed");
+            mismatches += 1;
+        }
+        if let Some(compute) = compute {
+            let started = Instant::now();
+            compute.run(data);
+            compute_time += started.elapsed();
+        }
+        if let Some(start) = opened.take() {
+            progress.restart(start);
+        }
+        progress.add(i, data.len() as u64, latency);
+    };
+
+    let alignment = (flags & libc::O_DIRECT != 0)
+        .then(|| Alignment::of(path))
+        .flatten();
+    let misaligned = |err, buf: *const u8, block: u64| {
+        let err = Misaligned::explain(err, alignment, buf, offsets.get(block), block_size);
+        op_error("read", block, offsets, err)
+    };
+
+    let start;
+    match strategy {
+        Strategy::Std => {
+            let file = open_read(path, flags)?;
+
+            let buf = mem_aligned(block_size as usize, 4096)?;
+            prefault(buf, block_size as usize);
+            let slice = unsafe { std::slice::from_raw_parts_mut(buf, block_size as usize) };
+            start = open(gate);
+            for i in first..end {
+                let issued = Instant::now();
+                usage::read_exact_at(&file, slice, offsets.get(i))
+                    .map_err(|err| misaligned(err, buf, i))?;
+                thread::sleep(inject::delay(inject, i));
+                let latency = issued.elapsed();
+                read += block_size as usize;
+                check(i, slice, latency);
+            }
+            mem_aligned_free(buf, block_size as usize, 4096);
+        }
+        Strategy::Sequential => {
+            let file = open_read_async(path, flags).await?;
+
+            let mut buf = Vec::with_capacity(block_size as usize);
+            prefault(buf.as_mut_ptr(), block_size as usize);
+            start = open(gate);
+            for i in first..end {
+                buf.clear();
+                let delay = inject::delay(inject, i);
+                let ((res, next), latency) =
+                    timed(delayed(file.read_exact_at(buf, offsets.get(i)), delay)).await;
+                res.map_err(|err| misaligned(err, next.as_ptr(), i))?;
+                read += next.len();
+                check(i, &next, latency);
+                buf = next;
+            }
+        }
+        Strategy::Async => {
+            let file = Rc::new(open_read_async(path, flags).await?);
+            start = open(gate);
+
+            let mut handles = Vec::with_capacity(count as usize);
+            for i in first..end {
+                let file = Rc::clone(&file);
+                let pos = offsets.get(i);
+                let delay = inject::delay(inject, i);
+                handles.push(monoio::spawn(async move {
+                    let buf = Vec::with_capacity(block_size as usize);
+                    timed(delayed(file.read_exact_at(buf, pos), delay)).await
+                }));
+            }
+            for (i, handle) in (first..).zip(handles) {
+                let ((res, buf), latency) = handle.await;
+                res.map_err(|err| misaligned(err, buf.as_ptr(), i))?;
+                read += buf.len();
+                check(i, &buf, latency);
+            }
+        }
+        Strategy::Async2 => {
+            let file = Rc::new(open_read_async(path, flags).await?);
+            start = open(gate);
+
+            let spawn = |i: u64| {
+                let file = Rc::clone(&file);
+                let pos = offsets.get(i);
+                let delay = inject::delay(inject, i);
+                monoio::spawn(async move {
+                    let buf = Vec::with_capacity(block_size as usize);
+                    timed(delayed(file.read_exact_at(buf, pos), delay)).await
+                })
+            };
+
+            if count > 0 {
+                let mut current = spawn(first);
+                for i in first + 1..end {
+                    let next = spawn(i);
+                    let ((res, buf), latency) = current.await;
+                    res.map_err(|err| misaligned(err, buf.as_ptr(), i - 1))?;
+                    read += buf.len();
+                    check(i - 1, &buf, latency);
+                    current = next;
+                }
+                let ((res, buf), latency) = current.await;
+                res.map_err(|err| misaligned(err, buf.as_ptr(), end - 1))?;
+                read += buf.len();
+                check(end - 1, &buf, latency);
+            }
+        }
+        Strategy::IOUring
+        | Strategy::IOUring2
+        | Strategy::IOUring8
+        | Strategy::IOUringEventfd
+        | Strategy::Null
+        | Strategy::Faulty => {
+            let mut ring = ring::new_ring(entries)?;
+            let mut notify = (strategy == Strategy::IOUringEventfd)
+                .then(|| EventFd::register(&ring))
+                .transpose()?;
+
+            let file = open_read(path, flags)?;
+            let fd = types::Fd(file.as_raw_fd());
+
+            // Each in-flight read owns one buffer slot, `user_data` is the slot.
+            let mut bufs = Vec::with_capacity(depth);
+            for _ in 0..depth {
+                let buf = mem_aligned(block_size as usize, 4096)?;
+                prefault(buf, block_size as usize);
+                bufs.push(buf);
+            }
+            let mut slots = vec![(0, Instant::now()); depth];
+            let mut free = (0..depth).rev().collect::<Vec<_>>();
+            let mut held = Held::new(inject);
+            start = open(gate);
+
+            let mut next = first;
+            let mut done = 0;
+            // Submitted or queued reads whose completions were not reaped yet.
+            let mut in_flight = 0;
+            let res = async {
+                while done < count {
+                    while next < end {
+                        let Some(slot) = free.pop() else {
+                            break;
+                        };
+                        let read_e = if matches!(strategy, Strategy::Null | Strategy::Faulty) {
+                            opcode::Nop::new().build()
+                        } else {
+                            opcode::Read::new(fd, bufs[slot], block_size as _)
+                                .offset(offsets.get(next))
+                                .build()
+                        };
+                        let read_e = read_e.user_data(slot as u64);
+
+                        // Note that the developer needs to ensure
+                        // that the entry pushed into submission queue is valid (e.g. fd, buffer).
+                        unsafe {
+                            ring.submission()
+                                .push(&read_e)
+                                .expect("submission queue is full");
+                        }
+                        slots[slot] = (next, Instant::now());
+                        next += 1;
+                        in_flight += 1;
+                    }
+
+                    match held.next_due() {
+                        None => eventfd::wait(&mut ring, notify.as_mut()).await?,
+                        due => ring::wait(&mut ring, in_flight > 0, due)?,
+                    }
+
+                    let completed = ring.completion().collect::<Vec<_>>();
+                    in_flight -= completed.len();
+                    let now = Instant::now();
+                    for cqe in completed {
+                        held.hold(slots[cqe.user_data() as usize].0, now, cqe);
+                    }
+                    while let Some(cqe) = held.release(Instant::now()) {
+                        let slot = cqe.user_data() as usize;
+                        let (block, issued) = slots[slot];
+                        let result = match faults {
+                            Some(faults) => faults.result(block, block_size),
+                            None => cqe.result(),
+                        };
+                        trace!("read result: {result} @ {block}");
+                        if result < 0 {
+                            let err = std::io::Error::from_raw_os_error(-result);
+                            return Err(misaligned(err, bufs[slot], block));
+                        }
+                        if result as u64 != block_size && strategy != Strategy::Null {
+                            let err = anyhow::anyhow!("short read of {result} bytes");
+                            return Err(op_error("read", block, offsets, err));
+                        }
+
+                        let slice =
+                            unsafe { std::slice::from_raw_parts(bufs[slot], block_size as usize) };
+                        read += result as usize;
+                        check(block, slice, issued.elapsed());
+                        free.push(slot);
+                        done += 1;
+                    }
+                }
+                Ok(())
+            }
+            .await;
+            // Reads may still be in flight after a failure, the kernel would
+            // write into their buffers after they were freed.
+            if let Err(err) = ring::drain(&mut ring, in_flight) {
+                warn!("{err:#}, leaking the read buffers");
+                res?;
+                return Err(err);
+            }
+            for buf in bufs {
+                mem_aligned_free(buf, block_size as usize, 4096);
+            }
+            res?;
+            counters = Some(RingCounters::of(&mut ring));
+        }
+        Strategy::Max => {
+            let (mut ring, setup) = ring::new_max_ring(entries)?;
+            let file = open_read(path, flags)?;
+            ring.submitter()
+                .register_files(&[file.as_raw_fd()])
+                .context("failed to register the file")?;
+            let bufs = fixed_buffers(&ring, block_size)?;
+            for &buf in &bufs {
+                prefault(buf, block_size as usize);
+            }
+            debug!("max: {setup}, {MAX_DEPTH} fixed buffers, registered file");
+
+            let mut slots = vec![(0, Instant::now()); MAX_DEPTH];
+            let mut free = (0..MAX_DEPTH).rev().collect::<Vec<_>>();
+            let mut held = Held::new(inject);
+            start = open(gate);
+
+            let mut next = first;
+            let mut done = 0;
+            let mut in_flight = 0;
+            let res = (|| {
+                while done < count {
+                    while next < end {
+                        let Some(slot) = free.pop() else {
+                            break;
+                        };
+                        let read_e = opcode::ReadFixed::new(
+                            types::Fixed(0),
+                            bufs[slot],
+                            block_size as _,
+                            slot as u16,
+                        )
+                        .offset(offsets.get(next))
+                        .build()
+                        .user_data(slot as u64);
+
+                        // The buffer stays registered and unused until the read
+                        // completes.
+                        unsafe {
+                            ring.submission()
+                                .push(&read_e)
+                                .expect("submission queue is full");
+                        }
+                        slots[slot] = (next, Instant::now());
+                        next += 1;
+                        in_flight += 1;
+                    }
+
+                    ring::wait(&mut ring, in_flight > 0, held.next_due())?;
+
+                    let completed = ring.completion().collect::<Vec<_>>();
+                    in_flight -= completed.len();
+                    let now = Instant::now();
+                    for cqe in completed {
+                        held.hold(slots[cqe.user_data() as usize].0, now, cqe);
+                    }
+                    while let Some(cqe) = held.release(Instant::now()) {
+                        let slot = cqe.user_data() as usize;
+                        let (block, issued) = slots[slot];
+                        let result = cqe.result();
+                        trace!("read result: {result} @ {block}");
+                        if result < 0 {
+                            let err = std::io::Error::from_raw_os_error(-result);
+                            return Err(misaligned(err, bufs[slot], block));
+                        }
+                        if result as u64 != block_size {
+                            let err = anyhow::anyhow!("short read of {result} bytes");
+                            return Err(op_error("read", block, offsets, err));
+                        }
+
+                        let slice =
+                            unsafe { std::slice::from_raw_parts(bufs[slot], block_size as usize) };
+                        read += result as usize;
+                        check(block, slice, issued.elapsed());
+                        free.push(slot);
+                        done += 1;
+                    }
+                }
+                Ok(())
+            })();
+            if let Err(err) = ring::drain(&mut ring, in_flight) {
+                warn!("{err:#}, leaking the read buffers");
+                res?;
+                return Err(err);
+            }
+            counters = Some(RingCounters::of(&mut ring));
+            drop(ring);
+            for buf in bufs {
+                mem_aligned_free(buf, block_size as usize, 4096);
+            }
+            res?;
+        }
+    }
+
+    let elapsed = start.elapsed();
+
+    Ok(Stats {
+        bytes: block_size * count,
+        transferred: read as u64,
+        ops: count,
+        elapsed,
+        latency: progress.take_latency(),
+        timeline: progress.take_timeline(),
+        soak: progress.take_soak(),
+        in_flight: progress.take_in_flight(),
+        ring: counters,
+        mismatches,
+        compute: compute_time,
+        faults: gate.faults(),
+        usage: gate.usage(),
+        workers: Vec::new(),
+        pressure: gate.pressure(elapsed),
+    })
+}
+
+/// Bytes written by the probe of `--estimate`.
+const PROBE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Writes the first blocks of `blocks` with one worker and prints how long
+/// all of them take at that rate.
+async fn estimate(job: &Job, mode: Mode, blocks: Range<u64>) -> Result<()> {
+    let block_size = job.block_size;
+    let probe = (PROBE_BYTES / block_size).clamp(1, blocks.end - blocks.start);
+    let offsets = Offsets::new(block_size, mode, 0, 0..block_size * job.count);
+    let mut progress = Progress::new(probe * block_size);
+    let stats = write_file(
+        &job.file,
+        &offsets,
+        blocks.start..blocks.start + probe,
+        job.strategy,
+        job.compute_per_block,
+        job.faults,
+        &mut progress,
+        &mut StartGate::none(),
+    )
+    .await
+    .context("failed to write the probe for --estimate")?;
+    let rate = stats.bytes_per_sec();
+    println!(
+        "estimated duration: {} at {}/s, measured over {probe} blocks",
+        Short(Duration::from_secs_f64(
+            ((blocks.end - blocks.start) * block_size) as f64 / rate
+        )),
+        ISizeFormatter::new(rate, BINARY),
+    );
     Ok(())
 }
 
+/// Awaits `fut` and holds its output for an injected `delay` on the
+/// runtime's timer, so that the other tasks keep running meanwhile.
+async fn delayed<T>(fut: impl Future<Output = T>, delay: Duration) -> T {
+    let output = fut.await;
+    if !delay.is_zero() {
+        monoio::time::sleep(delay).await;
+    }
+    output
+}
+
+/// Awaits `fut` and measures how long it took to complete.
+async fn timed<T>(fut: impl Future<Output = T>) -> (T, Duration) {
+    let issued = Instant::now();
+    let output = fut.await;
+    (output, issued.elapsed())
+}
+
 fn make_block(block_size: u64, idx: u64) -> Vec<u8> {
     let mut data = vec![0u8; block_size as usize];
+    fill_block(&mut data, idx);
+    data
+}
 
-    for i in 0..block_size as usize / 64 {
+/// Writes the pattern of [`make_block`] into a zeroed or previously filled
+/// buffer.
+fn fill_block(data: &mut [u8], idx: u64) {
+    for i in 0..data.len() / 64 {
         data[i * 64..i * 64 + 8].copy_from_slice(&u64::to_le_bytes(idx + i as u64));
     }
+}
 
-    data
+/// Blocks in flight with `--strategy max`.
+const MAX_DEPTH: usize = 32;
+
+/// Allocates [`MAX_DEPTH`] zeroed, aligned buffers and registers them with
+/// `ring`. They have to be freed after the ring is dropped, and are freed
+/// here if registering them fails.
+fn fixed_buffers(ring: &IoUring, block_size: u64) -> Result<Vec<*mut u8>> {
+    let free = |bufs: Vec<*mut u8>| {
+        for buf in bufs {
+            mem_aligned_free(buf, block_size as usize, 4096);
+        }
+    };
+    let mut bufs = Vec::with_capacity(MAX_DEPTH);
+    for _ in 0..MAX_DEPTH {
+        match mem_aligned(block_size as usize, 4096) {
+            Result::Ok(buf) => bufs.push(buf),
+            Err(err) => {
+                free(bufs);
+                return Err(err);
+            }
+        }
+    }
+    let iovecs = bufs
+        .iter()
+        .map(|&buf| libc::iovec {
+            iov_base: buf.cast(),
+            iov_len: block_size as usize,
+        })
+        .collect::<Vec<_>>();
+    if let Err(err) = unsafe { ring::register_buffers(ring, &iovecs) } {
+        free(bufs);
+        return Err(err);
+    }
+    Ok(bufs)
 }
 
 fn make_block_mem_aligned(block_size: u64, idx: u64) -> Result<*mut u8> {
-    let mut ptr = mem_aligned(block_size as usize, 4096)?;
+    let ptr = mem_aligned(block_size as usize, 4096)?;
 
     let slice = unsafe { std::slice::from_raw_parts_mut(ptr, block_size as usize) };
-    for i in 0..block_size as usize / 64 {
-        slice[i * 64..i * 64 + 8].copy_from_slice(&u64::to_le_bytes(idx + i as u64));
-    }
+    fill_block(slice, idx);
 
     Ok(ptr)
 }
 
+/// Opens `path` for reading with the extra open `flags`.
+fn open_read(path: &str, flags: i32) -> Result<fs::File> {
+    fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(flags)
+        .open(path)
+        .map_err(|err| open_error(path, flags, err))
+}
+
+async fn open_read_async(path: &str, flags: i32) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(flags)
+        .open(path)
+        .await
+        .map_err(|err| open_error(path, flags, err))
+}
+
+fn open_error(path: &str, flags: i32, err: io::Error) -> anyhow::Error {
+    if flags & libc::O_NOATIME != 0 && err.kind() == io::ErrorKind::PermissionDenied {
+        anyhow::Error::new(err).context(format!(
+            "failed to open {path} with O_NOATIME, which requires owning the file or CAP_FOWNER"
+        ))
+    } else {
+        anyhow::Error::new(err).context(format!("failed to open {path}"))
+    }
+}
+
+/// Adds the failed operation to `err` and logs it right away since some
+/// strategies keep going after a failed operation.
+fn op_error(
+    op: &str,
+    block: u64,
+    offsets: &Offsets,
+    err: impl Into<anyhow::Error>,
+) -> anyhow::Error {
+    let err = err.into().context(format!(
+        "{op} of block {block} (offset {}, {} bytes) failed",
+        offsets.get(block),
+        offsets.block_size(),
+    ));
+    debug!("{err:#}");
+    events::emit(Event::Error {
+        message: format!("{err:#}"),
+    });
+    err
+}
+
+/// A [`Verify`] check for blocks written by `raio write`.
+fn matches_pattern(pos: u64, data: &[u8]) -> bool {
+    verify_block(data, pos / 64)
+}
+
+/// Checks a block against the pattern written by [`make_block`].
+fn verify_block(data: &[u8], idx: u64) -> bool {
+    data.chunks(64).enumerate().all(|(i, chunk)| {
+        let (head, tail) = chunk.split_at(usize::min(8, chunk.len()));
+        let expected = if chunk.len() == 64 {
+            u64::to_le_bytes(idx + i as u64)
+        } else {
+            [0; 8]
+        };
+        head == &expected[..head.len()] && tail.iter().all(|&b| b == 0)
+    })
+}
+
 fn mem_aligned(size: usize, align: usize) -> Result<*mut u8> {
     let layout = std::alloc::Layout::from_size_align(size, align).context("invalid layout")?;
-    let ptr = unsafe { std::alloc::alloc(layout) };
+    let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
     if ptr.is_null() {
         Err(anyhow::anyhow!("failed to allocate memory"))
     } else {
+        memlock::lock(ptr, size);
         Ok(ptr)
     }
 }
 
+/// Touches every page of a buffer, so that its first use in the timed phase
+/// does not fault.
+fn prefault(ptr: *mut u8, size: usize) {
+    for offset in (0..size).step_by(4096) {
+        unsafe { ptr.add(offset).write_volatile(0) };
+    }
+}
+
 fn mem_aligned_free(ptr: *mut u8, size: usize, align: usize) {
     let layout = std::alloc::Layout::from_size_align(size, align).unwrap();
+    memlock::unlock(ptr, size);
     unsafe { std::alloc::dealloc(ptr, layout) }
 }
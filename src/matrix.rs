@@ -0,0 +1,141 @@
+//! The same job against several targets and strategies, one run after
+//! another, for ranking disks or cloud volumes against each other.
+
+use crate::{device::Device, latency::Short, results::Results, stream, Cmd, Job, Strategy};
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+use std::time::Duration;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Op {
+    Write,
+    Read,
+}
+
+/// Runs `job` once per target in its comma-separated `file` and strategy in
+/// `strategies`, or its own strategy without any, and prints the throughput,
+/// p99 latency, CPU time per GiB and bytes per system call of every run as
+/// tables of targets by strategies.
+pub async fn run(
+    cmd: &Cmd,
+    op: Op,
+    strategies: &[Strategy],
+    verify: bool,
+    job: &Job,
+) -> Result<()> {
+    let targets = job
+        .file
+        .split(',')
+        .filter(|target| !target.is_empty())
+        .collect::<Vec<_>>();
+    if targets.is_empty() {
+        bail!("no targets, expected e.g. -f /mnt/a/raio,/mnt/b/raio");
+    }
+    if targets.contains(&stream::STDIO) {
+        bail!("stdin and stdout cannot be matrix targets");
+    }
+    if job.output.is_some() || job.report.is_some() {
+        bail!("--output and --report record single runs, not a matrix");
+    }
+    if job.dry_run || job.auto_bs.is_some() {
+        bail!("--dry-run and --auto-bs replace single runs, not a matrix");
+    }
+    let strategies = match strategies {
+        [] => vec![job.strategy],
+        strategies => strategies.to_vec(),
+    };
+
+    let mut rows = Vec::with_capacity(targets.len());
+    let mut failures = 0;
+    for target in &targets {
+        let label = match Device::for_path(target) {
+            Ok(Some(device)) => format!("{target} ({})", device.name),
+            _ => target.to_string(),
+        };
+        let mut cells = Vec::with_capacity(strategies.len());
+        for &strategy in &strategies {
+            println!("== {strategy} on {label}");
+            let job = Job {
+                file: target.to_string(),
+                strategy,
+                ..job.clone()
+            };
+            let res = cmd.bench(&job, op == Op::Write, verify).await;
+            println!();
+            match res {
+                // Only dry runs and streams have no results.
+                Ok(results) => cells.push(results),
+                Err(err) => {
+                    warn!("{strategy} on {target} failed: {err:#}");
+                    failures += 1;
+                    cells.push(None);
+                }
+            }
+        }
+        rows.push((label, cells));
+    }
+
+    let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    let header = || {
+        let mut line = format!("{:<width$}", "target");
+        for strategy in &strategies {
+            line += &format!(" {:>15}", strategy.as_str());
+        }
+        line
+    };
+    let table = |title: &str, cell: &dyn Fn(&Results) -> String| {
+        println!("{title}:");
+        println!("{}", header());
+        for (label, cells) in &rows {
+            let mut line = format!("{label:<width$}");
+            for results in cells {
+                let text = results.as_ref().map_or("failed".to_string(), cell);
+                line += &format!(" {text:>15}");
+            }
+            println!("{line}");
+        }
+    };
+    table("throughput", &|results| {
+        format!("{}/s", ISizeFormatter::new(results.bytes_per_sec, BINARY))
+    });
+    println!();
+    table("p99 latency", &|results| match &results.latency {
+        Some(latency) => Short(Duration::from_secs_f64(latency.p99_us / 1e6)).to_string(),
+        None => "-".to_string(),
+    });
+    println!();
+    table("CPU per GiB", &|results| match &results.efficiency {
+        Some(efficiency) => format!("{:.1}ms", efficiency.cpu_ms_per_gib),
+        None => "-".to_string(),
+    });
+    println!();
+    table("bytes per syscall", &|results| {
+        let bytes = results.efficiency.and_then(|e| e.bytes_per_syscall);
+        bytes.map_or("-".to_string(), |bytes| {
+            SizeFormatter::new(bytes as u64, BINARY).to_string()
+        })
+    });
+
+    let best = rows
+        .iter()
+        .flat_map(|(label, cells)| cells.iter().zip(&strategies).map(move |c| (label, c)))
+        .filter_map(|(label, (results, strategy))| Some((label, strategy, results.as_ref()?)))
+        .max_by(|a, b| a.2.bytes_per_sec.total_cmp(&b.2.bytes_per_sec));
+    if let Some((label, strategy, results)) = best {
+        println!();
+        println!(
+            "fastest: {strategy} on {label} @ {}/s",
+            ISizeFormatter::new(results.bytes_per_sec, BINARY)
+        );
+    }
+
+    if failures > 0 {
+        bail!(
+            "{failures}/{} runs failed",
+            targets.len() * strategies.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! Locking the I/O buffers into memory, so page faults and swapping stay out
+//! of the timed phase on hosts under memory pressure.
+
+use crate::ring;
+use anyhow::{bail, Result};
+use humansize::{SizeFormatter, BINARY};
+use std::{collections::HashSet, fmt, io, sync::Mutex};
+use tracing::debug;
+
+static LOCKS: Mutex<Option<Locks>> = Mutex::new(None);
+
+/// The buffers locked so far, from [`enable`] on.
+#[derive(Debug, Default)]
+struct Locks {
+    buffers: HashSet<usize>,
+    bytes: u64,
+    peak: u64,
+    locked: u64,
+    failed: u64,
+    error: Option<io::Error>,
+}
+
+/// Locks every I/O buffer allocated from now on with `mlock`, after
+/// checking that the `required` bytes of buffers in use at once fit into
+/// `RLIMIT_MEMLOCK` (`ulimit -l`), which is raised to its hard limit if
+/// needed. Other memory stays unlocked, so allocations outside the buffers
+/// never run into the limit.
+pub fn enable(required: u64) -> Result<()> {
+    *LOCKS.lock().unwrap() = None;
+    if !ring::raise_memlock(required)? {
+        let (soft, _) = ring::memlock_limit()?;
+        bail!(
+            "the buffers need {} of locked memory but RLIMIT_MEMLOCK allows {} \
+             (check `ulimit -l`)",
+            SizeFormatter::new(required, BINARY),
+            SizeFormatter::new(soft.unwrap_or(0), BINARY),
+        );
+    }
+    *LOCKS.lock().unwrap() = Some(Locks::default());
+    Ok(())
+}
+
+/// Locks the buffer of `size` bytes at `ptr` if locking is enabled. A
+/// failure is counted and reported after the run instead of failing it.
+pub fn lock(ptr: *mut u8, size: usize) {
+    let mut locks = LOCKS.lock().unwrap();
+    let Some(locks) = locks.as_mut() else {
+        return;
+    };
+    if unsafe { libc::mlock(ptr.cast(), size) } < 0 {
+        let err = io::Error::last_os_error();
+        debug!("failed to lock a buffer of {size} bytes: {err}");
+        locks.failed += 1;
+        locks.error = Some(err);
+        return;
+    }
+    locks.buffers.insert(ptr as usize);
+    locks.locked += 1;
+    locks.bytes += size as u64;
+    locks.peak = locks.peak.max(locks.bytes);
+}
+
+/// Unlocks the buffer of `size` bytes at `ptr` before it is freed, if it
+/// was locked.
+pub fn unlock(ptr: *mut u8, size: usize) {
+    let mut locks = LOCKS.lock().unwrap();
+    let Some(locks) = locks.as_mut() else {
+        return;
+    };
+    if locks.buffers.remove(&(ptr as usize)) {
+        unsafe { libc::munlock(ptr.cast(), size) };
+        locks.bytes -= size as u64;
+    }
+}
+
+/// How locking went so far, `None` if it is not enabled.
+pub fn outcome() -> Option<Outcome> {
+    let locks = LOCKS.lock().unwrap();
+    let locks = locks.as_ref()?;
+    Some(Outcome {
+        locked: locks.locked,
+        peak: locks.peak,
+        failed: locks.failed,
+        error: locks.error.as_ref().map(ToString::to_string),
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    /// Buffers locked.
+    pub locked: u64,
+    /// Most bytes locked at once.
+    pub peak: u64,
+    /// Buffers that could not be locked, and the last error.
+    pub failed: u64,
+    pub error: Option<String>,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} buffers locked, at most {} at once",
+            self.locked,
+            SizeFormatter::new(self.peak, BINARY),
+        )?;
+        if self.failed > 0 {
+            write!(f, ", {} failed", self.failed)?;
+            if let Some(error) = &self.error {
+                write!(f, " ({error})")?;
+            }
+        }
+        Ok(())
+    }
+}
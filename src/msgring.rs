@@ -0,0 +1,109 @@
+//! IORING_OP_MSG_RING between two rings on two threads, the way work is
+//! handed between per-core rings in sharded architectures.
+
+use crate::{latency::Latency, ring};
+use anyhow::{anyhow, bail, Context, Result};
+use io_uring::{opcode, squeue::Flags, types, IoUring, Probe};
+use std::{os::unix::io::AsRawFd, thread, time::Instant};
+use tracing::{debug, info};
+
+/// `user_data` of the sends, whose completions are only posted on failure.
+const SEND: u64 = u64::MAX;
+
+/// Sends `count` messages from a client ring to a server ring, which echoes
+/// each one back, with up to `depth` messages on their way at a time.
+pub fn run(count: u64, depth: u64) -> Result<()> {
+    let entries = (depth as u32 * 2).next_power_of_two();
+    let mut client = ring::new_ring(entries)?;
+    let server = ring::new_ring(entries)?;
+    let mut probe = Probe::new();
+    client
+        .submitter()
+        .register_probe(&mut probe)
+        .context("failed to probe io_uring opcodes")?;
+    if !probe.is_supported(opcode::MsgRingData::CODE) {
+        bail!("IORING_OP_MSG_RING is not supported by this kernel, it requires Linux 5.18");
+    }
+    info!("sending {count} messages between two rings, {depth} in flight");
+
+    let client_fd = client.as_raw_fd();
+    let server_fd = server.as_raw_fd();
+    let echo = thread::spawn(move || echo(server, client_fd, count));
+    // On failure the server is left waiting for the rest of the messages, the
+    // process exits with the error right after.
+    ping(&mut client, server_fd, count, depth)?;
+    echo.join().expect("server thread panicked")
+}
+
+fn ping(ring: &mut IoUring, server_fd: i32, count: u64, depth: u64) -> Result<()> {
+    let mut issued = vec![Instant::now(); count as usize];
+    let mut latency = Latency::default();
+    let mut sent = 0;
+    let mut received = 0;
+    let start = Instant::now();
+    while received < count {
+        while sent < count && sent - received < depth {
+            issued[sent as usize] = Instant::now();
+            send(ring, server_fd, sent);
+            sent += 1;
+        }
+        ring.submit_and_wait(1)?;
+
+        for cqe in ring.completion().collect::<Vec<_>>() {
+            if cqe.user_data() == SEND {
+                return Err(send_error(cqe.result()));
+            }
+            latency.record(issued[cqe.user_data() as usize].elapsed());
+            received += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    // Every round trip carries two messages.
+    let messages = 2 * count;
+    println!(
+        "exchanged {messages} messages in {:.6} seconds @ {:.0} messages/s",
+        elapsed.as_secs_f64(),
+        messages as f64 / elapsed.as_secs_f64(),
+    );
+    println!("round trip: {latency}");
+    Ok(())
+}
+
+fn echo(mut ring: IoUring, client_fd: i32, count: u64) -> Result<()> {
+    let mut echoed = 0;
+    let mut wakeups = 0;
+    while echoed < count {
+        ring.submit_and_wait(1)?;
+        wakeups += 1;
+
+        for cqe in ring.completion().collect::<Vec<_>>() {
+            if cqe.user_data() == SEND {
+                return Err(send_error(cqe.result()));
+            }
+            send(&mut ring, client_fd, cqe.user_data());
+            echoed += 1;
+        }
+    }
+    ring.submit()?;
+    debug!("server woke up {wakeups} times for {count} messages");
+    Ok(())
+}
+
+fn send(ring: &mut IoUring, fd: i32, user_data: u64) {
+    let entry = opcode::MsgRingData::new(types::Fd(fd), 0, user_data, None)
+        .build()
+        .flags(Flags::SKIP_SUCCESS)
+        .user_data(SEND);
+    // Every message is received before it is sent on, so the submission
+    // queue never holds more than `depth` entries.
+    unsafe {
+        ring.submission()
+            .push(&entry)
+            .expect("submission queue is full");
+    }
+}
+
+fn send_error(result: i32) -> anyhow::Error {
+    anyhow!(std::io::Error::from_raw_os_error(-result)).context("MSG_RING failed")
+}
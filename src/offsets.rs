@@ -0,0 +1,268 @@
+//! Maps the n-th operation of a job to the file offset it transfers.
+
+use crate::parse;
+use anyhow::{bail, Context, Result};
+use std::{fs, ops::Range, path::Path, sync::Arc};
+
+/// How offsets are picked, independent of the range they are picked from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Sequential,
+    /// Uniformly random offsets that are multiples of `align`.
+    Random {
+        seed: u64,
+        align: u64,
+    },
+    /// Like `Random`, but every slot is transferred exactly once per pass, in
+    /// the same shuffled order each pass.
+    RandomMap {
+        seed: u64,
+        align: u64,
+    },
+    /// The offsets of an `--offsets` file in its order, one per operation.
+    List {
+        offsets: Arc<[u64]>,
+    },
+}
+
+/// Offsets are computed from the operation index alone, so any operation can
+/// be reproduced from the seed without replaying the ones before it. This
+/// keeps random runs replayable and resumable regardless of the order in
+/// which a strategy completes operations.
+#[derive(Debug, Clone)]
+pub struct Offsets {
+    block_size: u64,
+    first: u64,
+    base: u64,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Sequential,
+    Random {
+        seed: u64,
+        slots: u64,
+        align: u64,
+    },
+    /// A shuffled list of all slots, 8 bytes per slot.
+    Map {
+        order: Arc<[u64]>,
+        align: u64,
+    },
+    /// Absolute offsets, independent of the region.
+    List {
+        offsets: Arc<[u64]>,
+    },
+}
+
+impl Offsets {
+    /// Offsets for the operations starting at `first`, with every block
+    /// within `region`. Sequential offsets start at the beginning of the
+    /// region with operation `first`.
+    pub fn new(block_size: u64, mode: Mode, first: u64, region: Range<u64>) -> Self {
+        let size = region.end - region.start;
+        let kind = match mode {
+            Mode::Sequential => Kind::Sequential,
+            Mode::Random { seed, align } => Kind::Random {
+                seed,
+                slots: slots(block_size, size, align),
+                align,
+            },
+            Mode::RandomMap { seed, align } => {
+                let mut order = (0..slots(block_size, size, align)).collect::<Vec<_>>();
+                let mut state = seed;
+                for i in (1..order.len()).rev() {
+                    state = state.wrapping_add(GOLDEN_GAMMA);
+                    let j = ((splitmix64(state) as u128 * (i as u128 + 1)) >> 64) as usize;
+                    order.swap(i, j);
+                }
+                Kind::Map {
+                    order: order.into(),
+                    align,
+                }
+            }
+            Mode::List { offsets } => Kind::List { offsets },
+        };
+        Self {
+            block_size,
+            first,
+            base: region.start,
+            kind,
+        }
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    pub fn get(&self, i: u64) -> u64 {
+        let offset = match self.kind {
+            Kind::Sequential => (i - self.first) * self.block_size,
+            Kind::Random { seed, slots, align } => {
+                let x = splitmix64(seed.wrapping_add(i.wrapping_mul(GOLDEN_GAMMA)));
+                let slot = ((x as u128 * slots as u128) >> 64) as u64;
+                slot * align
+            }
+            Kind::Map { ref order, align } => order[(i % order.len() as u64) as usize] * align,
+            Kind::List { ref offsets } => return offsets[i as usize],
+        };
+        self.base + offset
+    }
+}
+
+/// Reads an `--offsets` file of `offset size` pairs, one per line and
+/// separated by whitespace or a comma, with `#` starting a comment. Returns
+/// the size, which has to be the same for every pair, and the offsets.
+pub fn load(path: &Path) -> Result<(u64, Arc<[u64]>)> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut size = None;
+    let mut offsets = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let context = || format!("{}:{}", path.display(), i + 1);
+        let mut fields = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty());
+        let (Some(offset), Some(len), None) = (fields.next(), fields.next(), fields.next()) else {
+            bail!("{}: expected `offset size`, got `{line}`", context());
+        };
+        let offset = offset
+            .parse::<u64>()
+            .map_err(|_| anyhow::anyhow!("invalid offset `{offset}`"))
+            .with_context(context)?;
+        let len = parse::size(len)
+            .map_err(anyhow::Error::msg)
+            .with_context(context)?;
+        match size {
+            None => size = Some(len),
+            Some(size) if size != len => bail!(
+                "{}: size {len} differs from the size {size} of the pairs before, \
+                 all operations of a run transfer the same number of bytes, \
+                 split the file by size",
+                context()
+            ),
+            Some(_) => {}
+        }
+        offsets.push(offset);
+    }
+    let Some(size) = size else {
+        bail!("{} lists no offsets", path.display());
+    };
+    Ok((size, offsets.into()))
+}
+
+/// Offsets `align` apart that a block fits at within `size` bytes.
+pub fn slots(block_size: u64, size: u64, align: u64) -> u64 {
+    size.saturating_sub(block_size) / align + 1
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The output function of SplitMix64, also used to derive fresh seeds.
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(GOLDEN_GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A seed for runs that did not ask for a specific one.
+pub fn random_seed() -> u64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    splitmix64(nanos ^ (u64::from(std::process::id()) << 32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes `text` to a file of its own in the temp directory.
+    fn file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("raio-offsets-{}-{name}", std::process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn load_text(name: &str, text: &str) -> Result<(u64, Vec<u64>)> {
+        let path = file(name, text);
+        let res = load(&path);
+        fs::remove_file(&path).unwrap();
+        res.map(|(size, offsets)| (size, offsets.to_vec()))
+    }
+
+    #[test]
+    fn load_pairs() {
+        let text = "# offset size\n0 4k\n\n8192,4096  # second\n  4096 , 4KiB\n";
+        let (size, offsets) = load_text("pairs", text).unwrap();
+        assert_eq!(size, 4096);
+        assert_eq!(offsets, [0, 8192, 4096]);
+    }
+
+    #[test]
+    fn load_mixed_sizes() {
+        let err = load_text("mixed", "0 4k\n4096 8k\n").unwrap_err();
+        assert!(
+            format!("{err:#}").contains(":2: size 8192 differs"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn load_empty() {
+        for (name, text) in [("empty", ""), ("comments", "# nothing\n\n  # here\n")] {
+            let err = load_text(name, text).unwrap_err();
+            assert!(err.to_string().ends_with("lists no offsets"), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn load_invalid_lines() {
+        for (name, text) in [
+            ("single", "0\n"),
+            ("triple", "0 4k 1\n"),
+            ("offset", "-1 4k\n"),
+            ("size", "0 0\n"),
+            ("suffix", "0 4x\n"),
+            ("overflow", "18446744073709551616 4k\n"),
+        ] {
+            let err = load_text(name, text).unwrap_err();
+            assert!(format!("{err:#}").contains(":1"), "{name}: {err:#}");
+        }
+    }
+
+    #[test]
+    fn list_ignores_region() {
+        let offsets = Offsets::new(
+            4096,
+            Mode::List {
+                offsets: vec![8192, 0].into(),
+            },
+            0,
+            4096..16384,
+        );
+        assert_eq!(offsets.get(0), 8192);
+        assert_eq!(offsets.get(1), 0);
+    }
+
+    #[test]
+    fn random_map_covers_every_slot() {
+        let mode = Mode::RandomMap {
+            seed: 7,
+            align: 4096,
+        };
+        let offsets = Offsets::new(4096, mode, 0, 4096..4096 + 16 * 4096);
+        let mut pass = (0..16).map(|i| offsets.get(i)).collect::<Vec<_>>();
+        assert_eq!(offsets.get(16), pass[0]);
+        pass.sort_unstable();
+        assert_eq!(pass, (1..17).map(|slot| slot * 4096).collect::<Vec<_>>());
+    }
+}
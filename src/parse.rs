@@ -0,0 +1,166 @@
+//! Value parsers for command line arguments.
+
+use std::time::Duration;
+
+/// Parses a byte size with an optional binary suffix, e.g. `4096`, `4k`,
+/// `64KiB` or `1M`.
+pub fn size(s: &str) -> Result<u64, String> {
+    let (number, suffix) = split_number(s);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size `{s}`, expected e.g. 4096, 4k or 1M"))?;
+    let shift = match suffix.to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => {
+            return Err(format!(
+                "invalid size suffix `{suffix}`, expected k, M, G or T"
+            ))
+        }
+    };
+    let size = number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size `{s}` is too large"))?;
+    if size == 0 {
+        return Err("size must be greater than 0".to_string());
+    }
+
+    Ok(size)
+}
+
+/// Parses a duration with a unit suffix, e.g. `500ms`, `10s`, `5m` or `1h`.
+/// Plain numbers are seconds.
+pub fn duration(s: &str) -> Result<Duration, String> {
+    let (number, suffix) = split_number(s);
+    let number = number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .ok_or_else(|| format!("invalid duration `{s}`, expected e.g. 500ms, 10s or 5m"))?;
+    let scale = match suffix {
+        "ns" => 1e-9,
+        "us" | "µs" => 1e-6,
+        "ms" => 1e-3,
+        "" | "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => {
+            return Err(format!(
+                "invalid duration unit `{suffix}`, expected ns, us, ms, s, m, h or d"
+            ))
+        }
+    };
+
+    Duration::try_from_secs_f64(number * scale).map_err(|_| format!("duration `{s}` is too long"))
+}
+
+/// Parses a ratio between 0 and 1, e.g. `0.25`.
+pub fn ratio(s: &str) -> Result<f64, String> {
+    s.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|r| (0.0..=1.0).contains(r))
+        .ok_or_else(|| format!("invalid ratio `{s}`, expected a number between 0 and 1"))
+}
+
+/// Parses a percentage, e.g. `5%` or `5`, as a ratio between 0 and 1.
+pub fn percent(s: &str) -> Result<f64, String> {
+    s.trim()
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|p| (0.0..=100.0).contains(p))
+        .map(|p| p / 100.0)
+        .ok_or_else(|| format!("invalid percentage `{s}`, expected e.g. 5%"))
+}
+
+fn split_number(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    (&s[..end], s[end..].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(size("4096"), Ok(4096));
+        assert_eq!(size("4k"), Ok(4096));
+        assert_eq!(size("64KiB"), Ok(64 << 10));
+        assert_eq!(size("1M"), Ok(1 << 20));
+        assert_eq!(size("2g"), Ok(2 << 30));
+        assert_eq!(size("1TB"), Ok(1 << 40));
+        assert_eq!(size(" 4 k "), Ok(4096));
+        assert_eq!(size("7b"), Ok(7));
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        assert!(size("").is_err());
+        assert!(size("k").is_err());
+        assert!(size("0").is_err());
+        assert!(size("0k").is_err());
+        assert!(size("1.5k").is_err());
+        assert!(size("-1").is_err());
+        assert!(size("4x").is_err());
+        assert!(size("4kk").is_err());
+    }
+
+    #[test]
+    fn size_overflow() {
+        assert_eq!(size("18446744073709551615"), Ok(u64::MAX));
+        assert!(size("18446744073709551616").is_err());
+        assert_eq!(size("16777215T"), Ok(16_777_215 << 40));
+        assert!(size("16777216T").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(duration("10s"), Ok(Duration::from_secs(10)));
+        assert_eq!(duration("10"), Ok(Duration::from_secs(10)));
+        assert_eq!(duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(duration("5min"), Ok(Duration::from_secs(300)));
+        assert_eq!(duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(duration("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(duration("250us"), Ok(Duration::from_micros(250)));
+        assert_eq!(duration("0"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        assert!(duration("").is_err());
+        assert!(duration("s").is_err());
+        assert!(duration("-1s").is_err());
+        assert!(duration("1w").is_err());
+        assert!(duration("1S").is_err());
+        assert!(duration("1e3s").is_err());
+    }
+
+    #[test]
+    fn duration_overflow() {
+        assert!(duration("100000000000000000000d").is_err());
+        assert!(duration("1000000000000000000000000000000s").is_err());
+    }
+
+    #[test]
+    fn ratios_and_percentages() {
+        assert_eq!(ratio("0.25"), Ok(0.25));
+        assert_eq!(ratio("1"), Ok(1.0));
+        assert!(ratio("1.5").is_err());
+        assert!(ratio("-0.1").is_err());
+        assert_eq!(percent("5%"), Ok(0.05));
+        assert_eq!(percent("100"), Ok(1.0));
+        assert!(percent("101%").is_err());
+        assert!(percent("%").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+//! Streams a file to another one through a processing stage, with reads and
+//! writes in flight at the same time as in ETL or transcoding tools.
+
+use crate::{compute::Compute, copy, progress::Progress, validate};
+use anyhow::Result;
+use humansize::{ISizeFormatter, BINARY};
+use tracing::info;
+
+/// Copies `src` to `dst` with `depth` buffers of `block_size` bytes,
+/// running `transform` on each block between reading and writing it.
+pub fn run(
+    src: &str,
+    dst: &str,
+    block_size: u64,
+    depth: usize,
+    transform: Option<Compute>,
+) -> Result<()> {
+    validate::block_size(block_size)?;
+    let (src_file, dst_file, len) = copy::open(src, dst)?;
+    let count = len.div_ceil(block_size);
+    info!("streaming {count} blocks of {block_size} bytes with {depth} buffers");
+    let mut progress = Progress::new(len);
+    let copied = copy::ring(
+        &src_file,
+        &dst_file,
+        len,
+        block_size,
+        depth,
+        transform,
+        &mut progress,
+    )?;
+    progress.finish(true);
+
+    let secs = copied.elapsed.as_secs_f64();
+    println!(
+        "streamed {len} bytes in {secs:.6} seconds @ {}/s",
+        ISizeFormatter::new(len as f64 / secs, BINARY),
+    );
+    if !copied.read.latency.is_empty() {
+        println!("read latency: {}", copied.read.latency);
+        println!("write latency: {}", copied.write.latency);
+    }
+    if let Some(transform) = transform {
+        println!(
+            "transform ({transform} per block): {:.6} seconds, {:.1}% of the run",
+            copied.transform.as_secs_f64(),
+            copied.transform.as_secs_f64() / secs * 100.0,
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use anyhow::{bail, Context, Result};
+use std::{fmt, io, str::FromStr};
+
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriorityClass {
+    RealTime,
+    BestEffort,
+    Idle,
+}
+
+/// I/O priority as `class:level`, e.g. `rt:0`, `be:4` or `idle`. The level
+/// ranges from 0 (highest) to 7 and is ignored for the idle class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoPriority {
+    pub class: IoPriorityClass,
+    pub level: u8,
+}
+
+impl IoPriority {
+    /// Sets the I/O priority of the calling thread. Rings and threads created
+    /// afterwards inherit it.
+    pub fn apply(self) -> Result<()> {
+        let class = match self.class {
+            IoPriorityClass::RealTime => 1,
+            IoPriorityClass::BestEffort => 2,
+            IoPriorityClass::Idle => 3,
+        };
+        let prio = (class << IOPRIO_CLASS_SHIFT) | self.level as libc::c_int;
+
+        let res = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) };
+        if res < 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("failed to set I/O priority {self}"));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for IoPriority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, level) = match s.split_once(':') {
+            Some((class, level)) => (class, Some(level)),
+            None => (s, None),
+        };
+        let class = match class {
+            "rt" | "realtime" => IoPriorityClass::RealTime,
+            "be" | "best-effort" => IoPriorityClass::BestEffort,
+            "idle" => IoPriorityClass::Idle,
+            _ => bail!("Invalid I/O priority class"),
+        };
+        let level = match level {
+            Some(level) => level.parse().context("Invalid I/O priority level")?,
+            None => 4,
+        };
+        if level > 7 {
+            bail!("Invalid I/O priority level");
+        }
+
+        Ok(Self { class, level })
+    }
+}
+
+impl fmt::Display for IoPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.class {
+            IoPriorityClass::RealTime => write!(f, "rt:{}", self.level),
+            IoPriorityClass::BestEffort => write!(f, "be:{}", self.level),
+            IoPriorityClass::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// CPU scheduling of the benchmark thread. Completion latencies at high IOPS
+/// are sensitive to preemption, so either lower the niceness or run the
+/// thread with a real-time policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuPriority {
+    Nice(i32),
+    Fifo(i32),
+}
+
+impl CpuPriority {
+    /// Applies the priority to the calling thread.
+    pub fn apply(self) -> Result<()> {
+        match self {
+            Self::Nice(nice) => {
+                if !(-20..=19).contains(&nice) {
+                    bail!("niceness must be in -20..=19");
+                }
+                let res = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+                if res < 0 {
+                    return Err(io::Error::last_os_error())
+                        .with_context(|| format!("failed to set niceness {nice}"));
+                }
+            }
+            Self::Fifo(prio) => {
+                let (min, max) = unsafe {
+                    (
+                        libc::sched_get_priority_min(libc::SCHED_FIFO),
+                        libc::sched_get_priority_max(libc::SCHED_FIFO),
+                    )
+                };
+                if !(min..=max).contains(&prio) {
+                    bail!("SCHED_FIFO priority must be in {min}..={max}");
+                }
+                let param = libc::sched_param {
+                    sched_priority: prio,
+                };
+                let res = unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) };
+                if res < 0 {
+                    return Err(io::Error::last_os_error())
+                        .with_context(|| format!("failed to set SCHED_FIFO priority {prio}"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for CpuPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nice(nice) => write!(f, "nice {nice}"),
+            Self::Fifo(prio) => write!(f, "SCHED_FIFO {prio}"),
+        }
+    }
+}
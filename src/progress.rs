@@ -0,0 +1,271 @@
+use crate::{
+    checkpoint::Checkpointer,
+    events::{self, Event},
+    inflight::Spans,
+    inject::InjectLatency,
+    latency::{Latency, Short},
+    soak::Soak,
+    timeline::Timeline,
+};
+use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+use std::time::{Duration, Instant};
+use tracing::{info, Level};
+
+/// Gap between two completed operations that is reported as a stall.
+const STALL: Duration = Duration::from_secs(1);
+
+/// Logs a progress line once per interval while a strategy runs, reports
+/// progress and stalls to the event log, drives checkpoints and records the
+/// latency of every operation.
+#[derive(Debug)]
+pub struct Progress {
+    enabled: bool,
+    events: bool,
+    interval: Duration,
+    total: u64,
+    done: u64,
+    start: Instant,
+    last: Instant,
+    last_done: u64,
+    last_op: Instant,
+    checkpoint: Option<Checkpointer>,
+    latency: Latency,
+    status: Option<Status>,
+    timeline: Option<Timeline>,
+    soak: Option<Soak>,
+    in_flight: Option<Spans>,
+    inject: Option<InjectLatency>,
+}
+
+/// Plain status lines on stderr, independent of the log level.
+#[derive(Debug)]
+struct Status {
+    interval: Duration,
+    label: Option<String>,
+    last: Instant,
+    last_done: u64,
+    latency: Latency,
+}
+
+impl Progress {
+    pub fn new(total: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            enabled: tracing::enabled!(Level::INFO),
+            events: events::enabled(),
+            interval: Duration::from_secs(1),
+            total,
+            done: 0,
+            start: now,
+            last: now,
+            last_done: 0,
+            last_op: now,
+            checkpoint: None,
+            latency: Latency::default(),
+            status: None,
+            timeline: None,
+            soak: None,
+            in_flight: None,
+            inject: None,
+        }
+    }
+
+    /// Restarts the clocks at `now`, when the timed phase starts after the
+    /// setup.
+    pub fn restart(&mut self, now: Instant) {
+        self.start = now;
+        self.last = now;
+        self.last_op = now;
+        if let Some(status) = &mut self.status {
+            status.last = now;
+        }
+    }
+
+    /// Starts from `done` bytes when resuming a run.
+    pub fn starting_at(mut self, done: u64) -> Self {
+        self.done = done;
+        self.last_done = done;
+        self
+    }
+
+    pub fn with_checkpoint(mut self, checkpoint: Checkpointer) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Prints a status line with the throughput and p99 latency of the last
+    /// `interval` every `interval`, prefixed with `label`.
+    pub fn with_status(mut self, interval: Duration, label: Option<String>) -> Self {
+        self.status = Some(Status {
+            interval,
+            label,
+            last: Instant::now(),
+            last_done: self.done,
+            latency: self.latency.empty_like(),
+        });
+        self
+    }
+
+    /// Records the bytes completed over time.
+    pub fn with_timeline(mut self) -> Self {
+        self.timeline = Some(Timeline::default());
+        self
+    }
+
+    /// Records throughput and tail latency per `window`, with the same
+    /// histogram settings as `latency`.
+    pub fn with_soak(mut self, window: Duration, latency: &Latency) -> Self {
+        self.soak = Some(Soak::new(window, latency));
+        self
+    }
+
+    /// Records when each operation was in flight.
+    pub fn with_in_flight(mut self) -> Self {
+        self.in_flight = Some(Spans::default());
+        self
+    }
+
+    /// Has the strategy hold every completed operation for an injected delay
+    /// before it is recorded, which adds the delay to its latency. See
+    /// [`Held`](crate::inject::Held).
+    pub fn with_injected_latency(mut self, inject: Option<InjectLatency>) -> Self {
+        self.inject = inject;
+        self
+    }
+
+    /// The delay the strategy holds completions for.
+    pub fn injected_latency(&self) -> Option<InjectLatency> {
+        self.inject
+    }
+
+    /// Records latencies into `latency` instead of a default histogram.
+    pub fn with_latency(mut self, latency: Latency) -> Self {
+        if let Some(status) = &mut self.status {
+            status.latency = latency.empty_like();
+        }
+        self.latency = latency;
+        self
+    }
+
+    /// Records that `block` completed with `bytes` transferred after
+    /// `latency`.
+    pub fn add(&mut self, block: u64, bytes: u64, latency: Duration) {
+        self.done += bytes;
+        self.latency.record(latency);
+        if !self.enabled
+            && !self.events
+            && self.checkpoint.is_none()
+            && self.status.is_none()
+            && self.timeline.is_none()
+            && self.soak.is_none()
+            && self.in_flight.is_none()
+        {
+            return;
+        }
+
+        let now = Instant::now();
+        if let Some(timeline) = &mut self.timeline {
+            timeline.add(now - self.start, bytes);
+        }
+        if let Some(spans) = &mut self.in_flight {
+            spans.add(now - self.start, latency);
+        }
+        if let Some(soak) = &mut self.soak {
+            if let Some(window) = soak.add(now - self.start, bytes, latency) {
+                info!("soak window {window}");
+            }
+        }
+        if let Some(status) = &mut self.status {
+            status.latency.record(latency);
+            let elapsed = now - status.last;
+            if elapsed >= status.interval {
+                let speed = (self.done - status.last_done) as f64 / elapsed.as_secs_f64();
+                eprintln!(
+                    "{}[{:>8.2}s] {}/{} ({:.1}%) @ {}/s p99={}",
+                    status
+                        .label
+                        .as_ref()
+                        .map(|label| format!("{label}: "))
+                        .unwrap_or_default(),
+                    (now - self.start).as_secs_f64(),
+                    SizeFormatter::new(self.done, BINARY),
+                    SizeFormatter::new(self.total, BINARY),
+                    self.done as f64 / self.total as f64 * 100.0,
+                    ISizeFormatter::new(speed, BINARY),
+                    Short(status.latency.percentile(99.0)),
+                );
+                status.last = now;
+                status.last_done = self.done;
+                status.latency.reset();
+            }
+        }
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.complete(block, now);
+        }
+        if self.events {
+            let gap = now - self.last_op;
+            if gap >= STALL {
+                events::emit(Event::Stall {
+                    bytes: self.done,
+                    seconds: gap.as_secs_f64(),
+                });
+            }
+            self.last_op = now;
+        }
+
+        let elapsed = now - self.last;
+        if elapsed < self.interval {
+            return;
+        }
+
+        let speed = (self.done - self.last_done) as f64 / elapsed.as_secs_f64();
+        events::emit(Event::Progress {
+            bytes: self.done,
+            total: self.total,
+            bytes_per_sec: speed,
+        });
+        info!(
+            "[{:>8.2}s] {}/{} ({:.1}%) @ {}/s",
+            (now - self.start).as_secs_f64(),
+            SizeFormatter::new(self.done, BINARY),
+            SizeFormatter::new(self.total, BINARY),
+            self.done as f64 / self.total as f64 * 100.0,
+            ISizeFormatter::new(speed, BINARY),
+        );
+        self.last = now;
+        self.last_done = self.done;
+    }
+
+    /// Hands out the latencies recorded so far.
+    pub fn take_latency(&mut self) -> Latency {
+        std::mem::take(&mut self.latency)
+    }
+
+    /// Hands out the timeline recorded so far, empty without
+    /// [`Progress::with_timeline`].
+    pub fn take_timeline(&mut self) -> Timeline {
+        self.timeline.take().unwrap_or_default()
+    }
+
+    /// Hands out the soak windows recorded so far, empty without
+    /// [`Progress::with_soak`].
+    pub fn take_soak(&mut self) -> Soak {
+        self.soak.take().unwrap_or_default()
+    }
+
+    /// Hands out the operation spans recorded so far, empty without
+    /// [`Progress::with_in_flight`].
+    pub fn take_in_flight(&mut self) -> Spans {
+        self.in_flight.take().unwrap_or_default()
+    }
+
+    /// Removes the checkpoint after a completed run or saves the blocks
+    /// reached so far after a failed one.
+    pub fn finish(self, completed: bool) {
+        match self.checkpoint {
+            Some(checkpoint) if completed => checkpoint.finish(),
+            Some(mut checkpoint) => checkpoint.save(Instant::now()),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Pressure Stall Information from `/proc/pressure`, to tell whether a run
+//! was starved of CPU, memory or I/O by other activity on the machine.
+
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, time::Duration};
+use tracing::debug;
+
+/// Share of the run in which CPU or memory stalls point at interference.
+const STARVED: f64 = 10.0;
+/// Timed phases shorter than this are not warned about, brief waits of
+/// kernel threads for a CPU the run keeps busy are a large share of them.
+const MIN_WINDOW: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Pressure {
+    /// Share of the time at least one task was stalled, in percent.
+    pub some: f64,
+    /// Share of the time all non-idle tasks were stalled at once, in percent.
+    /// Always 0 for CPU at the system level.
+    pub full: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Pressures {
+    pub cpu: Pressure,
+    pub memory: Pressure,
+    pub io: Pressure,
+}
+
+impl Pressures {
+    /// A warning if CPU or memory stalls took a noticeable share of a run
+    /// whose timed phase took `elapsed`. I/O stalls are the benchmark itself.
+    pub fn starvation_warning(&self, elapsed: Duration) -> Option<String> {
+        if elapsed < MIN_WINDOW {
+            return None;
+        }
+        let starved = [("cpu", self.cpu), ("memory", self.memory)]
+            .into_iter()
+            .filter(|(_, pressure)| pressure.some >= STARVED)
+            .map(|(resource, pressure)| format!("{resource} {:.1}%", pressure.some))
+            .collect::<Vec<_>>();
+        (!starved.is_empty()).then(|| {
+            format!(
+                "tasks were stalled on {} of the run, results may be skewed by other load",
+                starved.join(" and ")
+            )
+        })
+    }
+}
+
+impl fmt::Display for Pressures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cpu some={:.1}%, memory some={:.1}% full={:.1}%, io some={:.1}% full={:.1}%",
+            self.cpu.some, self.memory.some, self.memory.full, self.io.some, self.io.full,
+        )
+    }
+}
+
+/// Total stall times in microseconds, as counted since boot.
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot {
+    totals: [(u64, u64); 3],
+}
+
+impl Snapshot {
+    /// Reads all three resources, or `None` if the kernel has no PSI, which
+    /// needs `CONFIG_PSI` and may be disabled with `psi=0`.
+    pub fn read() -> Option<Self> {
+        let mut totals = [(0, 0); 3];
+        for (total, resource) in totals.iter_mut().zip(["cpu", "memory", "io"]) {
+            let path = format!("/proc/pressure/{resource}");
+            match fs::read_to_string(&path) {
+                Ok(data) => *total = parse(&data)?,
+                Err(err) => {
+                    debug!("no pressure information, failed to read {path}: {err}");
+                    return None;
+                }
+            }
+        }
+        Some(Self { totals })
+    }
+
+    /// Stall shares between `before` and `self`, which were `elapsed` apart.
+    pub fn since(&self, before: &Self, elapsed: Duration) -> Pressures {
+        let micros = elapsed.as_micros().max(1) as f64;
+        let [cpu, memory, io] = [0, 1, 2].map(|i| {
+            let (some, full) = self.totals[i];
+            let (some_before, full_before) = before.totals[i];
+            Pressure {
+                some: some.saturating_sub(some_before) as f64 / micros * 100.0,
+                full: full.saturating_sub(full_before) as f64 / micros * 100.0,
+            }
+        });
+        Pressures { cpu, memory, io }
+    }
+}
+
+/// Parses the `total=` fields of the `some` and `full` lines, e.g.
+/// `some avg10=0.00 avg60=0.00 avg300=0.00 total=1234`. Older kernels have
+/// no `full` line for CPU.
+fn parse(data: &str) -> Option<(u64, u64)> {
+    let total = |kind: &str| {
+        data.lines()
+            .find(|line| line.starts_with(kind))
+            .and_then(|line| {
+                line.split_whitespace()
+                    .find_map(|f| f.strip_prefix("total="))
+            })
+            .and_then(|total| total.parse().ok())
+    };
+    Some((total("some")?, total("full").unwrap_or(0)))
+}
@@ -0,0 +1,136 @@
+//! Energy use from the RAPL counters exposed through powercap, for
+//! comparing I/O stacks on efficiency.
+
+use anyhow::{bail, Context, Result};
+use humansize::{ISizeFormatter, BINARY};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+const POWERCAP: &str = "/sys/class/powercap";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneEnergy {
+    /// The zone's name, e.g. `package-0` or `dram`.
+    pub name: String,
+    pub joules: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Energy {
+    pub zones: Vec<ZoneEnergy>,
+    /// Sum of the package and DRAM zones. Core and uncore zones are part of
+    /// their package.
+    pub joules: f64,
+    pub bytes_per_joule: f64,
+}
+
+impl fmt::Display for Energy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3} J", self.joules)?;
+        for zone in &self.zones {
+            write!(f, " {}={:.3}J", zone.name, zone.joules)?;
+        }
+        write!(
+            f,
+            ", {}/J",
+            ISizeFormatter::new(self.bytes_per_joule, BINARY)
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Zone {
+    name: String,
+    path: PathBuf,
+    /// The counter wraps around after this many microjoules.
+    range: u64,
+    start: u64,
+}
+
+/// Counters of all RAPL package and DRAM zones at the start of a run.
+#[derive(Debug)]
+pub struct Meter {
+    zones: Vec<Zone>,
+}
+
+impl Meter {
+    pub fn start() -> Result<Self> {
+        let mut zones = Vec::new();
+        let entries = fs::read_dir(POWERCAP)
+            .with_context(|| format!("failed to list {POWERCAP}, is RAPL supported?"))?;
+        for entry in entries {
+            let path = entry?.path();
+            let dir = path.file_name().unwrap_or_default().to_string_lossy();
+            // Zones are named `intel-rapl:<package>[:<subzone>]`, also on AMD.
+            if !dir.starts_with("intel-rapl:") {
+                continue;
+            }
+            let name = read(&path, "name")?;
+            let top_level = dir.matches(':').count() == 1;
+            if !(top_level && name.starts_with("package") || name == "dram") {
+                continue;
+            }
+            let range = read(&path, "max_energy_range_uj")?
+                .parse()
+                .context("invalid max_energy_range_uj")?;
+            let start = energy(&path)?;
+            zones.push(Zone {
+                name,
+                path,
+                range,
+                start,
+            });
+        }
+        if zones.is_empty() {
+            bail!("no RAPL package zones in {POWERCAP}");
+        }
+        zones.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self { zones })
+    }
+
+    /// Energy since [`Meter::start`], assuming each counter wrapped around at
+    /// most once.
+    pub fn finish(&self, bytes: u64) -> Result<Energy> {
+        let mut zones = Vec::new();
+        for zone in &self.zones {
+            let now = energy(&zone.path)?;
+            let micro = if now >= zone.start {
+                now - zone.start
+            } else {
+                zone.range - zone.start + now
+            };
+            zones.push(ZoneEnergy {
+                name: zone.name.clone(),
+                joules: micro as f64 / 1e6,
+            });
+        }
+        let joules = zones.iter().map(|zone| zone.joules).sum::<f64>();
+        Ok(Energy {
+            zones,
+            joules,
+            bytes_per_joule: bytes as f64 / joules,
+        })
+    }
+}
+
+fn read(zone: &Path, attr: &str) -> Result<String> {
+    let path = zone.join(attr);
+    match fs::read_to_string(&path) {
+        Ok(value) => Ok(value.trim().to_string()),
+        // The counters are readable by root only since the PLATYPUS side
+        // channel mitigation.
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            bail!("failed to read {}, which requires root", path.display())
+        }
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+fn energy(zone: &Path) -> Result<u64> {
+    read(zone, "energy_uj")?
+        .parse()
+        .context("invalid energy_uj")
+}
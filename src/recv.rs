@@ -0,0 +1,375 @@
+//! Receiving from many TCP connections through io_uring, with one recv
+//! request per buffer or with one multishot recv per connection
+//! (IORING_RECV_MULTISHOT) that takes a provided buffer for every message,
+//! the way servers with many connections receive.
+
+use crate::{make_block, mem_aligned, mem_aligned_free, usage::Usage};
+use anyhow::{anyhow, bail, Context, Result};
+use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+use io_uring::{cqueue, opcode, types, IoUring};
+use std::{
+    fmt,
+    io::Write,
+    mem,
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    os::unix::io::AsRawFd,
+    sync::atomic::{AtomicU16, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+/// Buffer group of the provided buffer ring.
+const BGID: u16 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// A recv into the connection's own buffer, submitted again after every
+    /// completion.
+    OneShot,
+    /// One recv per connection that completes for every message into a
+    /// buffer the kernel picks from a shared ring, until it runs out of
+    /// buffers or the connection closes.
+    Multishot,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::OneShot => "one-shot recv",
+            Self::Multishot => "multishot recv",
+        })
+    }
+}
+
+/// What the receiving thread did for one mode.
+#[derive(Debug, Default)]
+struct Received {
+    bytes: u64,
+    completions: u64,
+    submissions: u64,
+    /// Multishot receives submitted again after they stopped.
+    rearms: u64,
+    /// Multishot receives that stopped because no buffer was left.
+    no_buffers: u64,
+    waits: u64,
+    elapsed: Duration,
+    cpu: Duration,
+}
+
+/// Sends `bytes` in messages of `size` bytes round-robin over `connections`
+/// loopback connections from one thread and receives them on another, once
+/// with one-shot recv and once with multishot recv and `buffers` provided
+/// buffers of `size` bytes, and compares the two.
+pub fn run(connections: u64, size: u64, bytes: u64, buffers: u64) -> Result<()> {
+    if !buffers.is_power_of_two() {
+        bail!("--buffers must be a power of two, got {buffers}");
+    }
+    if size == 0 {
+        bail!("--size must be more than 0");
+    }
+    if size > u32::MAX as u64 {
+        bail!("a message of {size} bytes is larger than a recv can take");
+    }
+    info!(
+        "receiving {} in messages of {} from {connections} connections over loopback",
+        SizeFormatter::new(bytes, BINARY),
+        SizeFormatter::new(size, BINARY),
+    );
+
+    let one_shot = measure(Mode::OneShot, connections, size, bytes, buffers)?;
+    let multishot = measure(Mode::Multishot, connections, size, bytes, buffers)?;
+
+    let change = |before: f64, after: f64| (after / before - 1.0) * 100.0;
+    println!(
+        "multishot against one-shot: throughput {:+.1}%, completions {:+.1}%, \
+         submissions {:+.1}%, CPU {:+.1}%",
+        change(one_shot.speed(), multishot.speed()),
+        change(one_shot.completions as f64, multishot.completions as f64),
+        change(one_shot.submissions as f64, multishot.submissions as f64),
+        change(one_shot.cpu.as_secs_f64(), multishot.cpu.as_secs_f64()),
+    );
+    Ok(())
+}
+
+fn measure(mode: Mode, connections: u64, size: u64, bytes: u64, buffers: u64) -> Result<Received> {
+    let (senders, receivers) = connect(connections)?;
+    // Every buffer can wait in a completion, and every connection can post
+    // one more for its end or for running out of buffers. A multishot recv
+    // that finds the completion queue full stops.
+    let entries = (connections as u32).next_power_of_two();
+    let completions = ((buffers + connections) as u32).next_power_of_two();
+    let mut ring = IoUring::builder()
+        .setup_cqsize(completions.max(2 * entries))
+        .build(entries)
+        .context("failed to create io_uring")?;
+
+    let sender = thread::spawn(move || send(senders, size, bytes));
+    let usage = Usage::of_thread();
+    let start = Instant::now();
+    let res = match mode {
+        Mode::OneShot => one_shot(&mut ring, &receivers, size),
+        Mode::Multishot => multishot(&mut ring, &receivers, size, buffers as u16),
+    };
+    let elapsed = start.elapsed();
+    let cpu = Usage::of_thread().since(usage).cpu;
+    // On failure the sender may block on a full connection, the process
+    // exits with the error right after.
+    let mut received = res?;
+    sender.join().expect("sender thread panicked")?;
+
+    received.elapsed = elapsed;
+    received.cpu = cpu;
+    if received.bytes != bytes {
+        bail!("{mode} received {} of {bytes} bytes", received.bytes);
+    }
+    println!("{mode}: {received}");
+    Ok(received)
+}
+
+/// Opens `connections` connections over loopback, as the sending and the
+/// receiving ends.
+fn connect(connections: u64) -> Result<(Vec<TcpStream>, Vec<TcpStream>)> {
+    let listener =
+        TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).context("failed to listen on loopback")?;
+    let addr = listener.local_addr()?;
+    let mut senders = Vec::with_capacity(connections as usize);
+    let mut receivers = Vec::with_capacity(connections as usize);
+    for _ in 0..connections {
+        let sender = TcpStream::connect(addr).context("failed to connect over loopback")?;
+        sender.set_nodelay(true)?;
+        let (receiver, _) = listener.accept().context("failed to accept a connection")?;
+        senders.push(sender);
+        receivers.push(receiver);
+    }
+    Ok((senders, receivers))
+}
+
+/// Writes `bytes` in messages of `size` bytes round-robin to `senders` and
+/// closes them, which the receiver sees as the end of each connection.
+fn send(mut senders: Vec<TcpStream>, size: u64, bytes: u64) -> Result<()> {
+    let data = make_block(size, 0);
+    let connections = senders.len() as u64;
+    for message in 0..bytes.div_ceil(size) {
+        let len = size.min(bytes - message * size);
+        senders[(message % connections) as usize]
+            .write_all(&data[..len as usize])
+            .context("failed to send")?;
+    }
+    Ok(())
+}
+
+/// Receives with one recv in flight per connection, each into the
+/// connection's own buffer.
+fn one_shot(ring: &mut IoUring, sockets: &[TcpStream], size: u64) -> Result<Received> {
+    let mut bufs = vec![0u8; sockets.len() * size as usize];
+    let mut received = Received::default();
+    let recv = |ring: &mut IoUring, bufs: &mut [u8], i: usize| {
+        let buf = bufs[i * size as usize..].as_mut_ptr();
+        let entry = opcode::Recv::new(types::Fd(sockets[i].as_raw_fd()), buf, size as u32)
+            .build()
+            .user_data(i as u64);
+        // One recv per connection at most, the ring has an entry for each.
+        unsafe {
+            ring.submission()
+                .push(&entry)
+                .expect("submission queue is full");
+        }
+    };
+    for i in 0..sockets.len() {
+        recv(ring, &mut bufs, i);
+        received.submissions += 1;
+    }
+
+    let mut open = sockets.len();
+    let res = (|| {
+        while open > 0 {
+            ring.submit_and_wait(1)?;
+            received.waits += 1;
+
+            for cqe in ring.completion().collect::<Vec<_>>() {
+                received.completions += 1;
+                match cqe.result() {
+                    0 => open -= 1,
+                    res if res < 0 => return Err(recv_error(res)),
+                    res => {
+                        received.bytes += res as u64;
+                        recv(ring, &mut bufs, cqe.user_data() as usize);
+                        received.submissions += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    })();
+    if res.is_err() {
+        // Receives on the other connections are still in flight and the
+        // kernel may write into their buffers until the process exits.
+        mem::forget(bufs);
+    }
+    res.map(|()| received)
+}
+
+/// Receives with one multishot recv per connection, which takes buffers
+/// from a ring of `buffers` buffers that every completion returns right
+/// away.
+fn multishot(
+    ring: &mut IoUring,
+    sockets: &[TcpStream],
+    size: u64,
+    buffers: u16,
+) -> Result<Received> {
+    let mut bufs = vec![0u8; buffers as usize * size as usize];
+    let ring_size = buffers as usize * mem::size_of::<types::BufRingEntry>();
+    // The kernel maps the ring, which has to start on a page.
+    let entries = mem_aligned(ring_size.max(4096), 4096)?.cast::<types::BufRingEntry>();
+    let mut provided = Provided {
+        entries,
+        mask: buffers - 1,
+        tail: 0,
+    };
+    for bid in 0..buffers {
+        let buf = bufs[bid as usize * size as usize..].as_mut_ptr();
+        provided.push(bid, buf, size as u32);
+    }
+    provided.publish();
+    // Registering requires Linux 5.19.
+    let res = unsafe {
+        ring.submitter()
+            .register_buf_ring(entries as u64, buffers, BGID)
+    };
+    if let Err(err) = res {
+        mem_aligned_free(entries.cast(), ring_size.max(4096), 4096);
+        return Err(err).context("failed to register the provided buffers (requires Linux 5.19)");
+    }
+
+    let mut received = Received::default();
+    let recv = |ring: &mut IoUring, i: usize| {
+        let entry = opcode::RecvMulti::new(types::Fd(sockets[i].as_raw_fd()), BGID)
+            .build()
+            .user_data(i as u64);
+        // One recv per connection at most, the ring has an entry for each.
+        unsafe {
+            ring.submission()
+                .push(&entry)
+                .expect("submission queue is full");
+        }
+    };
+    for i in 0..sockets.len() {
+        recv(ring, i);
+        received.submissions += 1;
+    }
+
+    let mut open = sockets.len();
+    let res = (|| {
+        while open > 0 {
+            ring.submit_and_wait(1)?;
+            received.waits += 1;
+
+            for cqe in ring.completion().collect::<Vec<_>>() {
+                received.completions += 1;
+                let i = cqe.user_data() as usize;
+                let more = cqueue::more(cqe.flags());
+                match cqe.result() {
+                    0 => {
+                        open -= 1;
+                        continue;
+                    }
+                    res if res == -libc::ENOBUFS => received.no_buffers += 1,
+                    res if res == -libc::EINVAL => {
+                        let err = std::io::Error::from_raw_os_error(-res);
+                        return Err(
+                            anyhow!(err).context("multishot recv failed (requires Linux 6.0)")
+                        );
+                    }
+                    res if res < 0 => return Err(recv_error(res)),
+                    res => {
+                        received.bytes += res as u64;
+                        let bid = cqueue::buffer_select(cqe.flags())
+                            .ok_or_else(|| anyhow!("recv completed without a buffer"))?;
+                        let buf = bufs[bid as usize * size as usize..].as_mut_ptr();
+                        provided.push(bid, buf, size as u32);
+                        provided.publish();
+                    }
+                }
+                if !more {
+                    recv(ring, i);
+                    received.submissions += 1;
+                    received.rearms += 1;
+                }
+            }
+        }
+        Ok(())
+    })();
+    if res.is_err() {
+        // Receives on the other connections are still in flight and the
+        // kernel may write into their buffers until the process exits.
+        mem::forget(bufs);
+        return res.map(|()| received);
+    }
+    ring.submitter()
+        .unregister_buf_ring(BGID)
+        .context("failed to unregister the provided buffers")?;
+    mem_aligned_free(entries.cast(), ring_size.max(4096), 4096);
+    Ok(received)
+}
+
+/// The ring of provided buffers, shared with the kernel.
+struct Provided {
+    entries: *mut types::BufRingEntry,
+    mask: u16,
+    tail: u16,
+}
+
+impl Provided {
+    /// Adds the buffer `bid` at `buf` of `len` bytes, which the kernel sees
+    /// with the next [`Provided::publish`].
+    fn push(&mut self, bid: u16, buf: *mut u8, len: u32) {
+        let entry = unsafe { &mut *self.entries.add((self.tail & self.mask) as usize) };
+        entry.set_addr(buf as u64);
+        entry.set_len(len);
+        entry.set_bid(bid);
+        self.tail = self.tail.wrapping_add(1);
+    }
+
+    fn publish(&self) {
+        let tail = unsafe { types::BufRingEntry::tail(self.entries) } as *const AtomicU16;
+        unsafe { (*tail).store(self.tail, Ordering::Release) };
+    }
+}
+
+fn recv_error(result: i32) -> anyhow::Error {
+    anyhow!(std::io::Error::from_raw_os_error(-result)).context("recv failed")
+}
+
+impl Received {
+    fn speed(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Received {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in {:.6} seconds @ {}/s, {} completions of {} on average, \
+             {} submissions, {} waits, {:.3} CPU seconds",
+            SizeFormatter::new(self.bytes, BINARY),
+            self.elapsed.as_secs_f64(),
+            ISizeFormatter::new(self.speed(), BINARY),
+            self.completions,
+            SizeFormatter::new(self.bytes / self.completions.max(1), BINARY),
+            self.submissions,
+            self.waits,
+            self.cpu.as_secs_f64(),
+        )?;
+        if self.rearms > 0 {
+            write!(
+                f,
+                " ({} re-armed, {} out of buffers)",
+                self.rearms, self.no_buffers
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+//! Self-contained HTML reports of one or more runs, with a summary table and
+//! charts drawn as inline SVG so the file can be shared on its own.
+
+use crate::{
+    latency::{Latency, Short},
+    results::Results,
+};
+use anyhow::{Context, Result};
+use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::warn;
+
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 280.0;
+const LEFT: f64 = 80.0;
+const RIGHT: f64 = 20.0;
+const TOP: f64 = 15.0;
+const BOTTOM: f64 = 30.0;
+const TICKS: usize = 5;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { padding: 0.25em 0.75em; border-bottom: 1px solid #ddd; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+svg text { font-size: 11px; fill: #444; }
+.legend span { display: inline-block; width: 0.8em; height: 0.8em; margin: 0 0.3em 0 1em; }
+pre { background: #f6f6f6; padding: 0.5em; overflow-x: auto; }
+";
+
+/// Renders `paths`, labelled with their paths, into one report.
+pub fn run(paths: &[PathBuf], output: &Path) -> Result<()> {
+    let runs = paths
+        .iter()
+        .map(|path| Ok((path.display().to_string(), Results::load(path)?)))
+        .collect::<Result<Vec<_>>>()?;
+    write(output, &runs)?;
+    println!(
+        "wrote a report of {} runs to {}",
+        runs.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Writes a report of `runs`, each with a label.
+pub fn write(path: &Path, runs: &[(String, Results)]) -> Result<()> {
+    fs::write(path, render(runs)?)
+        .with_context(|| format!("failed to write report {}", path.display()))
+}
+
+fn render(runs: &[(String, Results)]) -> Result<String> {
+    let mut html = String::new();
+    let title = match runs {
+        [(label, _)] => format!("raio: {label}"),
+        _ => format!("raio: {} runs", runs.len()),
+    };
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title>\
+         <style>{STYLE}</style></head><body>\n<h1>{}</h1>\n",
+        escape(&title),
+        escape(&title),
+    );
+
+    html.push_str("<h2>Summary</h2>\n<table><tr>");
+    for column in [
+        "run",
+        "op",
+        "strategy",
+        "block size",
+        "bytes",
+        "seconds",
+        "throughput",
+        "IOPS",
+        "mean",
+        "p50",
+        "p99",
+        "p99.9",
+        "max",
+    ] {
+        let _ = write!(html, "<th>{column}</th>");
+    }
+    html.push_str("</tr>\n");
+    for (i, (label, results)) in runs.iter().enumerate() {
+        let mut row = vec![
+            format!(
+                "<span style=\"color:{}\">&#9632;</span> {}",
+                color(i),
+                escape(label)
+            ),
+            results.op.clone(),
+            results.job.strategy.to_string(),
+            SizeFormatter::new(results.job.block_size, BINARY).to_string(),
+            SizeFormatter::new(results.bytes, BINARY).to_string(),
+            format!("{:.3}", results.seconds),
+            format!("{}/s", ISizeFormatter::new(results.bytes_per_sec, BINARY)),
+            format!("{:.0}", results.iops),
+        ];
+        match &results.latency {
+            Some(latency) => row.extend(
+                [
+                    latency.mean_us,
+                    latency.p50_us,
+                    latency.p99_us,
+                    latency.p999_us,
+                    latency.max_us,
+                ]
+                .map(|us| Short(Duration::from_secs_f64(us / 1e6)).to_string()),
+            ),
+            None => row.extend(std::iter::repeat_n("-".to_string(), 5)),
+        }
+        html.push_str("<tr>");
+        for cell in row {
+            let _ = write!(html, "<td>{cell}</td>");
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+
+    let throughput = series(runs, |results| results.timeline.rates().collect());
+    html.push_str(&section(
+        "Throughput over time",
+        &throughput,
+        |secs| format!("{secs:.1}s"),
+        |rate| format!("{}/s", ISizeFormatter::new(rate, BINARY)),
+    ));
+
+    let latency = series(runs, latency_shares);
+    html.push_str(&section(
+        "Latency distribution (share of operations per power-of-two bucket)",
+        &latency,
+        |x| Short(Duration::from_nanos(x.exp2() as u64)).to_string(),
+        |share| format!("{share:.1}%"),
+    ));
+
+    let util = series(runs, |results| {
+        results
+            .iostat
+            .iter()
+            .map(|sample| (sample.seconds, sample.util))
+            .collect()
+    });
+    html.push_str(&section(
+        "Device utilization",
+        &util,
+        |secs| format!("{secs:.1}s"),
+        |util| format!("{util:.0}%"),
+    ));
+
+    html.push_str("<h2>Parameters</h2>\n");
+    for (label, results) in runs {
+        let job = serde_json::to_string_pretty(&results.job).context("failed to serialize job")?;
+        let _ = writeln!(
+            html,
+            "<details><summary>{}</summary><pre>{}</pre></details>",
+            escape(label),
+            escape(&job),
+        );
+    }
+    let _ = write!(
+        html,
+        "<p><small>raio {}</small></p>\n</body></html>\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    Ok(html)
+}
+
+/// Share of operations in percent per latency bucket, keyed by the bucket's
+/// upper end as a power of two of nanoseconds.
+fn latency_shares(results: &Results) -> Vec<(f64, f64)> {
+    let Some(summary) = &results.latency else {
+        return Vec::new();
+    };
+    let latency = match Latency::decode(&summary.histogram) {
+        Ok(latency) => latency,
+        Err(err) => {
+            warn!("leaving out the latency distribution: {err:#}");
+            return Vec::new();
+        }
+    };
+    let buckets = latency.buckets();
+    let total = buckets.iter().map(|(_, count)| count).sum::<u64>().max(1);
+    buckets
+        .into_iter()
+        .map(|(upper, count)| {
+            let x = (upper.as_nanos() as f64).log2();
+            (x, count as f64 / total as f64 * 100.0)
+        })
+        .collect()
+}
+
+struct Series<'a> {
+    label: &'a str,
+    color: &'static str,
+    points: Vec<(f64, f64)>,
+}
+
+/// One series per run with points, keeping each run's color.
+fn series<'a>(
+    runs: &'a [(String, Results)],
+    mut points: impl FnMut(&Results) -> Vec<(f64, f64)>,
+) -> Vec<Series<'a>> {
+    runs.iter()
+        .enumerate()
+        .map(|(i, (label, results))| Series {
+            label,
+            color: color(i),
+            points: points(results),
+        })
+        .filter(|series| !series.points.is_empty())
+        .collect()
+}
+
+/// A heading with a line chart of `series`, or nothing without data.
+fn section(
+    title: &str,
+    series: &[Series],
+    x_label: impl Fn(f64) -> String,
+    y_label: impl Fn(f64) -> String,
+) -> String {
+    let points = || series.iter().flat_map(|series| &series.points);
+    let Some(x_min) = points().map(|p| p.0).reduce(f64::min) else {
+        return String::new();
+    };
+    let x_max = points().map(|p| p.0).fold(x_min, f64::max);
+    let y_max = points().map(|p| p.1).fold(0.0, f64::max);
+    let x_span = if x_max > x_min { x_max - x_min } else { 1.0 };
+    let y_span = if y_max > 0.0 { y_max } else { 1.0 };
+    let x_pos = |x: f64| LEFT + (x - x_min) / x_span * (WIDTH - LEFT - RIGHT);
+    let y_pos = |y: f64| TOP + (1.0 - y / y_span) * (HEIGHT - TOP - BOTTOM);
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<h2>{}</h2>\n<svg width=\"{WIDTH}\" height=\"{HEIGHT}\" viewBox=\"0 0 {WIDTH} {HEIGHT}\">\n",
+        escape(title)
+    );
+    for i in 0..=TICKS {
+        let share = i as f64 / TICKS as f64;
+        let (x, y) = (x_min + share * x_span, share * y_span);
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{LEFT}\" x2=\"{}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"#eee\"/>\
+             <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\
+             <text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            WIDTH - RIGHT,
+            LEFT - 5.0,
+            y_pos(y) + 4.0,
+            escape(&y_label(y)),
+            x_pos(x),
+            HEIGHT - 10.0,
+            escape(&x_label(x)),
+            y = y_pos(y),
+        );
+    }
+    for series in series {
+        let line = series
+            .points
+            .iter()
+            .map(|&(x, y)| format!("{:.1},{:.1}", x_pos(x), y_pos(y)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(
+            svg,
+            "<polyline points=\"{line}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>",
+            series.color
+        );
+        // Short series would be hard to see as lines alone.
+        if series.points.len() < 50 {
+            for &(x, y) in &series.points {
+                let _ = writeln!(
+                    svg,
+                    "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2.5\" fill=\"{}\"/>",
+                    x_pos(x),
+                    y_pos(y),
+                    series.color
+                );
+            }
+        }
+    }
+    svg.push_str("</svg>\n<div class=\"legend\">");
+    for series in series {
+        let _ = write!(
+            svg,
+            "<span style=\"background:{}\"></span>{}",
+            series.color,
+            escape(series.label)
+        );
+    }
+    svg.push_str("</div>\n");
+    svg
+}
+
+fn color(i: usize) -> &'static str {
+    COLORS[i % COLORS.len()]
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,189 @@
+//! Results files that record a run's parameters so it can be repeated.
+
+use crate::{
+    calibrate::Overhead, diskstats::DiskStats, extents::Extents, inflight::InFlight,
+    iostat::Sample, latency::LatencySummary, psi::Pressures, rapl::Energy, ring::RingCounters,
+    smart::Wear, soak::Window, timeline::Timeline, usage::Efficiency, worker::Worker, Job, Stats,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Results {
+    pub version: String,
+    pub op: String,
+    pub verify: bool,
+    /// Parameters as used by the run, including the seed picked for random
+    /// offsets.
+    pub job: Job,
+    pub bytes: u64,
+    pub transferred: u64,
+    pub ops: u64,
+    pub seconds: f64,
+    pub bytes_per_sec: f64,
+    pub iops: f64,
+    #[serde(default)]
+    pub latency: Option<LatencySummary>,
+    /// Throughput and latency per worker with `--jobs`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workers: Vec<Worker>,
+    /// Throughput over time, recorded when writing results or a report.
+    #[serde(default, skip_serializing_if = "Timeline::is_empty")]
+    pub timeline: Timeline,
+    /// Time from the last completed write until the file was synced, with
+    /// `--durable`.
+    #[serde(default)]
+    pub durable_seconds: Option<f64>,
+    /// See [`crate::hash::file`].
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// Of the written range, with `--extents`.
+    #[serde(default)]
+    pub extents: Option<Extents>,
+    #[serde(default)]
+    pub overhead: Option<Overhead>,
+    /// With `--in-flight`.
+    #[serde(default)]
+    pub in_flight: Option<InFlight>,
+    /// CPU time and system calls of the workers relative to the bytes moved.
+    #[serde(default)]
+    pub efficiency: Option<Efficiency>,
+    /// See [`RingCounters`], `None` for strategies without a ring.
+    #[serde(default)]
+    pub ring: Option<RingCounters>,
+    /// Kernel accounting of the target's whole device during the run, which
+    /// includes I/O by other processes.
+    #[serde(default)]
+    pub diskstats: Option<DiskStats>,
+    /// Health attributes of the target's device before and after the run.
+    #[serde(default)]
+    pub smart: Option<Wear>,
+    /// Throughput and tail latency per `--soak` window.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub soak: Vec<Window>,
+    /// Device utilization per `--iostat` interval.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub iostat: Vec<Sample>,
+    /// Stalls on CPU, memory and I/O during the run, machine-wide.
+    #[serde(default)]
+    pub pressure: Option<Pressures>,
+    #[serde(default)]
+    pub energy: Option<Energy>,
+}
+
+impl Results {
+    pub fn new(job: &Job, write: bool, verify: bool, stats: &Stats) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            op: if write { "write" } else { "read" }.to_string(),
+            verify,
+            job: job.clone(),
+            bytes: stats.bytes,
+            transferred: stats.transferred,
+            ops: stats.ops,
+            seconds: stats.elapsed.as_secs_f64(),
+            bytes_per_sec: stats.bytes_per_sec(),
+            iops: stats.iops(),
+            latency: (!stats.latency.is_empty()).then(|| stats.latency.summary()),
+            workers: stats.workers.clone(),
+            timeline: stats.timeline.clone(),
+            durable_seconds: None,
+            hash: None,
+            extents: None,
+            overhead: None,
+            ring: None,
+            in_flight: None,
+            efficiency: Some(Efficiency::new(
+                stats.usage,
+                stats.bytes,
+                job.strategy.counts_syscalls(),
+            )),
+            diskstats: None,
+            smart: None,
+            iostat: Vec::new(),
+            soak: Vec::new(),
+            pressure: None,
+            energy: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path)
+            .with_context(|| format!("failed to read results file {}", path.display()))?;
+        let results: Self = serde_json::from_slice(&data)
+            .with_context(|| format!("invalid results file {}", path.display()))?;
+        if !matches!(results.op.as_str(), "write" | "read") {
+            bail!("unknown operation `{}` in {}", results.op, path.display());
+        }
+        Ok(results)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_vec_pretty(self)?;
+        fs::write(path, data)
+            .with_context(|| format!("failed to write results file {}", path.display()))
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.op == "write"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("raio-results-{}-{name}", std::process::id()))
+    }
+
+    /// Results as an early version wrote them, before the optional fields.
+    fn minimal(op: &str) -> Value {
+        let job = Job::parse(&["-f", "data.bin", "-s", "4k", "-c", "8"]);
+        json!({
+            "version": "0.1.0",
+            "op": op,
+            "verify": false,
+            "job": job,
+            "bytes": 32768,
+            "transferred": 32768,
+            "ops": 8,
+            "seconds": 0.5,
+            "bytes_per_sec": 65536.0,
+            "iops": 16.0,
+        })
+    }
+
+    fn load_value(name: &str, value: &Value) -> Result<Results> {
+        let path = path(name);
+        fs::write(&path, value.to_string()).unwrap();
+        let res = Results::load(&path);
+        fs::remove_file(&path).unwrap();
+        res
+    }
+
+    #[test]
+    fn minimal_results_load_with_defaults() {
+        let results = load_value("minimal", &minimal("read")).unwrap();
+        assert!(!results.is_write());
+        assert_eq!((results.job.block_size, results.job.count), (4096, 8));
+        assert!(results.latency.is_none() && results.workers.is_empty());
+        assert!(load_value("unknown-op", &minimal("trim")).is_err());
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let results = load_value("source", &minimal("write")).unwrap();
+        let path = path("round-trip");
+        results.save(&path).unwrap();
+        let loaded = Results::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&results).unwrap()
+        );
+        assert!(loaded.is_write());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::usage;
+use anyhow::{anyhow, Context, Result};
+use humansize::{SizeFormatter, BINARY};
+use io_uring::{
+    types::{SubmitArgs, Timespec},
+    IoUring,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, thread, time::Instant};
+use tracing::debug;
+
+const PAGE_SIZE: u64 = 4096;
+/// Milliseconds the SQPOLL thread spins without submissions before it
+/// sleeps.
+const SQPOLL_IDLE_MS: u32 = 1000;
+
+/// Overflow counters of a ring, read after a run. A completion the kernel
+/// could not post would otherwise be missing from the statistics without
+/// any sign.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RingCounters {
+    /// Submissions the kernel dropped as invalid.
+    pub sq_dropped: u64,
+    /// Completions lost because the completion queue was full. Kernels with
+    /// IORING_FEAT_NODROP (5.5+) keep them in a backlog instead and only
+    /// lose them if that fails.
+    pub cq_overflow: u64,
+    /// Whether completions were waiting in that backlog at the end of the
+    /// run (IORING_SQ_CQ_OVERFLOW).
+    pub cq_backlog: bool,
+}
+
+impl RingCounters {
+    pub fn of(ring: &mut IoUring) -> Self {
+        let (_, sq, cq) = ring.split();
+        Self {
+            sq_dropped: sq.dropped() as u64,
+            cq_overflow: cq.overflow() as u64,
+            cq_backlog: sq.cq_overflow(),
+        }
+    }
+
+    /// Adds the counters of another ring, e.g. another worker's.
+    pub fn add(&mut self, other: Self) {
+        self.sq_dropped += other.sq_dropped;
+        self.cq_overflow += other.cq_overflow;
+        self.cq_backlog |= other.cq_backlog;
+    }
+
+    /// A warning if operations were lost, since the statistics then miss
+    /// them.
+    pub fn loss_warning(&self) -> Option<String> {
+        (self.sq_dropped > 0 || self.cq_overflow > 0).then(|| {
+            format!(
+                "the ring dropped {} submissions and lost {} completions, \
+                 the statistics miss these operations",
+                self.sq_dropped, self.cq_overflow,
+            )
+        })
+    }
+}
+
+impl fmt::Display for RingCounters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sq_dropped={} cq_overflow={}",
+            self.sq_dropped, self.cq_overflow
+        )?;
+        if self.cq_backlog {
+            f.write_str(" (completions left in the overflow backlog)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Creates a ring with `entries` submission entries. Kernels before 5.12
+/// account ring memory against RLIMIT_MEMLOCK, so if creation fails for lack
+/// of locked memory the soft limit is raised and creation is retried once.
+pub fn new_ring(entries: u32) -> Result<IoUring> {
+    match IoUring::new(entries) {
+        Ok(ring) => Ok(ring),
+        Err(err) if is_memlock_error(&err) => {
+            let required = ring_size(entries);
+            if raise_memlock(required)? {
+                if let Ok(ring) = IoUring::new(entries) {
+                    return Ok(ring);
+                }
+            }
+            Err(memlock_error(err, "create io_uring", required))
+        }
+        Err(err) => Err(err).context("failed to create io_uring"),
+    }
+}
+
+/// Submits what is queued and reaps completions until `in_flight`
+/// operations have completed, after a failure left them in flight with
+/// buffers that may only be freed afterwards.
+pub fn drain(ring: &mut IoUring, mut in_flight: usize) -> Result<()> {
+    while in_flight > 0 {
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err).context("failed to wait for the operations in flight"),
+        }
+        in_flight = in_flight.saturating_sub(ring.completion().count());
+    }
+    Ok(())
+}
+
+/// Submits what is queued and waits for a completion, but only until `due`
+/// if a held completion is due then. With nothing `in_flight` there is
+/// nothing to wait for, so it sleeps until `due`. Kernels before 5.11 cannot
+/// wait with a timeout, there it submits and sleeps until `due`, leaving
+/// completions to wait until then.
+pub fn wait(ring: &mut IoUring, in_flight: bool, due: Option<Instant>) -> Result<()> {
+    let Some(due) = due else {
+        ring.submit_and_wait(1)?;
+        usage::entered();
+        return Ok(());
+    };
+    let timeout = due.saturating_duration_since(Instant::now());
+    if !in_flight {
+        thread::sleep(timeout);
+        return Ok(());
+    }
+    if timeout.is_zero() || !ring.params().is_feature_ext_arg() {
+        ring.submit()?;
+        usage::entered();
+        thread::sleep(timeout);
+        return Ok(());
+    }
+    let timespec = Timespec::from(timeout);
+    let args = SubmitArgs::new().timespec(&timespec);
+    match ring.submitter().submit_with_args(1, &args) {
+        Ok(_) => {}
+        Err(err) if matches!(err.raw_os_error(), Some(libc::ETIME | libc::EINTR)) => {}
+        Err(err) => return Err(err.into()),
+    }
+    usage::entered();
+    Ok(())
+}
+
+/// How the ring of the `max` strategy was set up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setup {
+    /// A kernel thread polls the submission queue, so submitting needs no
+    /// system call.
+    SqPoll,
+    /// Completion work runs only when the thread waits for completions.
+    DeferTaskrun,
+    /// Completion work does not interrupt the thread.
+    CoopTaskrun,
+    Plain,
+}
+
+impl fmt::Display for Setup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::SqPoll => "SQPOLL",
+            Self::DeferTaskrun => "DEFER_TASKRUN",
+            Self::CoopTaskrun => "COOP_TASKRUN",
+            Self::Plain => "no setup flags",
+        })
+    }
+}
+
+/// Creates a ring with the first setup the kernel accepts out of SQPOLL
+/// (unprivileged since 5.11), DEFER_TASKRUN (6.1) and COOP_TASKRUN (5.19).
+/// SQPOLL is skipped on a single CPU, where the polling thread takes turns
+/// with the thread it submits for instead of running beside it.
+pub fn new_max_ring(entries: u32) -> Result<(IoUring, Setup)> {
+    let cpus = thread::available_parallelism().map_or(1, |cpus| cpus.get());
+    for setup in [Setup::SqPoll, Setup::DeferTaskrun, Setup::CoopTaskrun] {
+        if setup == Setup::SqPoll && cpus < 2 {
+            debug!("cannot set up a ring with {setup}: only one CPU is available");
+            continue;
+        }
+        let mut builder = IoUring::builder();
+        match setup {
+            Setup::SqPoll => builder.setup_sqpoll(SQPOLL_IDLE_MS),
+            Setup::DeferTaskrun => builder.setup_single_issuer().setup_defer_taskrun(),
+            Setup::CoopTaskrun | Setup::Plain => builder.setup_coop_taskrun(),
+        };
+        match builder.build(entries) {
+            Ok(ring) => return Ok((ring, setup)),
+            Err(err) => debug!("cannot set up a ring with {setup}: {err}"),
+        }
+    }
+    Ok((new_ring(entries)?, Setup::Plain))
+}
+
+/// Registers `bufs` as fixed buffers. Registered buffers are pinned and
+/// always accounted against RLIMIT_MEMLOCK, so the limit is checked (and
+/// raised if possible) up front.
+///
+/// # Safety
+///
+/// The buffers must stay valid until they are unregistered or the ring is
+/// dropped.
+pub unsafe fn register_buffers(ring: &IoUring, bufs: &[libc::iovec]) -> Result<()> {
+    let required = bufs
+        .iter()
+        .map(|buf| (buf.iov_len as u64).div_ceil(PAGE_SIZE) * PAGE_SIZE)
+        .sum();
+    raise_memlock(required)?;
+
+    match ring.submitter().register_buffers(bufs) {
+        Ok(()) => Ok(()),
+        Err(err) if is_memlock_error(&err) => Err(memlock_error(err, "register buffers", required)),
+        Err(err) => Err(err).context("failed to register buffers"),
+    }
+}
+
+/// Returns the soft and hard RLIMIT_MEMLOCK, `None` meaning unlimited.
+pub fn memlock_limit() -> Result<(Option<u64>, Option<u64>)> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } < 0 {
+        return Err(io::Error::last_os_error()).context("failed to get RLIMIT_MEMLOCK");
+    }
+
+    let finite = |value| (value != libc::RLIM_INFINITY).then_some(value);
+    Ok((finite(limit.rlim_cur), finite(limit.rlim_max)))
+}
+
+/// Raises the soft RLIMIT_MEMLOCK so that at least `required` bytes can be
+/// locked. Returns `false` if the hard limit does not allow it.
+pub fn raise_memlock(required: u64) -> Result<bool> {
+    let (soft, hard) = memlock_limit()?;
+    match soft {
+        None => return Ok(true),
+        Some(soft) if soft >= required => return Ok(true),
+        Some(_) => (),
+    }
+
+    let limit = libc::rlimit {
+        rlim_cur: hard.unwrap_or(libc::RLIM_INFINITY),
+        rlim_max: hard.unwrap_or(libc::RLIM_INFINITY),
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) } < 0 {
+        return Ok(false);
+    }
+
+    Ok(hard.is_none_or(|hard| hard >= required))
+}
+
+fn is_memlock_error(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(libc::ENOMEM | libc::EPERM))
+}
+
+fn memlock_error(err: io::Error, action: &str, required: u64) -> anyhow::Error {
+    let limit = |value: Option<u64>| match value {
+        Some(value) => SizeFormatter::new(value, BINARY).to_string(),
+        None => "unlimited".to_string(),
+    };
+    let (soft, hard) = match memlock_limit() {
+        Ok(limits) => limits,
+        Err(err) => return err,
+    };
+
+    anyhow!(
+        "failed to {action}: {err}\n\
+         at least {} of locked memory are required, RLIMIT_MEMLOCK is {} (hard limit {}); \
+         raise it with `ulimit -l {}` or the `memlock` setting in /etc/security/limits.conf",
+        SizeFormatter::new(required, BINARY),
+        limit(soft),
+        limit(hard),
+        required.div_ceil(1024),
+    )
+}
+
+/// Approximate memory of a ring: 64 byte SQEs, twice as many 16 byte CQEs
+/// and the SQ index array, each rounded up to pages.
+fn ring_size(entries: u32) -> u64 {
+    let entries = entries.next_power_of_two() as u64;
+    let pages = |bytes: u64| bytes.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+    pages(entries * 64) + pages(entries * 2 * 16 + entries * 4 + PAGE_SIZE)
+}
@@ -0,0 +1,164 @@
+//! A loop device backed by a sparse temp file, as a target for destructive
+//! block device features like discards and scheduler switching.
+
+use anyhow::{anyhow, bail, Context, Result};
+use humansize::{SizeFormatter, BINARY};
+use std::{
+    fs, io,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tracing::{debug, info, warn};
+
+const LOOP_SET_FD: libc::c_ulong = 0x4c00;
+const LOOP_CLR_FD: libc::c_ulong = 0x4c01;
+const LOOP_SET_STATUS64: libc::c_ulong = 0x4c04;
+const LOOP_CTL_GET_FREE: libc::c_ulong = 0x4c82;
+/// Detach the device once it is closed for the last time, so it does not
+/// outlive raio even if raio is killed.
+const LO_FLAGS_AUTOCLEAR: u32 = 4;
+
+/// `struct loop_info64` from `linux/loop.h`.
+#[repr(C)]
+struct LoopInfo64 {
+    lo_device: u64,
+    lo_inode: u64,
+    lo_rdevice: u64,
+    lo_offset: u64,
+    lo_sizelimit: u64,
+    lo_number: u32,
+    lo_encrypt_type: u32,
+    lo_encrypt_key_size: u32,
+    lo_flags: u32,
+    lo_file_name: [u8; 64],
+    lo_crypt_name: [u8; 64],
+    lo_encrypt_key: [u8; 32],
+    lo_init: [u64; 2],
+}
+
+/// An attached loop device, detached when dropped.
+#[derive(Debug)]
+pub struct LoopDevice {
+    path: PathBuf,
+    device: fs::File,
+}
+
+impl LoopDevice {
+    /// Attaches a new loop device to a sparse file of `size` bytes in `dir`.
+    /// The file is removed right away, the device keeps it alive.
+    pub fn create(dir: &Path, size: u64) -> Result<Self> {
+        let backing_path = dir.join(format!("raio-scratch-{}.img", std::process::id()));
+        let backing = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&backing_path)
+            .with_context(|| format!("failed to create {}", backing_path.display()))?;
+        let res = backing
+            .set_len(size)
+            .context("failed to size the backing file")
+            .and_then(|()| attach(&backing, &backing_path));
+        if let Err(err) = fs::remove_file(&backing_path) {
+            warn!("failed to remove {}: {err}", backing_path.display());
+        }
+        res
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for LoopDevice {
+    fn drop(&mut self) {
+        if unsafe { libc::ioctl(self.device.as_raw_fd(), LOOP_CLR_FD) } < 0 {
+            let err = io::Error::last_os_error();
+            warn!("failed to detach {}: {err}", self.path.display());
+        } else {
+            info!("detached {}", self.path.display());
+        }
+    }
+}
+
+fn attach(backing: &fs::File, backing_path: &Path) -> Result<LoopDevice> {
+    let control = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/loop-control")
+        .context("failed to open /dev/loop-control, is the loop module loaded?")?;
+
+    // Another process may grab the free device first.
+    for _ in 0..8 {
+        let number = unsafe { libc::ioctl(control.as_raw_fd(), LOOP_CTL_GET_FREE) };
+        if number < 0 {
+            return Err(io::Error::last_os_error()).context("failed to find a free loop device");
+        }
+        let path = PathBuf::from(format!("/dev/loop{number}"));
+        let device = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        if unsafe { libc::ioctl(device.as_raw_fd(), LOOP_SET_FD, backing.as_raw_fd()) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EBUSY) {
+                debug!("{} was taken, retrying", path.display());
+                continue;
+            }
+            return Err(err).with_context(|| format!("failed to attach {}", path.display()));
+        }
+        let device = LoopDevice { path, device };
+
+        let mut info = unsafe { std::mem::zeroed::<LoopInfo64>() };
+        info.lo_flags = LO_FLAGS_AUTOCLEAR;
+        let name = backing_path.as_os_str().as_encoded_bytes();
+        let len = name.len().min(info.lo_file_name.len() - 1);
+        info.lo_file_name[..len].copy_from_slice(&name[..len]);
+        if unsafe { libc::ioctl(device.device.as_raw_fd(), LOOP_SET_STATUS64, &info) } < 0 {
+            return Err(io::Error::last_os_error()).context("failed to configure the loop device");
+        }
+        return Ok(device);
+    }
+    Err(anyhow!("no free loop device after several attempts"))
+}
+
+/// Attaches a scratch loop device, then runs `command` with every `{}`
+/// replaced by the device path, or waits for Enter without a command, and
+/// detaches the device again.
+pub fn run(size: u64, dir: Option<&Path>, command: &[String]) -> Result<()> {
+    let dir = dir.map_or_else(std::env::temp_dir, Path::to_path_buf);
+    let device = LoopDevice::create(&dir, size)?;
+    let path = device.path().display().to_string();
+    info!(
+        "attached {path}, backed by a {} sparse file in {}",
+        SizeFormatter::new(size, BINARY),
+        dir.display()
+    );
+
+    let Some((program, args)) = command.split_first() else {
+        println!("{path}");
+        eprintln!("press Enter to detach {path}");
+        io::stdin()
+            .read_line(&mut String::new())
+            .context("failed to read stdin")?;
+        return Ok(());
+    };
+
+    let args = args.iter().map(|arg| arg.replace("{}", &path));
+    let mut child = Command::new(program)
+        .args(args)
+        .env("RAIO_SCRATCH", &path)
+        .spawn()
+        .with_context(|| format!("failed to run {program}"))?;
+    // Ctrl-C should stop the command, not skip detaching the device.
+    let previous = unsafe { libc::signal(libc::SIGINT, libc::SIG_IGN) };
+    let status = child.wait();
+    unsafe { libc::signal(libc::SIGINT, previous) };
+
+    let status = status.with_context(|| format!("failed to wait for {program}"))?;
+    if !status.success() {
+        bail!("{program} failed with {status}");
+    }
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use crate::{
+    matches_pattern,
+    offsets::{Mode, Offsets},
+    progress::Progress,
+    read_file,
+    worker::StartGate,
+    write_file, Stats, Strategy,
+};
+use anyhow::{bail, Context, Result};
+use humansize::{ISizeFormatter, BINARY};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Writes a temp file with every strategy, reads each one back with every
+/// strategy with verification enabled and checks that all strategies
+/// produced byte-identical files.
+pub async fn run(block_size: u64, count: u64) -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("raio-selftest-{}", std::process::id()));
+    fs::create_dir_all(&dir).context("failed to create temp dir")?;
+    let res = run_in(&dir, block_size, count).await;
+    let _ = fs::remove_dir_all(&dir);
+    res
+}
+
+async fn run_in(dir: &Path, block_size: u64, count: u64) -> Result<()> {
+    let mut failures = 0;
+    let mut reference: Option<(Strategy, PathBuf)> = None;
+    let offsets = Offsets::new(block_size, Mode::Sequential, 0, 0..block_size * count);
+
+    for strategy in Strategy::ALL {
+        let path = dir.join(format!("{strategy}.bin"));
+        fs::File::create(&path).context("failed to create temp file")?;
+        let file = path.to_str().context("temp path is not UTF-8")?;
+
+        let mut errors = Vec::new();
+        let mut progress = Progress::new(block_size * count);
+        let gate = &mut StartGate::none();
+        let stats = match write_file(
+            file,
+            &offsets,
+            0..count,
+            strategy,
+            None,
+            None,
+            &mut progress,
+            gate,
+        )
+        .await
+        {
+            Ok(stats) => Some(stats),
+            Err(err) => {
+                errors.push(format!("write failed: {err:#}"));
+                None
+            }
+        };
+        if stats.is_some() {
+            for reader in Strategy::ALL {
+                let mut progress = Progress::new(block_size * count);
+                let gate = &mut StartGate::none();
+                let res = read_file(
+                    file,
+                    0,
+                    &offsets,
+                    0..count,
+                    reader,
+                    None,
+                    None,
+                    Some(&mut matches_pattern),
+                    &mut progress,
+                    gate,
+                )
+                .await
+                .and_then(Stats::verified);
+                if let Err(err) = res {
+                    errors.push(format!("read back with {reader} failed: {err:#}"));
+                }
+            }
+            match &reference {
+                Some((reference, reference_path)) => {
+                    if !files_equal(reference_path, &path)? {
+                        errors.push(format!("file differs from the one written by {reference}"));
+                    }
+                }
+                None => reference = Some((strategy, path)),
+            }
+        }
+
+        match (stats, errors.is_empty()) {
+            (Some(stats), true) => println!(
+                "[  ok] {strategy:<16} {}/s",
+                ISizeFormatter::new(stats.bytes_per_sec(), BINARY),
+            ),
+            _ => {
+                failures += 1;
+                println!("[FAIL] {strategy:<16}");
+                for error in errors {
+                    println!("       {error}");
+                }
+            }
+        }
+    }
+
+    if failures > 0 {
+        bail!("{failures}/{} strategies failed", Strategy::ALL.len());
+    }
+    println!(
+        "all {} strategies produced identical files",
+        Strategy::ALL.len()
+    );
+
+    Ok(())
+}
+
+fn files_equal(a: &Path, b: &Path) -> Result<bool> {
+    let a = fs::read(a).context("failed to read temp file")?;
+    let b = fs::read(b).context("failed to read temp file")?;
+    Ok(a == b)
+}
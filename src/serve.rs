@@ -0,0 +1,220 @@
+//! Open, read and close cycles through io_uring, one per request, the way a
+//! static file server serves small files instead of streaming through one
+//! long-lived descriptor.
+
+use crate::{latency::Latency, make_block, ring};
+use anyhow::{anyhow, bail, Context, Result};
+use humansize::{ISizeFormatter, BINARY};
+use io_uring::{opcode, squeue::Flags, types, IoUring, Probe};
+use std::{
+    ffi::CString,
+    fs,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use tracing::{debug, info};
+
+/// The operations of one request, in order.
+const STEPS: [&str; 3] = ["openat", "read", "close"];
+
+/// Creates `files` files of `size` bytes in `dir` and serves `count`
+/// requests for them round-robin, `depth` at a time, each one opening,
+/// reading and closing a file. With direct descriptors the three operations
+/// are linked and submitted together, otherwise each one is submitted after
+/// the previous one completed. The files are freshly written, so the reads
+/// hit the page cache as for a hot set of static files.
+pub fn run(
+    dir: &Path,
+    files: u64,
+    size: u64,
+    count: u64,
+    depth: u64,
+    unlinked: bool,
+) -> Result<()> {
+    let paths = (0..files)
+        .map(|i| dir.join(format!("raio-serve-{i}")))
+        .collect::<Vec<_>>();
+    let res = create(&paths, size).and_then(|()| serve(dir, &paths, size, count, depth, unlinked));
+    for path in &paths {
+        if let Err(err) = fs::remove_file(path) {
+            debug!("failed to remove {}: {err}", path.display());
+        }
+    }
+    res
+}
+
+fn create(paths: &[PathBuf], size: u64) -> Result<()> {
+    let data = make_block(size, 0);
+    for path in paths {
+        fs::write(path, &data).with_context(|| format!("failed to create {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn serve(
+    dir: &Path,
+    paths: &[PathBuf],
+    size: u64,
+    count: u64,
+    depth: u64,
+    unlinked: bool,
+) -> Result<()> {
+    let dir_file =
+        fs::File::open(dir).with_context(|| format!("failed to open {}", dir.display()))?;
+    let dir_fd = types::Fd(dir_file.as_raw_fd());
+    let names = paths
+        .iter()
+        .map(|path| {
+            let name = path.file_name().expect("generated paths have file names");
+            CString::new(name.as_encoded_bytes()).expect("generated names have no NUL")
+        })
+        .collect::<Vec<_>>();
+
+    // Declared before the ring, which is dropped first with operations still
+    // in flight after a failure.
+    let mut bufs = vec![vec![0u8; size as usize]; depth as usize];
+    let mut ring = ring::new_ring((depth as u32 * 3).next_power_of_two())?;
+    check(&mut ring)?;
+    // Direct descriptors let the read and close refer to the file the openat
+    // installs, which is what allows linking the three.
+    let linked = !unlinked
+        && match ring.submitter().register_files_sparse(depth as u32) {
+            Ok(()) => true,
+            Err(err) => {
+                info!("direct descriptors are not supported ({err}), not linking the operations");
+                false
+            }
+        };
+    info!(
+        "serving {count} requests for {} files of {size} bytes, {depth} at a time, {}",
+        paths.len(),
+        if linked { "linked" } else { "unlinked" },
+    );
+
+    // Per slot: the request, the step that is in flight or, when linked, the
+    // completions still expected, its raw descriptor, and when it started.
+    let mut slots = vec![(0, 0, 0, Instant::now()); depth as usize];
+    let mut free = (0..depth as usize).rev().collect::<Vec<_>>();
+    let mut latency = Latency::default();
+    let mut next = 0;
+    let mut served = 0;
+    let start = Instant::now();
+    while served < count {
+        while next < count {
+            let Some(slot) = free.pop() else {
+                break;
+            };
+            let name = &names[(next % names.len() as u64) as usize];
+            let open = opcode::OpenAt::new(dir_fd, name.as_ptr()).flags(libc::O_RDONLY);
+            let buf = &mut bufs[slot];
+            if linked {
+                let fixed = types::Fixed(slot as u32);
+                let target = types::DestinationSlot::try_from_slot_target(slot as u32)
+                    .expect("slots are below the table size");
+                let entries = [
+                    open.file_index(Some(target)).build().flags(Flags::IO_LINK),
+                    opcode::Read::new(fixed, buf.as_mut_ptr(), size as u32)
+                        .build()
+                        .flags(Flags::IO_LINK),
+                    opcode::Close::new(fixed).build(),
+                ];
+                for (step, entry) in entries.into_iter().enumerate() {
+                    push(&mut ring, entry.user_data(user_data(slot, step)));
+                }
+                slots[slot] = (next, STEPS.len(), 0, Instant::now());
+            } else {
+                push(&mut ring, open.build().user_data(user_data(slot, 0)));
+                slots[slot] = (next, 0, 0, Instant::now());
+            }
+            next += 1;
+        }
+
+        ring.submit_and_wait(1)?;
+
+        for cqe in ring.completion().collect::<Vec<_>>() {
+            let (slot, step) = (cqe.user_data() as usize / 4, cqe.user_data() as usize % 4);
+            let (request, state, fd, started) = slots[slot];
+            let result = cqe.result();
+            if result < 0 {
+                let err = std::io::Error::from_raw_os_error(-result);
+                return Err(
+                    anyhow!(err).context(format!("{} of request {request} failed", STEPS[step]))
+                );
+            }
+            if step == 1 && result as u64 != size {
+                bail!("short read of request {request}: {result} of {size} bytes");
+            }
+
+            let done = if linked {
+                slots[slot].1 = state - 1;
+                state == 1
+            } else {
+                // The previous step completed, submit the next one.
+                match step {
+                    0 => {
+                        let fd = types::Fd(result);
+                        let buf = &mut bufs[slot];
+                        let entry = opcode::Read::new(fd, buf.as_mut_ptr(), size as u32).build();
+                        push(&mut ring, entry.user_data(user_data(slot, 1)));
+                        slots[slot] = (request, 1, result, started);
+                    }
+                    1 => {
+                        let entry = opcode::Close::new(types::Fd(fd)).build();
+                        push(&mut ring, entry.user_data(user_data(slot, 2)));
+                        slots[slot].1 = 2;
+                    }
+                    _ => {}
+                }
+                step == 2
+            };
+            if done {
+                latency.record(started.elapsed());
+                served += 1;
+                free.push(slot);
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let secs = elapsed.as_secs_f64();
+    println!(
+        "served {count} requests of {size} bytes in {secs:.6} seconds @ {:.0} requests/s, {}/s",
+        count as f64 / secs,
+        ISizeFormatter::new((count * size) as f64 / secs, BINARY),
+    );
+    println!("request latency: {latency}");
+    Ok(())
+}
+
+/// Fails unless the kernel supports the opcodes of a request.
+fn check(ring: &mut IoUring) -> Result<()> {
+    let mut probe = Probe::new();
+    ring.submitter()
+        .register_probe(&mut probe)
+        .context("failed to probe io_uring opcodes, they require Linux 5.6")?;
+    for (code, name) in [
+        (opcode::OpenAt::CODE, "IORING_OP_OPENAT"),
+        (opcode::Read::CODE, "IORING_OP_READ"),
+        (opcode::Close::CODE, "IORING_OP_CLOSE"),
+    ] {
+        if !probe.is_supported(code) {
+            bail!("{name} is not supported by this kernel");
+        }
+    }
+    Ok(())
+}
+
+fn user_data(slot: usize, step: usize) -> u64 {
+    (slot * 4 + step) as u64
+}
+
+fn push(ring: &mut IoUring, entry: io_uring::squeue::Entry) {
+    // The names and buffers outlive the ring's operations, and at most three
+    // entries per slot are queued at a time.
+    unsafe {
+        ring.submission()
+            .push(&entry)
+            .expect("submission queue is full");
+    }
+}
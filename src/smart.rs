@@ -0,0 +1,190 @@
+//! SMART and NVMe health attributes of the target device before and after a
+//! run, so that heavy write tests document the wear they cause.
+
+use crate::device::Device;
+use anyhow::{bail, Context, Result};
+use humansize::{SizeFormatter, BINARY};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, fs, io, os::unix::io::AsRawFd, process::Command};
+
+/// `_IOWR('N', 0x41, struct nvme_admin_cmd)`.
+const NVME_IOCTL_ADMIN_CMD: libc::c_ulong = 0xc048_4e41;
+const NVME_ADMIN_GET_LOG_PAGE: u8 = 0x02;
+const NVME_LOG_SMART: u32 = 0x02;
+const NVME_LOG_SMART_LEN: usize = 512;
+/// NVMe data units are thousands of 512-byte sectors.
+const NVME_DATA_UNIT: u64 = 512 * 1000;
+
+/// `struct nvme_admin_cmd` from `linux/nvme_ioctl.h`.
+#[repr(C)]
+#[derive(Default)]
+struct NvmeAdminCmd {
+    opcode: u8,
+    flags: u8,
+    rsvd1: u16,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    metadata: u64,
+    addr: u64,
+    metadata_len: u32,
+    data_len: u32,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+    timeout_ms: u32,
+    result: u32,
+}
+
+/// Attributes of the NVMe SMART / health log page and their byte offsets,
+/// each a little-endian integer of the given length.
+const NVME_ATTRIBUTES: [(&str, usize, usize); 14] = [
+    ("critical_warning", 0, 1),
+    ("temperature_kelvin", 1, 2),
+    ("available_spare_percent", 3, 1),
+    ("percentage_used", 5, 1),
+    ("data_units_read", 32, 16),
+    ("data_units_written", 48, 16),
+    ("host_read_commands", 64, 16),
+    ("host_write_commands", 80, 16),
+    ("controller_busy_minutes", 96, 16),
+    ("power_cycles", 112, 16),
+    ("power_on_hours", 128, 16),
+    ("unsafe_shutdowns", 144, 16),
+    ("media_errors", 160, 16),
+    ("error_log_entries", 176, 16),
+];
+
+/// Health attributes by name: the NVMe health log for NVMe devices, the raw
+/// values of the ATA SMART attributes from `smartctl` otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Health {
+    pub source: String,
+    pub attributes: BTreeMap<String, u64>,
+}
+
+impl Health {
+    pub fn read(device: &Device) -> Result<Self> {
+        let path = format!("/dev/{}", device.name);
+        if device.name.starts_with("nvme") {
+            nvme(&path)
+        } else {
+            smartctl(&path)
+        }
+    }
+}
+
+fn nvme(path: &str) -> Result<Health> {
+    let file = fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+    let mut log = [0u8; NVME_LOG_SMART_LEN];
+    let dwords = (NVME_LOG_SMART_LEN / 4 - 1) as u32;
+    let mut cmd = NvmeAdminCmd {
+        opcode: NVME_ADMIN_GET_LOG_PAGE,
+        // The controller-wide log rather than one namespace's.
+        nsid: u32::MAX,
+        addr: log.as_mut_ptr() as u64,
+        data_len: NVME_LOG_SMART_LEN as u32,
+        cdw10: dwords << 16 | NVME_LOG_SMART,
+        ..NvmeAdminCmd::default()
+    };
+    if unsafe { libc::ioctl(file.as_raw_fd(), NVME_IOCTL_ADMIN_CMD, &mut cmd) } < 0 {
+        let err = io::Error::last_os_error();
+        if matches!(err.raw_os_error(), Some(libc::EACCES | libc::EPERM)) {
+            bail!("reading the NVMe health log of {path} requires root");
+        }
+        return Err(err).with_context(|| format!("failed to read the NVMe health log of {path}"));
+    }
+    let attributes = NVME_ATTRIBUTES
+        .iter()
+        .map(|&(name, offset, len)| {
+            let mut bytes = [0u8; 16];
+            bytes[..len].copy_from_slice(&log[offset..offset + len]);
+            let value = u128::from_le_bytes(bytes).min(u64::MAX as u128) as u64;
+            (name.to_string(), value)
+        })
+        .collect();
+    Ok(Health {
+        source: "nvme".to_string(),
+        attributes,
+    })
+}
+
+fn smartctl(path: &str) -> Result<Health> {
+    // The exit status is a bit mask that also flags past problems of the
+    // drive, so the JSON decides whether the attributes could be read.
+    let output = Command::new("smartctl")
+        .args(["--json", "-A", path])
+        .output()
+        .context("failed to run smartctl, is smartmontools installed?")?;
+    let json: serde_json::Value =
+        serde_json::from_slice(&output.stdout).context("invalid JSON from smartctl")?;
+    let Some(table) = json["ata_smart_attributes"]["table"].as_array() else {
+        let messages = json["smartctl"]["messages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|message| message["string"].as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        bail!("smartctl reported no SMART attributes for {path}: {messages}");
+    };
+    let attributes = table
+        .iter()
+        .filter_map(|attribute| {
+            let name = attribute["name"].as_str()?;
+            let id = attribute["id"].as_u64()?;
+            let raw = attribute["raw"]["value"].as_u64()?;
+            Some((format!("{id}_{name}"), raw))
+        })
+        .collect();
+    Ok(Health {
+        source: "smartctl".to_string(),
+        attributes,
+    })
+}
+
+/// The health of a device before and after a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Wear {
+    pub device: String,
+    pub before: Health,
+    pub after: Health,
+}
+
+impl Wear {
+    /// Attributes that changed during the run and by how much.
+    pub fn changes(&self) -> Vec<(&str, i128)> {
+        self.after
+            .attributes
+            .iter()
+            .filter_map(|(name, &after)| {
+                let before = *self.before.attributes.get(name)?;
+                let change = after as i128 - before as i128;
+                (change != 0).then_some((name.as_str(), change))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Wear {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changes = self.changes();
+        if changes.is_empty() {
+            return f.write_str("no attribute changed");
+        }
+        for (i, (name, change)) in changes.into_iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{name} {change:+}")?;
+            if name.starts_with("data_units_") && change > 0 {
+                let bytes = change as u64 * NVME_DATA_UNIT;
+                write!(f, " ({})", SizeFormatter::new(bytes, BINARY))?;
+            }
+        }
+        Ok(())
+    }
+}
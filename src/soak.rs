@@ -0,0 +1,215 @@
+//! Throughput and tail latency per window of a long run, to see them drift
+//! over its course, e.g. when an SSD's SLC cache runs out or its garbage
+//! collection kicks in.
+
+use crate::latency::{Latency, Short};
+use humansize::{ISizeFormatter, BINARY};
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+#[derive(Debug, Clone, Default)]
+pub struct Soak {
+    window: Duration,
+    /// Empty histogram each window starts from.
+    latency: Latency,
+    windows: Vec<(u64, Latency)>,
+    /// Time of the last operation since the start of the run.
+    last: Duration,
+}
+
+/// One window as printed and stored in results files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Window {
+    pub start_seconds: f64,
+    pub seconds: f64,
+    pub bytes_per_sec: f64,
+    pub p99_us: f64,
+    pub p999_us: f64,
+}
+
+impl Soak {
+    pub fn new(window: Duration, latency: &Latency) -> Self {
+        Self {
+            window,
+            latency: latency.empty_like(),
+            windows: Vec::new(),
+            last: Duration::ZERO,
+        }
+    }
+
+    /// Records an operation. Returns the window before the operation's
+    /// window once the first operation of a new window is recorded.
+    pub fn add(&mut self, since_start: Duration, bytes: u64, latency: Duration) -> Option<Window> {
+        let i = (since_start.as_nanos() / self.window.as_nanos()) as usize;
+        let finished = (i >= self.windows.len() && !self.windows.is_empty())
+            .then(|| self.summary(self.windows.len() - 1, self.window));
+        while self.windows.len() <= i {
+            self.windows.push((0, self.latency.empty_like()));
+        }
+        let (total, histogram) = &mut self.windows[i];
+        *total += bytes;
+        histogram.record(latency);
+        self.last = self.last.max(since_start);
+        finished
+    }
+
+    /// Adds `other`, which started at the same time, e.g. another worker.
+    pub fn merge(&mut self, other: &Self) {
+        if self.windows.is_empty() {
+            *self = other.clone();
+            return;
+        }
+        for (i, (bytes, latency)) in other.windows.iter().enumerate() {
+            match self.windows.get_mut(i) {
+                Some((total, histogram)) => {
+                    *total += bytes;
+                    histogram.merge(latency);
+                }
+                None => self.windows.push((*bytes, latency.clone())),
+            }
+        }
+        self.last = self.last.max(other.last);
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// All windows, the last one only up to the last operation.
+    pub fn windows(&self) -> Vec<Window> {
+        let Some(last) = self.windows.len().checked_sub(1) else {
+            return Vec::new();
+        };
+        (0..self.windows.len())
+            .map(|i| {
+                let length = if i == last {
+                    self.last.saturating_sub(self.window * i as u32)
+                } else {
+                    self.window
+                };
+                self.summary(i, length)
+            })
+            .collect()
+    }
+
+    fn summary(&self, i: usize, length: Duration) -> Window {
+        let (bytes, latency) = &self.windows[i];
+        let us = |latency: Duration| latency.as_secs_f64() * 1e6;
+        Window {
+            start_seconds: (self.window * i as u32).as_secs_f64(),
+            seconds: length.as_secs_f64(),
+            bytes_per_sec: *bytes as f64 / length.as_secs_f64().max(1e-9),
+            p99_us: us(latency.percentile(99.0)),
+            p999_us: us(latency.percentile(99.9)),
+        }
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let short = |us: f64| Short(Duration::from_secs_f64(us / 1e6));
+        write!(
+            f,
+            "{}..{} @ {}/s p99={} p99.9={}",
+            short(self.start_seconds * 1e6),
+            short((self.start_seconds + self.seconds) * 1e6),
+            ISizeFormatter::new(self.bytes_per_sec, BINARY),
+            short(self.p99_us),
+            short(self.p999_us),
+        )
+    }
+}
+
+/// The change from the first to the last full window, `None` with fewer
+/// than two full windows.
+pub fn drift(windows: &[Window], window: Duration) -> Option<String> {
+    let full = windows
+        .iter()
+        .filter(|w| w.seconds >= window.as_secs_f64())
+        .collect::<Vec<_>>();
+    let (first, last) = match full.as_slice() {
+        [first, .., last] => (first, last),
+        _ => return None,
+    };
+    let change = |before: f64, after: f64| (after / before - 1.0) * 100.0;
+    Some(format!(
+        "throughput {:+.1}%, p99 {:+.1}%, p99.9 {:+.1}%",
+        change(first.bytes_per_sec, last.bytes_per_sec),
+        change(first.p99_us, last.p99_us),
+        change(first.p999_us, last.p999_us),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+    const US: Duration = Duration::from_micros(1);
+
+    fn soak() -> Soak {
+        Soak::new(100 * MS, &Latency::default())
+    }
+
+    #[test]
+    fn windows_split_by_time() {
+        let mut soak = soak();
+        assert!(soak.add(10 * MS, 1000, 10 * US).is_none());
+        assert!(soak.add(90 * MS, 1000, 10 * US).is_none());
+        let finished = soak.add(150 * MS, 500, 20 * US).unwrap();
+        assert_eq!((finished.start_seconds, finished.seconds), (0.0, 0.1));
+        assert_eq!(finished.bytes_per_sec, 20_000.0);
+
+        let windows = soak.windows();
+        assert_eq!(windows.len(), 2);
+        // The last window only lasts until the last operation.
+        assert_eq!((windows[1].start_seconds, windows[1].seconds), (0.1, 0.05));
+        assert_eq!(windows[1].bytes_per_sec, 10_000.0);
+    }
+
+    #[test]
+    fn windows_without_operations_are_empty() {
+        let mut soak = soak();
+        soak.add(10 * MS, 1000, 10 * US);
+        soak.add(250 * MS, 1000, 10 * US);
+        let windows = soak.windows();
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[1].bytes_per_sec, 0.0);
+    }
+
+    #[test]
+    fn merge_adds_windows() {
+        let mut soak = soak();
+        soak.add(10 * MS, 1000, 10 * US);
+        let mut other = self::soak();
+        other.add(20 * MS, 1000, 10 * US);
+        other.add(120 * MS, 1000, 10 * US);
+        soak.merge(&other);
+        let windows = soak.windows();
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].bytes_per_sec, 20_000.0);
+    }
+
+    #[test]
+    fn drift_compares_the_first_and_last_full_window() {
+        let window = |start_seconds, seconds, bytes_per_sec, p99_us| Window {
+            start_seconds,
+            seconds,
+            bytes_per_sec,
+            p99_us,
+            p999_us: p99_us * 2.0,
+        };
+        let windows = [
+            window(0.0, 1.0, 100.0, 10.0),
+            window(1.0, 1.0, 90.0, 20.0),
+            window(2.0, 1.0, 50.0, 40.0),
+            window(3.0, 0.5, 10.0, 80.0),
+        ];
+        let second = Duration::from_secs(1);
+        assert_eq!(
+            drift(&windows, second).unwrap(),
+            "throughput -50.0%, p99 +300.0%, p99.9 +300.0%"
+        );
+        assert_eq!(drift(&windows[..1], second), None);
+    }
+}
@@ -0,0 +1,200 @@
+//! Streaming to stdout or from stdin with `-f -`, so raio can sit in a shell
+//! pipeline the way pv or dd do, e.g. `raio write -f - -c 1000 | ssh host
+//! raio read -f - -c 1000 --verify`.
+
+use crate::{latency::Latency, make_block, verify_block, Job, Strategy};
+use anyhow::{bail, Context, Result};
+use humansize::{ISizeFormatter, BINARY};
+use io_uring::{opcode, types, IoUring};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    mem::ManuallyDrop,
+    os::unix::io::{AsRawFd, FromRawFd},
+    time::Instant,
+};
+use tracing::info;
+
+/// The file name that selects stdin or stdout.
+pub const STDIO: &str = "-";
+
+/// Writes `count` generated blocks to stdout, or reads up to `count` blocks
+/// from stdin until its end. Streams have no offsets, so a ring keeps one
+/// operation in flight to preserve the order. The results go to stderr,
+/// since stdout may carry the data.
+pub fn run(job: &Job, write: bool, verify: bool) -> Result<()> {
+    check(job)?;
+    let Job {
+        block_size,
+        count,
+        strategy,
+        ..
+    } = *job;
+    let mut io = Io::new(strategy, write)?;
+    let mut latency = Latency::new(job.hist_digits, job.hist_max)?;
+    info!(
+        "{} up to {count} blocks of {block_size} bytes {} with {strategy}",
+        if write { "writing" } else { "reading" },
+        if write { "to stdout" } else { "from stdin" },
+    );
+
+    let mut buf = vec![0; block_size as usize];
+    let mut bytes = 0;
+    let mut mismatches = 0;
+    let start = Instant::now();
+    for i in 0..count {
+        let issued = Instant::now();
+        let n = if write {
+            buf = make_block(block_size, i * block_size / 64);
+            io.write_all(&buf)
+                .with_context(|| format!("failed to write block {i} to stdout"))?;
+            buf.len()
+        } else {
+            let n = io
+                .read_full(&mut buf)
+                .with_context(|| format!("failed to read block {i} from stdin"))?;
+            if verify && !verify_block(&buf[..n], i * block_size / 64) {
+                mismatches += 1;
+            }
+            n
+        };
+        latency.record(issued.elapsed());
+        bytes += n as u64;
+        if n < buf.len() {
+            break;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    eprintln!(
+        "{} {bytes} bytes {} in {:.6} seconds @ {}/s",
+        if write { "wrote" } else { "read" },
+        if write { "to stdout" } else { "from stdin" },
+        elapsed.as_secs_f64(),
+        ISizeFormatter::new(bytes as f64 / elapsed.as_secs_f64(), BINARY),
+    );
+    if !latency.is_empty() {
+        eprintln!("latency: {latency}");
+    }
+    if verify {
+        if mismatches > 0 {
+            bail!("{mismatches} blocks from stdin failed verification");
+        }
+        eprintln!("verified {} bytes", bytes);
+    }
+    Ok(())
+}
+
+/// Rejects options that need a seekable target or a file to inspect.
+fn check(job: &Job) -> Result<()> {
+    if !matches!(
+        job.strategy,
+        Strategy::Std | Strategy::IOUring | Strategy::IOUring2 | Strategy::IOUring8
+    ) {
+        bail!(
+            "strategy {} cannot stream, use std or one of the io_uring strategies",
+            job.strategy
+        );
+    }
+    for (set, flag) in [
+        (job.random, "--random"),
+        (job.offsets.is_some(), "--offsets"),
+        (job.jobs > 1, "--jobs"),
+        (job.checkpoint.is_some(), "--checkpoint"),
+        (job.dry_run, "--dry-run"),
+        (job.estimate, "--estimate"),
+        (job.durable, "--durable"),
+        (job.hash.is_some(), "--hash"),
+        (job.extents, "--extents"),
+        (job.output.is_some(), "--output"),
+        (job.report.is_some(), "--report"),
+    ] {
+        if set {
+            bail!("{flag} does not apply to stdin or stdout");
+        }
+    }
+    Ok(())
+}
+
+/// stdin or stdout, through std or a ring.
+struct Io {
+    /// Unbuffered, unlike `io::stdout()`, and not closed on drop.
+    file: ManuallyDrop<fs::File>,
+    ring: Option<IoUring>,
+}
+
+impl Io {
+    fn new(strategy: Strategy, write: bool) -> Result<Self> {
+        let fd = if write {
+            io::stdout().as_raw_fd()
+        } else {
+            io::stdin().as_raw_fd()
+        };
+        Ok(Self {
+            file: ManuallyDrop::new(unsafe { fs::File::from_raw_fd(fd) }),
+            ring: match strategy {
+                Strategy::Std => None,
+                _ => Some(IoUring::new(2).context("failed to create io_uring")?),
+            },
+        })
+    }
+
+    fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let n = match &mut self.ring {
+                None => self.file.write(buf)?,
+                Some(ring) => {
+                    let fd = types::Fd(self.file.as_raw_fd());
+                    let entry = opcode::Write::new(fd, buf.as_ptr(), buf.len() as u32)
+                        .offset(u64::MAX)
+                        .build();
+                    complete(ring, &entry)?
+                }
+            };
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+
+    /// Fills `buf` unless the stream ends first, returning the bytes read.
+    fn read_full(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let rest = &mut buf[filled..];
+            let n = match &mut self.ring {
+                None => self.file.read(rest)?,
+                Some(ring) => {
+                    let fd = types::Fd(self.file.as_raw_fd());
+                    let entry = opcode::Read::new(fd, rest.as_mut_ptr(), rest.len() as u32)
+                        .offset(u64::MAX)
+                        .build();
+                    complete(ring, &entry)?
+                }
+            };
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        Ok(filled)
+    }
+}
+
+/// Submits `entry` alone and returns its result.
+fn complete(ring: &mut IoUring, entry: &io_uring::squeue::Entry) -> io::Result<usize> {
+    // The buffer outlives the operation, which completes before returning.
+    unsafe {
+        ring.submission()
+            .push(entry)
+            .expect("submission queue is full");
+    }
+    ring.submit_and_wait(1)?;
+    let cqe = ring.completion().next().expect("completion queue is empty");
+    match cqe.result() {
+        res if res < 0 => Err(io::Error::from_raw_os_error(-res)),
+        res => Ok(res as usize),
+    }
+}
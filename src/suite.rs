@@ -0,0 +1,393 @@
+//! `raio suite`: a standard battery of named workloads against one target,
+//! as a one-command characterization of a device.
+
+use crate::{
+    fill_block, history,
+    latency::{Latency, Short},
+    make_block,
+    offsets::{self, splitmix64, Mode, Offsets},
+    progress::Progress,
+    report,
+    results::Results,
+    ring,
+    validate::Target,
+    worker::StartGate,
+    write_file, Cmd, Job, Stats, Strategy,
+};
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use humansize::{ISizeFormatter, SizeFormatter, BINARY};
+use io_uring::{opcode, types};
+use std::{
+    fmt, fs,
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::Path,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// Sequential 1 MiB writes, synced at the end, the bulk write rate
+    #[value(name = "1M-seqwrite")]
+    SeqWrite1M,
+    /// Sequential 128 KiB reads, the streaming read rate
+    #[value(name = "128k-seqread")]
+    SeqRead128k,
+    /// Random 4 KiB reads, 8 in flight, the small read IOPS
+    #[value(name = "4k-randread")]
+    RandRead4k,
+    /// Random 4 KiB O_DSYNC writes one at a time, the commit latency of a
+    /// database
+    #[value(name = "4k-randwrite-sync")]
+    RandWriteSync4k,
+    /// Random 4 KiB operations, 70% reads and 30% writes, 16 in flight
+    #[value(name = "mixed-70-30")]
+    Mixed7030,
+}
+
+impl Preset {
+    /// In the order they run, the write first so that the reads find data.
+    pub const ALL: [Self; 5] = [
+        Self::SeqWrite1M,
+        Self::SeqRead128k,
+        Self::RandRead4k,
+        Self::RandWriteSync4k,
+        Self::Mixed7030,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::SeqWrite1M => "1M-seqwrite",
+            Self::SeqRead128k => "128k-seqread",
+            Self::RandRead4k => "4k-randread",
+            Self::RandWriteSync4k => "4k-randwrite-sync",
+            Self::Mixed7030 => "mixed-70-30",
+        }
+    }
+
+    fn block_size(self) -> u64 {
+        match self {
+            Self::SeqWrite1M => 1 << 20,
+            Self::SeqRead128k => 128 << 10,
+            Self::RandRead4k | Self::RandWriteSync4k | Self::Mixed7030 => 4096,
+        }
+    }
+
+    /// The arguments of the equivalent `raio write` or `raio read`, `None`
+    /// for the presets that run on a ring of their own, since per-operation
+    /// syncs and mixed operations are not modes of a single run.
+    fn args(self) -> Option<(bool, &'static [&'static str])> {
+        match self {
+            Self::SeqWrite1M => Some((true, &["--strategy", "io_uring8", "--durable"])),
+            Self::SeqRead128k => Some((false, &["--strategy", "io_uring8"])),
+            Self::RandRead4k => Some((false, &["--strategy", "io_uring8", "--random"])),
+            Self::RandWriteSync4k | Self::Mixed7030 => None,
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parses preset arguments with the same defaults as the command line.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    job: Job,
+}
+
+fn job(file: &str, block_size: u64, count: u64, args: &[&str]) -> Job {
+    let (block_size, count) = (block_size.to_string(), count.to_string());
+    let argv = ["raio", "-f", file, "-s", &block_size, "-c", &count]
+        .into_iter()
+        .chain(args.iter().copied());
+    Args::try_parse_from(argv)
+        .expect("preset arguments are valid")
+        .job
+}
+
+/// Runs `presets` against `size` bytes of `file`, which is written first if
+/// it is shorter, and prints one table of their results. Random presets
+/// transfer up to `ops` blocks each. Every preset writes or reads the
+/// target as the equivalent `raio` command would, including the ones that
+/// overwrite it.
+pub async fn run(
+    cmd: &Cmd,
+    file: &str,
+    size: u64,
+    ops: u64,
+    presets: &[Preset],
+    report_path: Option<&Path>,
+) -> Result<()> {
+    if size < 1 << 20 {
+        bail!("--size has to be at least 1 MiB");
+    }
+    prepare(file, size, presets).await?;
+
+    let mut runs = Vec::with_capacity(presets.len());
+    let mut failures = 0;
+    for &preset in presets {
+        let block_size = preset.block_size();
+        let count = match preset {
+            Preset::SeqWrite1M | Preset::SeqRead128k => size / block_size,
+            _ => ops.min(size / block_size),
+        };
+        let res = match preset.args() {
+            Some((write, args)) => {
+                let op = if write { "write" } else { "read" };
+                println!(
+                    "== {preset}: raio {op} -f {file} -s {block_size} -c {count} {}",
+                    args.join(" ")
+                );
+                let job = job(file, block_size, count, args);
+                cmd.bench(&job, write, false)
+                    .await
+                    .map(|results| results.expect("presets are neither dry runs nor streams"))
+            }
+            None => {
+                println!("== {preset}");
+                let (read_percent, depth) = match preset {
+                    Preset::Mixed7030 => (70, 16),
+                    _ => (0, 1),
+                };
+                let res = mixed(file, block_size, count, size, read_percent, depth);
+                if let (Ok(results), false) = (&res, cmd.no_history) {
+                    if let Err(err) = history::record(results, None) {
+                        warn!("failed to record the run in the history: {err:#}");
+                    }
+                }
+                res
+            }
+        };
+        println!();
+        match res {
+            Ok(results) => runs.push((preset, results)),
+            Err(err) => {
+                warn!("{preset} failed: {err:#}");
+                failures += 1;
+            }
+        }
+    }
+
+    println!(
+        "{:<18} {:>14} {:>10} {:>9} {:>9}",
+        "preset", "throughput", "IOPS", "p50", "p99"
+    );
+    for (preset, results) in &runs {
+        let us = |us: f64| Short(Duration::from_secs_f64(us / 1e6)).to_string();
+        let (p50, p99) = match &results.latency {
+            Some(latency) => (us(latency.p50_us), us(latency.p99_us)),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!(
+            "{:<18} {:>14} {:>10.0} {p50:>9} {p99:>9}",
+            preset.as_str(),
+            format!("{}/s", ISizeFormatter::new(results.bytes_per_sec, BINARY)),
+            results.iops,
+        );
+    }
+    if let Some(path) = report_path {
+        let runs = runs
+            .iter()
+            .map(|(preset, results)| (preset.to_string(), results.clone()))
+            .collect::<Vec<_>>();
+        report::write(path, &runs)?;
+        println!("report: {}", path.display());
+    }
+
+    if failures > 0 {
+        bail!("{failures}/{} presets failed", presets.len());
+    }
+    Ok(())
+}
+
+/// Writes `file` up to `size` bytes unless it already holds them or only
+/// the sequential write runs, which lays it out itself.
+async fn prepare(file: &str, size: u64, presets: &[Preset]) -> Result<()> {
+    let len = match Target::of(file)? {
+        Target::Missing => {
+            fs::File::create(file).with_context(|| format!("failed to create {file}"))?;
+            0
+        }
+        Target::File(len) => len,
+        Target::BlockDevice(len) if len < size => {
+            bail!(
+                "--size {} is beyond the end of {file} at {}",
+                SizeFormatter::new(size, BINARY),
+                SizeFormatter::new(len, BINARY),
+            )
+        }
+        Target::BlockDevice(_) => size,
+        Target::Other => bail!("{file} is neither a regular file nor a block device"),
+    };
+    if len >= size || presets.first() == Some(&Preset::SeqWrite1M) {
+        return Ok(());
+    }
+
+    info!(
+        "writing {} of {file} for the presets to read",
+        SizeFormatter::new(size, BINARY)
+    );
+    let block_size = 1 << 20;
+    let count = size / block_size;
+    let offsets = Offsets::new(block_size, Mode::Sequential, 0, 0..count * block_size);
+    let mut progress = Progress::new(count * block_size);
+    let res = write_file(
+        file,
+        &offsets,
+        0..count,
+        Strategy::IOUring8,
+        None,
+        None,
+        &mut progress,
+        &mut StartGate::none(),
+    )
+    .await;
+    progress.finish(res.is_ok());
+    res.with_context(|| format!("failed to prepare {file}"))?;
+    Ok(())
+}
+
+/// Transfers `count` blocks at random offsets within `size` bytes through
+/// one ring with `depth` in flight. Each block is a read with a chance of
+/// `read_percent`, otherwise a write, and writes are O_DSYNC without any
+/// reads.
+fn mixed(
+    file: &str,
+    block_size: u64,
+    count: u64,
+    size: u64,
+    read_percent: u64,
+    depth: usize,
+) -> Result<Results> {
+    let dsync = read_percent == 0;
+    let handle = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(if dsync { libc::O_DSYNC } else { 0 })
+        .open(file)
+        .with_context(|| format!("failed to open {file}"))?;
+    let fd = types::Fd(handle.as_raw_fd());
+    let seed = offsets::random_seed();
+    let offsets = Offsets::new(
+        block_size,
+        Mode::Random {
+            seed,
+            align: block_size,
+        },
+        0,
+        0..size,
+    );
+    let is_read = |i: u64| splitmix64(seed ^ i) % 100 < read_percent;
+    info!(
+        "{count} random {} of {block_size} bytes, {depth} in flight{}",
+        match read_percent {
+            0 => "writes".to_string(),
+            100 => "reads".to_string(),
+            percent => format!("operations, {percent}% reads"),
+        },
+        if dsync { ", O_DSYNC" } else { "" },
+    );
+
+    // Declared before the ring, which is dropped first with operations still
+    // in flight after a failure.
+    let mut bufs = (0..depth)
+        .map(|_| make_block(block_size, 0))
+        .collect::<Vec<_>>();
+    let mut ring = ring::new_ring((depth as u32).next_power_of_two())?;
+    let mut issued = vec![(0, Instant::now()); depth];
+    let mut free = (0..depth).rev().collect::<Vec<_>>();
+    let mut latency = Latency::default();
+    let mut progress = Progress::new(count * block_size);
+    let (mut reads, mut next, mut done) = (0, 0, 0);
+    let start = Instant::now();
+    while done < count {
+        while next < count {
+            let Some(slot) = free.pop() else {
+                break;
+            };
+            let pos = offsets.get(next);
+            let entry = if is_read(next) {
+                reads += 1;
+                opcode::Read::new(fd, bufs[slot].as_mut_ptr(), block_size as u32)
+                    .offset(pos)
+                    .build()
+            } else {
+                // Writes carry the pattern of their offset, like `raio write`.
+                fill_block(&mut bufs[slot], pos / 64);
+                opcode::Write::new(fd, bufs[slot].as_ptr(), block_size as u32)
+                    .offset(pos)
+                    .build()
+            }
+            .user_data(slot as u64);
+            // The buffer stays untouched until the operation completes.
+            unsafe {
+                ring.submission()
+                    .push(&entry)
+                    .expect("submission queue is full");
+            }
+            issued[slot] = (next, Instant::now());
+            next += 1;
+        }
+
+        ring.submit_and_wait(1)?;
+
+        for cqe in ring.completion().collect::<Vec<_>>() {
+            let slot = cqe.user_data() as usize;
+            let (i, started) = issued[slot];
+            let elapsed = started.elapsed();
+            let op = if is_read(i) { "read" } else { "write" };
+            match cqe.result() {
+                res if res < 0 => {
+                    return Err(std::io::Error::from_raw_os_error(-res)).with_context(|| {
+                        format!("{op} of block {i} (offset {}) failed", offsets.get(i))
+                    });
+                }
+                res if res as u64 != block_size => {
+                    bail!("short {op} of block {i}: {res} of {block_size} bytes");
+                }
+                _ => {}
+            }
+            latency.record(elapsed);
+            progress.add(i, block_size, elapsed);
+            free.push(slot);
+            done += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+    progress.finish(true);
+
+    let stats = Stats {
+        bytes: count * block_size,
+        transferred: count * block_size,
+        ops: count,
+        elapsed,
+        latency,
+        ..Stats::default()
+    };
+    println!(
+        "{count} operations ({reads} reads) in {:.6} seconds @ {}/s, {:.0} IOPS",
+        elapsed.as_secs_f64(),
+        ISizeFormatter::new(stats.bytes_per_sec(), BINARY),
+        stats.iops(),
+    );
+    println!("latency: {}", stats.latency);
+
+    let job = job(
+        file,
+        block_size,
+        count,
+        &["--strategy", "io_uring", "--random"],
+    );
+    let mut results = Results::new(&job, reads == 0, false, &stats);
+    if reads > 0 {
+        results.op = "mixed".to_string();
+    }
+    // The operations did not run on a worker that measured its CPU time.
+    results.efficiency = None;
+    Ok(results)
+}
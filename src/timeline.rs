@@ -0,0 +1,75 @@
+//! Bytes completed over the course of a run, for throughput charts.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Intervals kept before neighbouring intervals are merged.
+const MAX_POINTS: usize = 1000;
+
+/// Bytes completed per interval since the start of the run. The interval
+/// starts at 100ms and doubles whenever a run outgrows [`MAX_POINTS`]
+/// intervals, so long runs take bounded memory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeline {
+    pub interval_ms: u64,
+    pub bytes: Vec<u64>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            interval_ms: 100,
+            bytes: Vec::new(),
+        }
+    }
+}
+
+impl Timeline {
+    pub fn add(&mut self, since_start: Duration, bytes: u64) {
+        let ms = since_start.as_millis() as u64;
+        while ms / self.interval_ms >= MAX_POINTS as u64 {
+            self.coarsen();
+        }
+        let i = (ms / self.interval_ms) as usize;
+        if self.bytes.len() <= i {
+            self.bytes.resize(i + 1, 0);
+        }
+        self.bytes[i] += bytes;
+    }
+
+    /// Adds `other`, which started at the same time, e.g. another worker.
+    pub fn merge(&mut self, other: &Self) {
+        let mut other = other.clone();
+        while other.interval_ms < self.interval_ms {
+            other.coarsen();
+        }
+        while self.interval_ms < other.interval_ms {
+            self.coarsen();
+        }
+        if self.bytes.len() < other.bytes.len() {
+            self.bytes.resize(other.bytes.len(), 0);
+        }
+        for (total, bytes) in self.bytes.iter_mut().zip(other.bytes) {
+            *total += bytes;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Bytes per second in each interval, keyed by the end of the interval
+    /// in seconds.
+    pub fn rates(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        let secs = self.interval_ms as f64 / 1000.0;
+        self.bytes
+            .iter()
+            .enumerate()
+            .map(move |(i, &bytes)| ((i + 1) as f64 * secs, bytes as f64 / secs))
+    }
+
+    fn coarsen(&mut self) {
+        self.interval_ms *= 2;
+        self.bytes = self.bytes.chunks(2).map(|pair| pair.iter().sum()).collect();
+    }
+}
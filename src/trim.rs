@@ -0,0 +1,160 @@
+//! Writes interleaved with discards of previously written blocks, the
+//! pattern of log-structured stores that free old segments while appending.
+
+use crate::{
+    latency::Latency,
+    make_block_mem_aligned, mem_aligned_free, ring,
+    validate::{self, Target},
+};
+use anyhow::{bail, Context, Result};
+use humansize::{ISizeFormatter, BINARY};
+use io_uring::{opcode, types};
+use std::{fs, os::unix::io::AsRawFd, time::Instant};
+use tracing::{debug, info, trace, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Write,
+    Discard,
+}
+
+/// Writes `count` blocks sequentially and, after every write, discards the
+/// oldest written block at `ratio` discards per write. Discards are issued as
+/// `fallocate(PUNCH_HOLE)`, which filesystems and block devices turn into
+/// discards of the underlying blocks.
+pub fn run(path: &str, block_size: u64, count: u64, ratio: f64, depth: usize) -> Result<()> {
+    validate::block_size(block_size)?;
+    let Some(end) = count.checked_mul(block_size) else {
+        bail!("{count} blocks of {block_size} bytes are beyond the largest possible offset");
+    };
+    match Target::of(path)? {
+        Target::Missing => bail!("{path} does not exist"),
+        target => validate::range(path, target, end, true)?,
+    }
+    let mut ring = ring::new_ring((depth as u32).next_power_of_two())?;
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("failed to open {path}"))?;
+    let fd = types::Fd(file.as_raw_fd());
+    info!("writing {count} blocks of {block_size} bytes with {ratio} discards per write, {depth} in flight");
+
+    let mut slots = vec![None; depth];
+    let mut free = (0..depth).rev().collect::<Vec<_>>();
+    let mut written = vec![false; count as usize];
+    let mut write_latency = Latency::default();
+    let mut discard_latency = Latency::default();
+
+    let mut next_write = 0;
+    let mut next_discard = 0;
+    let mut debt = 0.0;
+    let mut in_flight = 0;
+    let mut discarded = 0;
+    let start = Instant::now();
+    let res = (|| {
+        while next_write < count || in_flight > 0 {
+            while let Some(&slot) = free.last() {
+                let (op, block, buf) =
+                    if debt >= 1.0 && next_discard < next_write && written[next_discard as usize] {
+                        debt -= 1.0;
+                        next_discard += 1;
+                        (Op::Discard, next_discard - 1, std::ptr::null_mut())
+                    } else if next_write < count {
+                        debt += ratio;
+                        next_write += 1;
+                        let block = next_write - 1;
+                        let buf = make_block_mem_aligned(block_size, block * block_size / 64)?;
+                        (Op::Write, block, buf)
+                    } else {
+                        break;
+                    };
+
+                let entry = match op {
+                    Op::Write => opcode::Write::new(fd, buf, block_size as _)
+                        .offset(block * block_size)
+                        .build(),
+                    Op::Discard => opcode::Fallocate::new(fd, block_size)
+                        .offset(block * block_size)
+                        .mode(libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE)
+                        .build(),
+                };
+                // Note that the developer needs to ensure
+                // that the entry pushed into submission queue is valid (e.g. fd, buffer).
+                unsafe {
+                    ring.submission()
+                        .push(&entry.user_data(slot as u64))
+                        .expect("submission queue is full");
+                }
+                free.pop();
+                slots[slot] = Some((op, block, buf, Instant::now()));
+                in_flight += 1;
+            }
+
+            ring.submit_and_wait(1)?;
+
+            let completed = ring.completion().collect::<Vec<_>>();
+            in_flight -= completed.len();
+            for cqe in completed {
+                let slot = cqe.user_data() as usize;
+                let (op, block, buf, issued) = slots[slot].take().expect("slot is in flight");
+                trace!("{op:?} result: {} @ {block}", cqe.result());
+                free.push(slot);
+
+                if cqe.result() < 0 {
+                    if op == Op::Write {
+                        mem_aligned_free(buf, block_size as usize, 4096);
+                    }
+                    let err = std::io::Error::from_raw_os_error(-cqe.result());
+                    let op = if op == Op::Write { "write" } else { "discard" };
+                    return Err(err).with_context(|| {
+                        format!(
+                            "{op} of block {block} (offset {}, {block_size} bytes) failed",
+                            block * block_size
+                        )
+                    });
+                }
+                match op {
+                    Op::Write => {
+                        mem_aligned_free(buf, block_size as usize, 4096);
+                        write_latency.record(issued.elapsed());
+                        written[block as usize] = true;
+                    }
+                    Op::Discard => {
+                        discard_latency.record(issued.elapsed());
+                        discarded += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    })();
+    let elapsed = start.elapsed();
+    // The kernel may still write from the buffers of writes in flight after
+    // a failure.
+    if let Err(err) = ring::drain(&mut ring, in_flight) {
+        warn!("{err:#}, leaking the buffers in flight");
+        res?;
+        return Err(err);
+    }
+    for (op, _, buf, _) in slots.into_iter().flatten() {
+        if op == Op::Write {
+            mem_aligned_free(buf, block_size as usize, 4096);
+        }
+    }
+    res?;
+    debug!("{} discards still owed at the end", debt as u64);
+
+    println!(
+        "written {} bytes and discarded {} bytes in {:.6} seconds @ {}/s written",
+        block_size * count,
+        block_size * discarded,
+        elapsed.as_secs_f64(),
+        ISizeFormatter::new((block_size * count) as f64 / elapsed.as_secs_f64(), BINARY),
+    );
+    println!("write latency: {write_latency}");
+    if !discard_latency.is_empty() {
+        println!("discard latency: {discard_latency}");
+    }
+
+    Ok(())
+}
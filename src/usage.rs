@@ -0,0 +1,148 @@
+//! CPU time, context switches and system calls of the timed phase, and the
+//! efficiency metrics derived from them, for comparing strategies by what
+//! they cost rather than only by how fast they are.
+
+use humansize::{SizeFormatter, BINARY};
+use serde::{Deserialize, Serialize};
+use std::{cell::Cell, fmt, fs, io, os::unix::fs::FileExt, time::Duration};
+
+const GIB: f64 = (1u64 << 30) as f64;
+
+thread_local! {
+    /// System calls the strategies issued on this thread, which leaves out
+    /// the ones for status lines, events, logs and checkpoints.
+    static CALLS: Cell<u64> = const { Cell::new(0) };
+}
+
+fn count() {
+    CALLS.with(|calls| calls.set(calls.get() + 1));
+}
+
+/// Counts an `io_uring_enter` of the calling thread. Rings owned by the
+/// runtime submit on their own and are not counted.
+pub fn entered() {
+    count();
+}
+
+/// [`FileExt::read_exact_at`], counting each `pread` it takes.
+pub fn read_exact_at(file: &fs::File, mut buf: &mut [u8], mut pos: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        count();
+        match file.read_at(buf, pos) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                pos += n as u64;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// [`FileExt::write_all_at`], counting each `pwrite` it takes.
+pub fn write_all_at(file: &fs::File, mut buf: &[u8], mut pos: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        count();
+        match file.write_at(buf, pos) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                pos += n as u64;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Resources used by a thread, or summed over workers.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Usage {
+    /// User and system CPU time.
+    pub cpu: Duration,
+    /// Voluntary and involuntary context switches.
+    pub switches: u64,
+    /// The `pread`, `pwrite` and `io_uring_enter` calls of the strategy.
+    pub syscalls: u64,
+}
+
+impl Usage {
+    pub fn of_thread() -> Self {
+        let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+        if unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut usage) } < 0 {
+            return Self::default();
+        }
+        let time = |tv: libc::timeval| {
+            Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+        };
+        Self {
+            cpu: time(usage.ru_utime) + time(usage.ru_stime),
+            switches: (usage.ru_nvcsw + usage.ru_nivcsw) as u64,
+            syscalls: CALLS.with(Cell::get),
+        }
+    }
+
+    pub fn since(self, before: Self) -> Self {
+        Self {
+            cpu: self.cpu.saturating_sub(before.cpu),
+            switches: self.switches.saturating_sub(before.switches),
+            syscalls: self.syscalls.saturating_sub(before.syscalls),
+        }
+    }
+
+    pub fn add(&mut self, other: Self) {
+        self.cpu += other.cpu;
+        self.switches += other.switches;
+        self.syscalls += other.syscalls;
+    }
+}
+
+/// Costs per unit of work of a run.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Efficiency {
+    pub cpu_seconds: f64,
+    pub cpu_ms_per_gib: f64,
+    pub context_switches: u64,
+    /// `None` for strategies whose system calls are not counted.
+    #[serde(default)]
+    pub syscalls: Option<u64>,
+    #[serde(default)]
+    pub bytes_per_syscall: Option<f64>,
+}
+
+impl Efficiency {
+    pub fn new(usage: Usage, bytes: u64, syscalls_counted: bool) -> Self {
+        let syscalls = syscalls_counted.then_some(usage.syscalls);
+        Self {
+            cpu_seconds: usage.cpu.as_secs_f64(),
+            cpu_ms_per_gib: usage.cpu.as_secs_f64() * 1000.0 / (bytes as f64 / GIB),
+            context_switches: usage.switches,
+            syscalls,
+            bytes_per_syscall: syscalls
+                .filter(|&calls| calls > 0)
+                .map(|calls| bytes as f64 / calls as f64),
+        }
+    }
+}
+
+impl fmt::Display for Efficiency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.3} CPU seconds, {:.1} CPU-ms per GiB, {} context switches",
+            self.cpu_seconds, self.cpu_ms_per_gib, self.context_switches
+        )?;
+        match (self.syscalls, self.bytes_per_syscall) {
+            (Some(calls), Some(bytes)) => write!(
+                f,
+                ", {calls} syscalls, {} per syscall",
+                SizeFormatter::new(bytes as u64, BINARY)
+            ),
+            (Some(calls), None) => write!(f, ", {calls} syscalls"),
+            (None, _) => f.write_str(", syscalls not counted"),
+        }
+    }
+}
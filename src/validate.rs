@@ -0,0 +1,353 @@
+//! Checks of a job's parameters against each other, the target and the
+//! kernel, done before any setup so that a bad combination fails with a
+//! precise message instead of deep inside the run.
+
+use crate::{
+    device::Device,
+    direct::{Alignment, Misaligned},
+    Job, Strategy,
+};
+use anyhow::{bail, Context, Result};
+use humansize::{SizeFormatter, BINARY};
+use io_uring::{opcode, IoUring, Probe};
+use std::{
+    ffi::CString,
+    fs,
+    io::{self, Seek, SeekFrom},
+    os::unix::{ffi::OsStrExt, fs::FileTypeExt},
+    path::Path,
+};
+use tracing::warn;
+
+/// The most bytes Linux transfers in one read or write, `MAX_RW_COUNT`.
+const MAX_TRANSFER: u64 = 0x7fff_f000;
+/// The most submission entries of a ring, `IORING_MAX_ENTRIES`.
+const MAX_RING_ENTRIES: u32 = 32768;
+/// The largest buffer the kernel registers with a ring.
+const MAX_FIXED_BUFFER: u64 = 1 << 30;
+
+/// Share of a filesystem below which the free space left by a write is
+/// warned about.
+const LOW_SPACE: f64 = 0.05;
+
+/// What the target of a job is before the run.
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+    Missing,
+    File(u64),
+    BlockDevice(u64),
+    Other,
+}
+
+impl Target {
+    pub fn of(path: &str) -> Result<Self> {
+        let meta = match fs::metadata(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::Missing),
+            res => res.with_context(|| format!("failed to stat {path}"))?,
+        };
+        let file_type = meta.file_type();
+        if file_type.is_file() {
+            Ok(Self::File(meta.len()))
+        } else if file_type.is_block_device() {
+            // `metadata().len()` is 0 for block devices.
+            let len = fs::File::open(path)
+                .and_then(|mut file| file.seek(SeekFrom::End(0)))
+                .with_context(|| format!("failed to get the size of {path}"))?;
+            Ok(Self::BlockDevice(len))
+        } else {
+            Ok(Self::Other)
+        }
+    }
+}
+
+/// Space on the filesystem of a path, as available to unprivileged users.
+#[derive(Debug, Clone, Copy)]
+pub struct Space {
+    pub available: u64,
+    pub size: u64,
+}
+
+impl Space {
+    /// The space on the filesystem of `path`, or of its parent directory if
+    /// it does not exist yet.
+    pub fn of(path: &str) -> Result<Self> {
+        let mut path = Path::new(path);
+        if !path.exists() {
+            path = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+        }
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        let mut stat = unsafe { std::mem::zeroed::<libc::statvfs>() };
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } < 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("failed to get the free space of {}", path.display()));
+        }
+        Ok(Self {
+            available: stat.f_bavail * stat.f_frsize,
+            size: stat.f_blocks * stat.f_frsize,
+        })
+    }
+}
+
+/// Checks `job` as a write or a read and returns its target. `listed` are
+/// the offsets of its `--offsets` file and `device` is the block device the
+/// target resolved to, if any.
+pub fn job(
+    job: &Job,
+    write: bool,
+    verify: bool,
+    listed: Option<&[u64]>,
+    device: Option<&Device>,
+) -> Result<Target> {
+    let Job {
+        file,
+        block_size,
+        count,
+        strategy,
+        jobs,
+        ..
+    } = job;
+
+    let end = match listed {
+        Some(offsets) => offsets
+            .iter()
+            .max()
+            .and_then(|max| max.checked_add(*block_size)),
+        None => count.checked_mul(*block_size),
+    };
+    let Some(end) = end else {
+        bail!("{count} blocks of {block_size} bytes are beyond the largest possible offset");
+    };
+    self::block_size(*block_size)?;
+    if *jobs > *count {
+        bail!("--jobs {jobs} is more than the {count} blocks to split between the workers");
+    }
+    if job.soak.is_some_and(|window| window.is_zero()) {
+        bail!("--soak needs a window longer than 0");
+    }
+    if *jobs > 1 && job.checkpoint.is_some() {
+        bail!("--checkpoint is not supported with --jobs");
+    }
+
+    if verify {
+        if matches!(strategy, Strategy::Null | Strategy::Faulty) {
+            bail!("--verify needs a strategy that reads data");
+        }
+        match job.random_align {
+            Some(align) if job.random && align % 64 != 0 => {
+                bail!("--verify requires --random-align to be a multiple of 64, got {align}")
+            }
+            None if block_size % 64 != 0 => bail!(
+                "--verify requires offsets that are multiples of 64, \
+                 use a block size that is one or --random --random-align 64"
+            ),
+            _ => {}
+        }
+        if let Some(offset) = listed.and_then(|offsets| offsets.iter().find(|&&o| o % 64 != 0)) {
+            bail!("--verify requires offsets that are multiples of 64, --offsets lists {offset}");
+        }
+    }
+    match (strategy, job.faults) {
+        (Strategy::Faulty, None) => bail!("--strategy faulty needs a --faults pattern"),
+        (Strategy::Faulty, _) | (_, None) => {}
+        (_, Some(_)) => bail!("--faults only applies to --strategy faulty"),
+    }
+    if write && job.noatime {
+        bail!("--noatime only applies to reads");
+    }
+    if write && job.direct {
+        bail!("--direct only applies to reads");
+    }
+    if !write && job.hash.is_some() {
+        bail!("--hash only applies to writes");
+    }
+    if !write && job.extents {
+        bail!("--extents only applies to writes");
+    }
+    if !write && job.durable {
+        bail!("--durable only applies to writes");
+    }
+    if !write && job.estimate {
+        bail!("--estimate only applies to writes");
+    }
+    if job.iostat.is_some() && device.is_none() {
+        bail!("--iostat requires a target on a block device");
+    }
+    if job.smart && device.is_none() {
+        bail!("--smart requires a target on a block device");
+    }
+
+    if strategy.uses_io_uring() {
+        io_uring_support(*strategy, write)?;
+    }
+    if let Some((entries, depth)) = strategy.queue() {
+        queue(*strategy, entries, depth)?;
+    }
+    if *strategy == Strategy::Max && *block_size > MAX_FIXED_BUFFER {
+        bail!(
+            "--strategy max registers buffers of the block size of {}, \
+             the kernel registers at most {} per buffer",
+            SizeFormatter::new(*block_size, BINARY),
+            SizeFormatter::new(MAX_FIXED_BUFFER, BINARY),
+        );
+    }
+
+    let target = Target::of(file)?;
+    match target {
+        Target::Missing if !write => bail!("{file} does not exist"),
+        Target::Missing if !strategy.creates_file() => {
+            bail!("{file} does not exist and --strategy {strategy} does not create it")
+        }
+        _ => {}
+    }
+    range(file, target, end, write)?;
+    if let Some(alignment) = job.direct.then(|| Alignment::of(file)).flatten() {
+        direct(job, alignment, listed)?;
+    }
+    Ok(target)
+}
+
+/// Checks that the offsets and lengths of `job` meet the O_DIRECT
+/// `alignment` of its target. The error carries the [`Misaligned`]
+/// constraints, which `--direct-fallback` adjusts the job for.
+fn direct(job: &Job, alignment: Alignment, listed: Option<&[u64]>) -> Result<()> {
+    let align = alignment.offset;
+    let misaligned = |offset: Option<u64>, length: Option<u64>, what: String| {
+        let misaligned = Misaligned {
+            alignment,
+            buffer: None,
+            offset,
+            length,
+        };
+        Err(anyhow::Error::new(misaligned).context(format!(
+            "--direct requires offsets and lengths that are multiples of {align} on {}, {what}",
+            job.file
+        )))
+    };
+    if !job.block_size.is_multiple_of(align) {
+        // Sequential offsets and the default random alignment are multiples
+        // of the block size, so the block size fixes both.
+        let offset =
+            (job.random_align.is_none() && job.offsets.is_none()).then_some(job.block_size);
+        return misaligned(
+            offset,
+            Some(job.block_size),
+            format!("the block size is {}", job.block_size),
+        );
+    }
+    match (job.random_align, listed) {
+        (Some(random_align), _) if !random_align.is_multiple_of(align) => misaligned(
+            Some(random_align),
+            None,
+            format!("--random-align is {random_align}"),
+        ),
+        (_, Some(offsets)) => match offsets.iter().find(|offset| !offset.is_multiple_of(align)) {
+            Some(&offset) => misaligned(Some(offset), None, format!("--offsets lists {offset}")),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Checks that `target`, the state of `file`, holds a run reaching `end`,
+/// and for a write that its filesystem has the space to grow it that far.
+pub fn range(file: &str, target: Target, end: u64, write: bool) -> Result<()> {
+    let size = |bytes: u64| SizeFormatter::new(bytes, BINARY);
+    match target {
+        Target::BlockDevice(len) if end > len => {
+            bail!(
+                "the run reaches {} but {file} ends at {}",
+                size(end),
+                size(len)
+            )
+        }
+        Target::File(len) if !write && end > len => {
+            bail!(
+                "the run reads up to {} but {file} ends at {}",
+                size(end),
+                size(len)
+            )
+        }
+        _ => {}
+    }
+    let len = match target {
+        Target::Missing => Some(0),
+        Target::File(len) => Some(len),
+        Target::BlockDevice(_) | Target::Other => None,
+    };
+    if let Some(len) = len.filter(|_| write) {
+        let growth = end.saturating_sub(len);
+        let space = Space::of(file)?;
+        if growth > space.available {
+            bail!(
+                "the run grows {file} by up to {} but only {} are free on its filesystem",
+                size(growth),
+                size(space.available),
+            );
+        }
+        let left = space.available - growth;
+        if growth > 0 && (left as f64) < space.size as f64 * LOW_SPACE {
+            warn!(
+                "the run leaves only {} of {} free on the filesystem of {file}",
+                size(left),
+                size(space.size),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Fails if blocks of `block_size` bytes are more than one read or write
+/// transfers.
+pub fn block_size(block_size: u64) -> Result<()> {
+    if block_size > MAX_TRANSFER {
+        bail!(
+            "the block size of {} is larger than the {} Linux transfers in one operation",
+            SizeFormatter::new(block_size, BINARY),
+            SizeFormatter::new(MAX_TRANSFER, BINARY),
+        );
+    }
+    Ok(())
+}
+
+/// Checks that the ring of `entries` submission entries `strategy` sets up
+/// holds the `depth` operations it keeps in flight, and that the kernel
+/// creates rings that large.
+fn queue(strategy: Strategy, entries: u32, depth: usize) -> Result<()> {
+    if entries > MAX_RING_ENTRIES {
+        bail!(
+            "--strategy {strategy} sets up a ring of {entries} entries, \
+             the kernel allows at most {MAX_RING_ENTRIES}"
+        );
+    }
+    if depth > entries as usize {
+        bail!(
+            "--strategy {strategy} keeps {depth} operations in flight, \
+             more than its ring of {entries} entries holds"
+        );
+    }
+    Ok(())
+}
+
+/// Checks that rings can be created and support the operations `strategy`
+/// submits.
+fn io_uring_support(strategy: Strategy, write: bool) -> Result<()> {
+    let ring = IoUring::new(2).with_context(|| {
+        format!("--strategy {strategy} needs io_uring, which is unavailable (see `raio doctor`)")
+    })?;
+    let (name, code) = match strategy {
+        Strategy::Null | Strategy::Faulty => ("IORING_OP_NOP", opcode::Nop::CODE),
+        Strategy::Max if write => ("IORING_OP_WRITE_FIXED", opcode::WriteFixed::CODE),
+        Strategy::Max => ("IORING_OP_READ_FIXED", opcode::ReadFixed::CODE),
+        _ if write => ("IORING_OP_WRITE", opcode::Write::CODE),
+        _ => ("IORING_OP_READ", opcode::Read::CODE),
+    };
+    let mut probe = Probe::new();
+    // Kernels before 5.6 cannot be probed and are left to fail in the run.
+    if ring.submitter().register_probe(&mut probe).is_ok() && !probe.is_supported(code) {
+        bail!("--strategy {strategy} needs {name}, which this kernel does not support");
+    }
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! A storage latency monitor: a small probe of random reads at a fixed
+//! interval, with one line per probe appended to a log for trend tracking.
+
+use crate::{
+    latency::Latency,
+    offsets::{self, Mode, Offsets},
+    progress::Progress,
+    read_file,
+    validate::Target,
+    worker::StartGate,
+    Strategy,
+};
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+/// One line of the log.
+#[derive(Debug, Serialize)]
+struct Probe<'a> {
+    ts: f64,
+    file: &'a str,
+    block_size: u64,
+    reads: u64,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Outcome {
+    Done {
+        seconds: f64,
+        iops: f64,
+        mean_us: f64,
+        p50_us: f64,
+        p99_us: f64,
+        max_us: f64,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// Probes `file` with `count` random reads of `block_size` bytes every
+/// `interval`, bypassing the page cache unless `buffered`. Runs `probes`
+/// times or until interrupted. A failed probe is logged and the next one
+/// runs as planned.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    file: &str,
+    interval: Duration,
+    count: u64,
+    block_size: u64,
+    strategy: Strategy,
+    buffered: bool,
+    log: Option<&Path>,
+    probes: Option<u64>,
+) -> Result<()> {
+    let mut log = log
+        .map(|path| {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open {}", path.display()))
+        })
+        .transpose()?;
+    let flags = if buffered { 0 } else { libc::O_DIRECT };
+    let start = Instant::now();
+    for n in 0.. {
+        if probes.is_some_and(|probes| n >= probes) {
+            break;
+        }
+        if let Some(wait) = (start + interval * n as u32).checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let outcome = match probe(file, flags, count, block_size, strategy).await {
+            Ok(latency) => {
+                let seconds = latency.1.as_secs_f64();
+                let latency = latency.0;
+                println!(
+                    "probe {n}: {count} reads, {:.0} IOPS, latency: {latency}",
+                    count as f64 / seconds,
+                );
+                let us = |latency: Duration| latency.as_secs_f64() * 1e6;
+                Outcome::Done {
+                    seconds,
+                    iops: count as f64 / seconds,
+                    mean_us: us(latency.mean()),
+                    p50_us: us(latency.percentile(50.0)),
+                    p99_us: us(latency.percentile(99.0)),
+                    max_us: us(latency.max()),
+                }
+            }
+            Err(err) => {
+                warn!("probe {n} failed: {err:#}");
+                Outcome::Failed {
+                    error: format!("{err:#}"),
+                }
+            }
+        };
+        if let Some(log) = &mut log {
+            let probe = Probe {
+                ts,
+                file,
+                block_size,
+                reads: count,
+                outcome,
+            };
+            let line = serde_json::to_string(&probe)?;
+            writeln!(log, "{line}").context("failed to append to the log")?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads `count` blocks at random offsets across the whole of `file`, which
+/// may have changed size since the last probe.
+async fn probe(
+    file: &str,
+    flags: i32,
+    count: u64,
+    block_size: u64,
+    strategy: Strategy,
+) -> Result<(Latency, Duration)> {
+    let len = match Target::of(file)? {
+        Target::File(len) | Target::BlockDevice(len) => len,
+        Target::Missing => bail!("{file} does not exist"),
+        Target::Other => bail!("{file} is neither a regular file nor a block device"),
+    };
+    if len < block_size {
+        bail!("{file} is smaller than one block");
+    }
+    let mode = Mode::Random {
+        seed: offsets::random_seed(),
+        align: block_size,
+    };
+    let offsets = Offsets::new(block_size, mode, 0, 0..len);
+    let mut progress = Progress::new(count * block_size).with_latency(Latency::new(3, None)?);
+    let stats = read_file(
+        file,
+        flags,
+        &offsets,
+        0..count,
+        strategy,
+        None,
+        None,
+        None,
+        &mut progress,
+        &mut StartGate::none(),
+    )
+    .await?;
+    Ok((stats.latency, stats.elapsed))
+}
@@ -0,0 +1,408 @@
+use crate::{
+    latency::{Latency, LatencySummary, Short},
+    matches_pattern,
+    offsets::{Mode, Offsets},
+    progress::Progress,
+    psi::{self, Pressures},
+    read_file,
+    usage::Usage,
+    write_file, Job, Stats,
+};
+use anyhow::{Context, Result};
+use humansize::{ISizeFormatter, BINARY};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    ops::Range,
+    sync::Barrier,
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info_span};
+
+/// Lets workers finish their setup and then start the timed phase together.
+/// A worker that fails before opening its gate still waits when the gate is
+/// dropped so that it does not leave the other workers blocked.
+#[derive(Debug)]
+pub struct StartGate<'a> {
+    barrier: Option<&'a Barrier>,
+    open: bool,
+    /// Page faults of the thread when the gate opened.
+    faults: PageFaults,
+    usage: Usage,
+    pressure: Option<psi::Snapshot>,
+}
+
+impl<'a> StartGate<'a> {
+    pub fn new(barrier: &'a Barrier) -> Self {
+        Self {
+            barrier: Some(barrier),
+            open: false,
+            faults: PageFaults::default(),
+            usage: Usage::default(),
+            pressure: None,
+        }
+    }
+
+    /// A gate for a single worker that never waits.
+    pub fn none() -> Self {
+        Self {
+            barrier: None,
+            open: false,
+            faults: PageFaults::default(),
+            usage: Usage::default(),
+            pressure: None,
+        }
+    }
+
+    /// Waits until every worker is ready and returns the start of the timed
+    /// phase.
+    pub fn open(&mut self) -> Instant {
+        if let (Some(barrier), false) = (self.barrier, self.open) {
+            if barrier.wait().is_leader() {
+                debug!("all workers ready");
+            }
+        }
+        self.open = true;
+        self.faults = PageFaults::of_thread();
+        self.usage = Usage::of_thread();
+        self.pressure = psi::Snapshot::read();
+        Instant::now()
+    }
+
+    /// Page faults of the calling thread since the gate opened.
+    pub fn faults(&self) -> PageFaults {
+        PageFaults::of_thread().since(self.faults)
+    }
+
+    /// Resources used by the calling thread since the gate opened.
+    pub fn usage(&self) -> Usage {
+        Usage::of_thread().since(self.usage)
+    }
+
+    /// Machine-wide stalls since the gate opened `elapsed` ago.
+    pub fn pressure(&self, elapsed: Duration) -> Option<Pressures> {
+        Some(psi::Snapshot::read()?.since(self.pressure.as_ref()?, elapsed))
+    }
+}
+
+/// Page faults counted by the kernel, which show first-use faults and
+/// swapping inside the timed phase.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PageFaults {
+    /// Faults served without I/O, e.g. on the first touch of a page.
+    pub minor: u64,
+    /// Faults that had to read the page, e.g. from swap.
+    pub major: u64,
+}
+
+impl PageFaults {
+    pub fn of_thread() -> Self {
+        let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+        if unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut usage) } < 0 {
+            return Self::default();
+        }
+        Self {
+            minor: usage.ru_minflt as u64,
+            major: usage.ru_majflt as u64,
+        }
+    }
+
+    fn since(self, before: Self) -> Self {
+        Self {
+            minor: self.minor.saturating_sub(before.minor),
+            major: self.major.saturating_sub(before.major),
+        }
+    }
+
+    pub fn add(&mut self, other: Self) {
+        self.minor += other.minor;
+        self.major += other.major;
+    }
+}
+
+impl Drop for StartGate<'_> {
+    fn drop(&mut self) {
+        if !self.open {
+            self.open();
+        }
+    }
+}
+
+/// The blocks of `worker` and the byte range its offsets stay within.
+pub fn share(job: &Job, worker: u64) -> (Range<u64>, Range<u64>) {
+    let Job {
+        block_size,
+        count,
+        jobs,
+        overlap,
+        ..
+    } = job;
+    let blocks = count * worker / jobs..count * (worker + 1) / jobs;
+    let region = if *overlap {
+        0..count * block_size
+    } else {
+        blocks.start * block_size..blocks.end * block_size
+    };
+    (blocks, region)
+}
+
+/// Runs `job` on `job.jobs` threads with one runtime each. The blocks are
+/// split evenly between the workers and each worker's offsets stay within
+/// its share of the range, unless `job.overlap` lets every worker use the
+/// whole range. The timed phase starts on all workers at once after each
+/// has opened its file and allocated its ring and buffers.
+pub fn run(job: &Job, mode: Mode, latency: &Latency, write: bool, verify: bool) -> Result<Stats> {
+    let Job {
+        file,
+        block_size,
+        strategy,
+        jobs,
+        ..
+    } = job;
+
+    let barrier = Barrier::new(*jobs as usize);
+    let results = thread::scope(|scope| {
+        let handles = (0..*jobs)
+            .map(|worker| {
+                let (blocks, region) = share(job, worker);
+                let offsets = Offsets::new(*block_size, mode.clone(), blocks.start, region);
+                let barrier = &barrier;
+                scope.spawn(move || {
+                    let _span = info_span!("worker", id = worker).entered();
+                    let mut gate = StartGate::new(barrier);
+                    let mut progress = Progress::new(block_size * (blocks.end - blocks.start))
+                        .with_latency(latency.empty_like())
+                        .with_injected_latency(job.inject_latency);
+                    if job.records_timeline() {
+                        progress = progress.with_timeline();
+                    }
+                    if let Some(window) = job.soak {
+                        progress = progress.with_soak(window, latency);
+                    }
+                    if job.in_flight {
+                        progress = progress.with_in_flight();
+                    }
+                    if let Some(interval) = job.status_interval {
+                        let label = format!("worker {worker}");
+                        progress = progress.with_status(interval, Some(label));
+                    }
+                    let mut runtime = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
+                        .enable_timer()
+                        .build()
+                        .context("failed to build runtime")?;
+                    let res = runtime.block_on(async {
+                        if write {
+                            let compute = job.compute_per_block;
+                            let progress = &mut progress;
+                            write_file(
+                                file, &offsets, blocks, *strategy, compute, job.faults, progress,
+                                &mut gate,
+                            )
+                            .await
+                        } else {
+                            let progress = &mut progress;
+                            read_file(
+                                file,
+                                job.read_flags(),
+                                &offsets,
+                                blocks,
+                                *strategy,
+                                job.compute_per_block,
+                                job.faults,
+                                verify.then_some(&mut matches_pattern),
+                                progress,
+                                &mut gate,
+                            )
+                            .await
+                        }
+                    });
+                    // Threads may migrate, this is where the worker ended up.
+                    let cpu = u32::try_from(unsafe { libc::sched_getcpu() }).ok();
+                    res.map(|stats| (stats, cpu))
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("worker panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    let mut total = Stats {
+        latency: latency.empty_like(),
+        ..Stats::default()
+    };
+    for (worker, res) in results.into_iter().enumerate() {
+        let (stats, cpu) = res.with_context(|| format!("worker {worker} failed"))?;
+        total.workers.push(Worker::new(worker as u64, &stats, cpu));
+        total.bytes += stats.bytes;
+        total.transferred += stats.transferred;
+        total.ops += stats.ops;
+        // The stalls are machine-wide, those of the longest timed phase
+        // cover the others.
+        if stats.elapsed >= total.elapsed {
+            total.pressure = stats.pressure;
+        }
+        total.elapsed = total.elapsed.max(stats.elapsed);
+        total.latency.merge(&stats.latency);
+        total.timeline.merge(&stats.timeline);
+        total.soak.merge(&stats.soak);
+        total.in_flight.merge(&stats.in_flight);
+        if let Some(ring) = stats.ring {
+            total.ring.get_or_insert_with(Default::default).add(ring);
+        }
+        total.mismatches += stats.mismatches;
+        total.compute += stats.compute;
+        total.faults.add(stats.faults);
+        total.usage.add(stats.usage);
+    }
+
+    Ok(total)
+}
+
+/// One worker's share of a run, to show skew between workers that the
+/// totals average away.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Worker {
+    pub id: u64,
+    /// The CPU the worker ran on when it finished, and its NUMA node.
+    pub cpu: Option<u32>,
+    pub node: Option<u32>,
+    pub bytes_per_sec: f64,
+    pub iops: f64,
+    pub latency: Option<LatencySummary>,
+}
+
+impl Worker {
+    fn new(id: u64, stats: &Stats, cpu: Option<u32>) -> Self {
+        Self {
+            id,
+            cpu,
+            node: cpu.and_then(node_of),
+            bytes_per_sec: stats.bytes_per_sec(),
+            iops: stats.iops(),
+            latency: (!stats.latency.is_empty()).then(|| stats.latency.summary()),
+        }
+    }
+}
+
+impl fmt::Display for Worker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "worker {}", self.id)?;
+        match (self.cpu, self.node) {
+            (Some(cpu), Some(node)) => write!(f, " (cpu {cpu}, node {node})")?,
+            (Some(cpu), None) => write!(f, " (cpu {cpu})")?,
+            _ => {}
+        }
+        write!(
+            f,
+            ": {}/s, {:.0} IOPS",
+            ISizeFormatter::new(self.bytes_per_sec, BINARY),
+            self.iops,
+        )?;
+        if let Some(latency) = &self.latency {
+            let us = |us: f64| Short(Duration::from_secs_f64(us / 1e6));
+            write!(
+                f,
+                ", p50={} p99={} max={}",
+                us(latency.p50_us),
+                us(latency.p99_us),
+                us(latency.max_us),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How far the slowest worker fell behind the fastest one.
+pub fn skew(workers: &[Worker]) -> Option<String> {
+    let slowest = workers
+        .iter()
+        .min_by(|a, b| a.bytes_per_sec.total_cmp(&b.bytes_per_sec))?;
+    let fastest = workers
+        .iter()
+        .max_by(|a, b| a.bytes_per_sec.total_cmp(&b.bytes_per_sec))?;
+    Some(format!(
+        "worker {} at {:.1}% of the throughput of worker {}",
+        slowest.id,
+        slowest.bytes_per_sec / fastest.bytes_per_sec * 100.0,
+        fastest.id,
+    ))
+}
+
+/// The NUMA node of `cpu`, from the `nodeN` link in its sysfs directory.
+fn node_of(cpu: u32) -> Option<u32> {
+    fs::read_dir(format!("/sys/devices/system/cpu/cpu{cpu}"))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find_map(|entry| {
+            entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("node")?
+                .parse()
+                .ok()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_split_blocks_and_range() {
+        let job = Job::parse(&["-f", "x", "-s", "4k", "-c", "10", "-j", "3"]);
+        let shares = (0..3).map(|worker| share(&job, worker)).collect::<Vec<_>>();
+        assert_eq!(
+            shares,
+            [
+                (0..3, 0..3 * 4096),
+                (3..6, 3 * 4096..6 * 4096),
+                (6..10, 6 * 4096..10 * 4096),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_shares_use_the_whole_range() {
+        let job = Job::parse(&["-f", "x", "-s", "4k", "-c", "10", "-j", "3", "--overlap"]);
+        for worker in 0..3 {
+            assert_eq!(share(&job, worker).1, 0..10 * 4096);
+        }
+    }
+
+    #[test]
+    fn more_workers_than_blocks() {
+        let job = Job::parse(&["-f", "x", "-c", "2", "-j", "4"]);
+        let blocks = (0..4)
+            .map(|worker| share(&job, worker).0)
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, [0..0, 0..1, 1..1, 1..2]);
+    }
+
+    fn worker(id: u64, bytes_per_sec: f64) -> Worker {
+        Worker {
+            id,
+            cpu: Some(id as u32),
+            node: None,
+            bytes_per_sec,
+            iops: bytes_per_sec / 4096.0,
+            latency: None,
+        }
+    }
+
+    #[test]
+    fn skew_compares_the_slowest_and_fastest_worker() {
+        let workers = [worker(0, 300.0), worker(1, 400.0), worker(2, 100.0)];
+        assert_eq!(
+            skew(&workers).unwrap(),
+            "worker 2 at 25.0% of the throughput of worker 1"
+        );
+        assert_eq!(skew(&[]), None);
+        assert_eq!(
+            worker(1, 4096.0).to_string(),
+            "worker 1 (cpu 1): 4 KiB/s, 1 IOPS"
+        );
+    }
+}